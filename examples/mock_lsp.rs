@@ -0,0 +1,192 @@
+//! A small language server that red's tests talk to in place of a real one. It keeps its
+//! own copy of every open file, applying changes as they come in, and answers from that:
+//! every `bad` is an error and every `meh` a warning, hover names the word under the
+//! cursor, definitions are found by looking for `fn name`, and completions are the words
+//! of the file. Columns count UTF-16 code units, the protocol's default.
+//!
+//! It only takes changes as edits, so a client that sends whole files makes it quit.
+
+use std::collections::HashMap;
+use std::io::{self,BufRead,Write};
+use std::path::PathBuf;
+
+#[path = "../src/json.rs"]
+#[allow(dead_code)]
+mod json;
+
+use json::Json;
+
+fn main(){
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut documents: HashMap<String,String> = HashMap::new();
+    while let Some(message) = read(&mut input){
+        let id = message.get("id").clone();
+        let params = message.get("params");
+        let uri = params.get("textDocument").get("uri").as_str().unwrap_or_default().to_string();
+        let result = match message.get("method").as_str().unwrap_or_default(){
+            "initialize" => Json::object([("capabilities", Json::object([
+                ("textDocumentSync", 2u64.into()),
+                ("hoverProvider", true.into()),
+                ("definitionProvider", true.into()),
+                ("completionProvider", Json::object([])),
+            ]))]),
+            "textDocument/didOpen" =>{
+                documents.insert(uri.clone(), params.get("textDocument").get("text").as_str().unwrap_or_default().to_string());
+                publish(&uri, &documents[&uri]);
+                continue;
+            },
+            "textDocument/didChange" =>{
+                let text = documents.entry(uri.clone()).or_default();
+                for change in params.get("contentChanges").items(){
+                    if change.get("range").is_null(){
+                        std::process::exit(2);
+                    }
+                    let start = offset(text, change.get("range").get("start"));
+                    let end = offset(text, change.get("range").get("end"));
+                    text.replace_range(start..end, change.get("text").as_str().unwrap_or_default());
+                }
+                publish(&uri, text);
+                continue;
+            },
+            "textDocument/didClose" =>{
+                documents.remove(&uri);
+                continue;
+            },
+            "textDocument/hover" =>{
+                let text = documents.get(&uri).map(String::as_str).unwrap_or_default();
+                match word_at(text, offset(text, params.get("position"))){
+                    "" => Json::Null,
+                    word => Json::object([("contents", Json::object([
+                        ("kind", "markdown".into()),
+                        ("value", format!("```rust\nfn {}()\n```\nA word of the file.", word).into()),
+                    ]))]),
+                }
+            },
+            "textDocument/definition" =>{
+                let text = documents.get(&uri).map(String::as_str).unwrap_or_default();
+                let word = word_at(text, offset(text, params.get("position")));
+                definition(&uri, &documents, word).unwrap_or(Json::Null)
+            },
+            "textDocument/completion" =>{
+                let text = documents.get(&uri).map(String::as_str).unwrap_or_default();
+                let mut words: Vec<&str> = text.split(|c: char| !c.is_alphanumeric() && c != '_').filter(|word| word.len() > 1).collect();
+                words.sort();
+                words.dedup();
+                Json::object([
+                    ("isIncomplete", false.into()),
+                    ("items", words.into_iter().map(|word| Json::object([("label", word.into())])).collect::<Vec<_>>().into()),
+                ])
+            },
+            "exit" => return,
+            _ if id.is_null() => continue,
+            _ => Json::Null,
+        };
+        send(&Json::object([("jsonrpc", "2.0".into()), ("id", id), ("result", result)]));
+    }
+}
+
+fn read(input: &mut impl BufRead) -> Option<Json>{
+    let mut length = 0;
+    loop{
+        let mut header = String::new();
+        if input.read_line(&mut header).ok()? == 0{
+            return None;
+        }
+        let header = header.trim_end();
+        if header.is_empty(){
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:"){
+            length = value.trim().parse().ok()?;
+        }
+    }
+    let mut body = vec![0; length];
+    input.read_exact(&mut body).ok()?;
+    Json::parse(std::str::from_utf8(&body).ok()?)
+}
+
+fn send(message: &Json){
+    let body = message.to_string();
+    let mut stdout = io::stdout().lock();
+    write!(stdout, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+    stdout.flush().unwrap();
+}
+
+/// Reports every `bad` as an error and every `meh` as a warning.
+fn publish(uri: &str, text: &str){
+    let mut diagnostics = vec![];
+    for (line, content) in text.lines().enumerate(){
+        for (word, severity, message) in [("bad", 1u64, "bad is bad"), ("meh", 2, "meh is dubious")]{
+            for (start, _) in content.match_indices(word){
+                let character = content[..start].encode_utf16().count();
+                diagnostics.push(Json::object([
+                    ("range", range(line, character, line, character + word.len())),
+                    ("severity", severity.into()),
+                    ("message", message.into()),
+                ]));
+            }
+        }
+    }
+    send(&Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("method", "textDocument/publishDiagnostics".into()),
+        ("params", Json::object([("uri", uri.into()), ("diagnostics", diagnostics.into())])),
+    ]));
+}
+
+/// Finds `fn word` in the open files, then in the other files next to `uri`.
+fn definition(uri: &str, documents: &HashMap<String,String>, word: &str) -> Option<Json>{
+    if word.is_empty(){
+        return None;
+    }
+    let needle = format!("fn {}", word);
+    let find = |uri: &str, text: &str|{
+        text.lines().enumerate().find_map(|(line, content)|{
+            let start = content.find(&needle)? + 3;
+            let character = content[..start].encode_utf16().count();
+            Some(Json::object([("uri", uri.into()), ("range", range(line, character, line, character + word.len()))]))
+        })
+    };
+    if let Some(location) = documents.iter().find_map(|(uri, text)| find(uri, text)){
+        return Some(location);
+    }
+    let dir = PathBuf::from(uri.strip_prefix("file://")?).parent()?.to_path_buf();
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir).ok()?.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+    files.sort();
+    files.iter().find_map(|file| find(&format!("file://{}", file.display()), &std::fs::read_to_string(file).ok()?))
+}
+
+/// The byte offset of a protocol position in `text`.
+fn offset(text: &str, position: &Json) -> usize{
+    let line = position.get("line").as_u64().unwrap_or(0) as usize;
+    let character = position.get("character").as_u64().unwrap_or(0) as usize;
+    let mut start = 0;
+    for _ in 0..line{
+        match text[start..].find('\n'){
+            Some(end) => start += end + 1,
+            None => return text.len(),
+        }
+    }
+    let content = text[start..].split('\n').next().unwrap_or_default();
+    let mut units = 0;
+    for (i, c) in content.char_indices(){
+        if units >= character{
+            return start + i;
+        }
+        units += c.len_utf16();
+    }
+    start + content.len()
+}
+
+fn word_at(text: &str, offset: usize) -> &str{
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let start = text[..offset].rfind(|c: char| !is_word(c)).map_or(0, |i| i + 1);
+    let end = text[offset..].find(|c: char| !is_word(c)).map_or(text.len(), |i| offset + i);
+    &text[start..end]
+}
+
+fn range(start_line: usize, start: usize, end_line: usize, end: usize) -> Json{
+    let position = |line: usize, character: usize| Json::object([("line", line.into()), ("character", character.into())]);
+    Json::object([("start", position(start_line, start)), ("end", position(end_line, end))])
+}
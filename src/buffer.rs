@@ -1,27 +1,63 @@
-use std::fmt::Error;
 use std::fs::File as RFile;
 use std::io::{BufReader, BufWriter, ErrorKind};
 use std::path::{Path,PathBuf};
 use std::io::prelude::*;
+use std::cmp;
+use std::collections::{BTreeMap,HashSet};
 use crate::terminal::Position;
+use crate::undo::{Change,History};
+use crate::error::{Error,Result};
 
-pub(crate) struct Buffer{
+/// The text being edited, held as a list of lines without their line endings.
+///
+/// Every edit is recorded so it can be undone. Edits made between two calls to
+/// [`checkpoint`](Buffer::checkpoint) are undone together.
+///
+/// ```
+/// use red::{Buffer, Position};
+///
+/// let mut buffer = Buffer::from_text("hello\nworld");
+/// buffer.checkpoint(Position::default());
+/// let end = buffer.insert_str(Position{r: 0, c: 5}, ", there");
+/// assert_eq!(end, Position{r: 0, c: 12});
+/// assert_eq!(buffer.get(0), Some("hello, there"));
+///
+/// buffer.undo(end);
+/// assert_eq!(buffer.text(), "hello\nworld");
+/// ```
+pub struct Buffer{
+    /// Name shown in the modeline, usually the file name.
     pub name: String,
     lines: Vec<String>,
     path: PathBuf,
+    /// Set for files that can't be written; `insert`, `remove` and friends leave the text alone.
     pub read_only: bool,
+    history: History,
+    /// Edits not yet passed on to a language server, once someone asked for them.
+    edits: Option<Vec<Edit>>,
+    /// Goes up with every edit, so views of the text can tell when they are out of date.
+    generation: u64,
+    /// Named marks, which move along with their text as lines are inserted and removed.
+    marks: BTreeMap<char,Position>,
+    /// Unnamed marks on places the editor keeps track of, like the errors in a command's
+    /// output. They move the same way as named marks.
+    places: BTreeMap<usize,Position>,
 }
 
-pub(crate) enum Direction{
+/// Lines `row..row+removed` of a buffer were replaced by `lines`.
+pub(crate) struct Edit{
+    pub(crate) row: usize,
+    pub(crate) removed: usize,
+    pub(crate) lines: Vec<String>,
+}
+
+/// Which way an edit or motion goes from a position.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Direction{
     Forward,
     Backward,
 }
 
-//impl Index<usize> for Buffer{
-//    type Output = String;
-//    fn index(&self, )
-//}
-
 impl Default for Buffer{
     fn default()->Self{
         Self{
@@ -29,203 +65,657 @@ impl Default for Buffer{
              name: String::from("scratch"),
              read_only: false,
              path: PathBuf::new(),
+             history: Default::default(),
+             edits: None,
+             generation: 0,
+             marks: BTreeMap::new(),
+             places: BTreeMap::new(),
         }
     }
 }
 
 impl Buffer{
-    pub fn open(file_path: &str)->(Self,bool){
-        let path = PathBuf::from(file_path);
-        let mut name = path.file_name().unwrap_or_default().to_str().unwrap().to_string();
-        let mut read_only = false;
-        let file = RFile::open(path.clone());
-        let mut new = true;
-        let vec: Vec<String> = match file {
-            Ok(f) =>{
-                let reader = BufReader::new(f.try_clone().unwrap());
-                let lines: Vec<String> = reader.lines().map(|l| l.expect("red: error: could not parse line")).collect();
-                let path = path.as_path();
-                name = path.file_name().unwrap().to_os_string().into_string().unwrap();
-                drop(f);
-                std::env::set_current_dir(&path.parent().unwrap_or(Path::new("."))).ok();
-                new = false;
-
-                lines
-            },
-            Err(e) =>{
-                vec![]
+    /// Builds an unnamed buffer holding `text`.
+    pub fn from_text(text: &str) -> Self{
+        Self{
+            lines: text.lines().map(String::from).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// An empty buffer for `path`. Nothing is read or created until it is written.
+    pub fn with_path(path: impl AsRef<Path>) -> Self{
+        let path = path.as_ref().to_path_buf();
+        Self{
+            name: name_of(&path),
+            path,
+            ..Default::default()
+        }
+    }
+
+    /// Reads the file at `file_path`.
+    ///
+    /// ```
+    /// let err = red::Buffer::open("does/not/exist.txt").err().unwrap();
+    /// assert!(err.is_not_found());
+    /// ```
+    pub fn open(file_path: impl AsRef<Path>)->Result<Self>{
+        let path = file_path.as_ref().to_path_buf();
+        let file = RFile::open(&path).map_err(Error::file(&path))?;
+        let read_only = file.metadata().map(|stats| stats.permissions().readonly()).unwrap_or(false);
+        let mut lines = vec![];
+        for (n,line) in BufReader::new(file).lines().enumerate(){
+            match line{
+                Ok(line) => lines.push(line),
+                Err(e) if e.kind() == ErrorKind::InvalidData => return Err(Error::Encoding{path, line: n}),
+                Err(e) => return Err(Error::File{path, source: e}),
             }
-        };
+        }
 
-        if let Ok(f) = RFile::open(path.clone()){
-            let stats = f.metadata().unwrap();
-            read_only = stats.permissions().readonly();
+        Ok(Self{
+            lines,
+            name: name_of(&path),
+            read_only,
+            path,
+            history: Default::default(),
+            edits: None,
+            generation: 0,
+            marks: BTreeMap::new(),
+            places: BTreeMap::new(),
+        })
+    }
+
+    /// Writes the buffer to `file_name`, or back to the file it was opened from when
+    /// `None`, and returns a message describing what was written.
+    pub fn write(&mut self, file_name: Option<String>) -> Result<String>{
+        let path = match file_name{
+            Some(name) if !name.is_empty() => PathBuf::from(name),
+            Some(_) => return Err(Error::NoFileName),
+            None if self.path.as_os_str().is_empty() => return Err(Error::NoFileName),
+            None => self.path.clone(),
+        };
+        let outfile = RFile::create(&path).map_err(Error::file(&path))?;
+        let mut outfile = BufWriter::new(outfile);
+        for line in self.lines.iter(){
+            writeln!(outfile,"{}",line).map_err(Error::file(&path))?;
         }
+        outfile.flush().map_err(Error::file(&path))?;
+        self.name = name_of(&path);
+        self.path = path;
+        self.history.mark_saved();
+        Ok(format!("Wrote {} lines to disk.",self.lines.len()))
+    }
 
+    /// The file the buffer was opened from or last written to. Empty for scratch buffers.
+    pub fn path(&self) -> &Path{
+        self.path.as_path()
+    }
 
-        (Self{
-            lines: vec,
-            name: name,
-            read_only: read_only,
-            path
-        },new)
+    /// Whether the text was changed since it was read or last written.
+    pub fn is_modified(&self) -> bool{
+        !self.history.at_saved()
+    }
 
+    /// The whole buffer as a string, lines joined with `\n`.
+    pub fn text(&self) -> String{
+        self.lines.join("\n")
     }
 
+    /// Finds the first occurrence of `pattern`.
+    ///
+    /// ```
+    /// use red::{Buffer, Position};
+    ///
+    /// let buffer = Buffer::from_text("one\ntwo needle");
+    /// assert_eq!(buffer.find("needle"), Some(Position{r: 1, c: 4}));
+    /// assert_eq!(buffer.find("haystack"), None);
+    /// ```
+    pub fn find(&self, pattern: &str) -> Option<Position>{
+        self.find_from(pattern, Position::default())
+    }
 
-    pub fn write(&mut self, file_name: Option<String>) -> Result<String,std::io::Error>{
-        let create = file_name.is_some();
-        let file_name = file_name.unwrap_or(self.name.to_string());
-        let fully_qualified_file_path =if create{
-            Path::new(file_name.as_str())
-        }
-        else{
-            self.path.as_path()
-        };
-        let mut outfile = match RFile::create(fully_qualified_file_path){
-            Err(e) => {
-                return Err(e);
-            }
-            Ok(file) =>{
-                file
+    /// Finds the first occurrence of `pattern` at or after `from`.
+    pub fn find_from(&self, pattern: &str, from: Position) -> Option<Position>{
+        for (r,line) in self.lines.iter().enumerate().skip(from.r){
+            let start = if r == from.r {cmp::min(from.c, line.len())} else {0};
+            if let Some(c) = line.get(start..).and_then(|rest| rest.find(pattern)){
+                return Some(Position{r, c: start + c});
             }
+        }
+        None
+    }
+
+    /// Inserts a single character. A tab is inserted as four spaces.
+    pub fn insert(&mut self, pos: Position, c: char){
+        match c{
+            '\t' => self.insert_str(pos, "    "),
+            _ => self.insert_str(pos, c.encode_utf8(&mut [0; 4])),
         };
+    }
 
-        let _ : Vec<_> = self.lines.iter().map(|l| outfile.write((l.clone() + "\n").as_bytes())).collect();
-        self.name = String::from(fully_qualified_file_path.file_name().unwrap().to_str().unwrap());
-        Ok(format!("Wrote {} lines to disk.",self.lines.len()))
+    /// Number of lines in the buffer.
+    pub fn len(&self) -> usize{
+        self.lines.len()
     }
 
-    pub(crate) fn find(&self, s: &String) -> Result<usize, ()>{
-        for (p,l) in self.lines.iter().enumerate(){
-            if l.contains(s){
-                return Ok(p);
-            }
-        }
-        Err(())
+    /// Whether the buffer has no lines at all. A buffer holding a single empty line, as
+    /// after deleting everything typed into it, is not empty.
+    pub fn is_empty(&self) -> bool{
+        self.lines.is_empty()
     }
 
-    pub(crate) fn insert(&mut self, pos: Position, c: char){
-        if self.read_only{
-            return
-        }
-        if c=='\n'{
-            self.insert_newline(pos);
-        }
-        else if c== '\t'{
-            self.insert_tab(pos);
+    /// The line at `index`, without its line ending.
+    pub fn get(&self, index: usize)->Option<&str>{
+        self.lines.get(index).map(|line| line.as_str())
+    }
+
+    /// All the lines, without their line endings.
+    pub(crate) fn lines(&self) -> &[String]{
+        &self.lines
+    }
+
+    /// How many edits were made so far; any edit changes it.
+    pub(crate) fn generation(&self) -> u64{
+        self.generation
+    }
+
+    /// Adds lines at the end without recording an edit, even in read-only buffers. This is
+    /// how output that arrives over time, like search results, fills its buffer.
+    pub(crate) fn append_lines(&mut self, lines: impl IntoIterator<Item = String>){
+        let row = self.lines.len();
+        self.lines.extend(lines);
+        self.generation += 1;
+        if let Some(edits) = &mut self.edits{
+            edits.push(Edit{row, removed: 0, lines: self.lines[row..].to_vec()});
         }
-        else if self.lines.len() != 0{
-            let row = &mut self.lines[pos.r as usize];
-            row.insert(pos.c as usize, c);
+    }
+
+    /// Puts the mark called `name` at `pos`, in place of any mark of that name.
+    ///
+    /// ```
+    /// use red::{Buffer, Direction, Position};
+    ///
+    /// let mut buffer = Buffer::from_text("one\ntwo");
+    /// buffer.set_mark('a', Position{r: 1, c: 2});
+    /// buffer.insert_str(Position{r: 0, c: 0}, "zero\n");
+    /// assert_eq!(buffer.mark('a'), Some(Position{r: 2, c: 2}));
+    /// buffer.remove(Position{r: 2, c: 0}, Direction::Forward);
+    /// assert_eq!(buffer.mark('a'), Some(Position{r: 2, c: 1}));
+    /// ```
+    pub fn set_mark(&mut self, name: char, pos: Position){
+        self.marks.insert(name, pos);
+    }
+
+    /// Where the mark called `name` is now.
+    pub fn mark(&self, name: char) -> Option<Position>{
+        self.marks.get(&name).copied()
+    }
+
+    /// Every mark with where it is, in order of their names.
+    pub fn marks(&self) -> impl Iterator<Item = (char,Position)> + '_{
+        self.marks.iter().map(|(&name, &pos)| (name, pos))
+    }
+
+    /// Puts the unnamed mark `id` at `pos`, in place of any mark with that id.
+    pub(crate) fn set_place(&mut self, id: usize, pos: Position){
+        self.places.insert(id, pos);
+    }
+
+    /// Every unnamed mark with where it is now, in order of their ids.
+    pub(crate) fn places(&self) -> impl Iterator<Item = (usize,Position)> + '_{
+        self.places.iter().map(|(&id, &pos)| (id, pos))
+    }
+
+    pub(crate) fn clear_places(&mut self){
+        self.places.clear();
+    }
+
+    /// Starts keeping every edit, to be collected with [`take_edits`](Buffer::take_edits).
+    pub(crate) fn track_edits(&mut self){
+        self.edits.get_or_insert_with(Vec::new);
+    }
+
+    /// The edits made since the last call, oldest first.
+    pub(crate) fn take_edits(&mut self) -> Vec<Edit>{
+        self.edits.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Deletes one character next to `pos`. Deleting forward at the end of a line joins
+    /// the next line onto it; deleting on an empty line removes the line. Positions past
+    /// the last line do nothing, and columns past the end of the line count as its end.
+    pub fn remove(&mut self, pos: Position, direction: Direction){
+        if self.read_only || pos.r >= self.lines.len(){
+            return;
         }
-        else{
-            self.insert_newline((0,0).into());
-            let line = &mut self.lines[0];
-            line.push(c);
+        let r = pos.r;
+        let mut line = self.lines[r].clone();
+        let c = char_start(&line, pos.c);
+        match direction{
+            Direction::Forward =>{
+                if line.is_empty(){
+                    self.splice(r, 1, vec![]);
+                }
+                else if c != line.len(){
+                    line.remove(c);
+                    self.splice(r, 1, vec![line]);
+                }
+                else if r+1 != self.lines.len(){
+                    line.push_str(self.lines[r+1].as_str());
+                    self.splice(r, 2, vec![line]);
+                }
+            },
+            Direction::Backward =>{
+                if line.is_empty(){
+                    self.splice(r, 1, vec![]);
+                }
+                else if c == line.len(){
+                    line.pop();
+                    self.splice(r, 1, vec![line]);
+                }
+                else if c != 0{
+                    line.remove(c);
+                    self.splice(r, 1, vec![line]);
+                }
+            },
         }
     }
 
+    /// Inserts `text`, which may span several lines, and returns the position just past it.
+    /// A column inside a character inserts before that character.
+    ///
+    /// ```
+    /// use red::{Buffer, Position};
+    ///
+    /// let mut buffer = Buffer::from_text("café");
+    /// assert_eq!(buffer.insert_str(Position{r: 0, c: 4}, "s\nx"), Position{r: 1, c: 1});
+    /// assert_eq!(buffer.text(), "cafs\nxé");
+    /// ```
+    pub fn insert_str(&mut self, pos: Position, text: &str) -> Position{
+        if self.read_only{
+            return pos;
+        }
+        let row = cmp::min(pos.r, self.lines.len().saturating_sub(1));
+        let line = self.lines.get(row).cloned().unwrap_or_default();
+        let (head, tail) = line.split_at(char_start(&line, pos.c));
+        let mut new: Vec<String> = text.split('\n').map(String::from).collect();
+        new[0].insert_str(0, head);
+        let last = new.len()-1;
+        let end = Position{r: (row + last), c: new[last].len()};
+        new[last].push_str(tail);
+        self.splice(row, cmp::min(1, self.lines.len()), new);
+        end
+    }
 
-    fn insert_tab(&mut self, pos: Position) {
-        let tab = "    ";
-        if self.lines.len() == 0{
-            self.lines.push(Default::default());
-            self.lines[0].insert_str(0, tab);
+    /// Returns the text between `start` and `end`, joining lines with `\n`. The two can
+    /// come in either order; columns inside a character count from its start.
+    ///
+    /// ```
+    /// use red::{Buffer, Position};
+    ///
+    /// let buffer = Buffer::from_text("naïve\ncafé");
+    /// assert_eq!(buffer.text_range(Position{r: 0, c: 3}, Position{r: 1, c: 2}), "ïve\nca");
+    /// assert_eq!(buffer.text_range(Position{r: 1, c: 9}, Position{r: 0, c: 4}), "ve\ncafé");
+    /// assert_eq!(buffer.text_range(Position{r: 5, c: 0}, Position{r: 6, c: 0}), "");
+    /// ```
+    pub fn text_range(&self, start: Position, end: Position) -> String{
+        let (start, end) = in_order(start, end);
+        let mut text = String::new();
+        for (r, line) in self.lines.iter().enumerate().take(end.r.saturating_add(1)).skip(start.r){
+            let from = if r == start.r {char_start(line, start.c)} else {0};
+            let to = if r == end.r {char_start(line, end.c)} else {line.len()};
+            if r != start.r{
+                text.push('\n');
+            }
+            text.push_str(&line[from..cmp::max(from,to)]);
         }
-        else{
-            let row = &mut self.lines[pos.r as usize];
-            row.insert_str(pos.c as usize, tab);
+        text
+    }
+
+    /// Deletes the text between `start` and `end`, which can come in either order.
+    pub fn remove_range(&mut self, start: Position, end: Position){
+        let (start, end) = in_order(start, end);
+        if self.read_only || start.r >= self.lines.len(){
+            return;
         }
+        let end_r = cmp::min(end.r, self.lines.len()-1);
+        let end_line = &self.lines[end_r];
+        let tail = &end_line[char_start(end_line, end.c)..];
+        let mut line = self.lines[start.r].clone();
+        line.truncate(char_start(&line, start.c));
+        line.push_str(tail);
+        self.splice(start.r, end_r + 1 - start.r, vec![line]);
     }
 
-    fn insert_newline(&mut self, pos: Position){
+    /// Replaces `count` whole lines starting at `row` with `lines`, as one edit.
+    pub fn replace_lines(&mut self, row: usize, count: usize, lines: Vec<String>){
+        if self.read_only || row > self.lines.len(){
+            return;
+        }
+        let count = cmp::min(count, self.lines.len() - row);
+        self.splice(row, count, lines);
+    }
 
-        if self.lines.len() == 0{
-            self.lines.push(Default::default());
+    /// Replaces `count` lines starting at `row` with `new`. Every edit goes through
+    /// here so that it ends up in the undo history.
+    fn splice(&mut self, row: usize, count: usize, new: Vec<String>){
+        let new_len = new.len();
+        if let Some(edits) = &mut self.edits{
+            edits.push(Edit{row, removed: count, lines: new.clone()});
         }
-        else if pos.r as usize == self.lines.len()-1{
-            self.lines.push(Default::default());
+        let old: Vec<String> = self.lines.splice(row..row+count, new).collect();
+        self.move_marks(row, &old, new_len);
+        self.history.record(Change{row, old, new_len});
+        self.generation += 1;
+    }
+
+    /// Moves the marks after lines `old` at `row` were replaced by the `new_len` lines now
+    /// there. Marks on lines the edit left alone stay with them; a mark on a changed line
+    /// stays with the text on either side of it, as far as that is still there.
+    fn move_marks(&mut self, row: usize, old: &[String], new_len: usize){
+        if self.marks.is_empty() && self.places.is_empty(){
+            return;
         }
-        else{
-            self.lines.insert(pos.r as usize +1,Default::default());
+        let new = &self.lines[row..row + new_len];
+        let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+        let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+        let (old_changed, new_changed) = (&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+        let start = row + prefix;
+        for pos in self.marks.values_mut().chain(self.places.values_mut()){
+            if pos.r < start{
+                continue;
+            }
+            if pos.r >= start + old_changed.len(){
+                pos.r = pos.r + new_changed.len() - old_changed.len();
+                continue;
+            }
+            let i = pos.r - start;
+            let line = &old_changed[i];
+            let mut c = cmp::min(pos.c, line.len());
+            while !line.is_char_boundary(c){
+                c -= 1;
+            }
+            *pos = match new_changed.last(){
+                None => Position{r: start, c: 0},
+                Some(_) if i == 0 && new_changed[0].starts_with(&line[..c]) => Position{r: start, c},
+                Some(last) if i + 1 == old_changed.len() && last.ends_with(&line[c..]) =>{
+                    Position{r: start + new_changed.len() - 1, c: last.len() - (line.len() - c)}
+                },
+                Some(_) => Position{r: start + cmp::min(i, new_changed.len() - 1), c: 0},
+            };
         }
-        if pos.c as usize != self.lines[pos.r as usize].len(){
-            let view = self.lines[pos.r as usize].clone();
-            let view = &view.as_str()[pos.c as usize..];
-            self.lines[pos.r as usize +1].insert_str(0,view);
-            let newsize = self.lines[pos.r as usize].len() - view.len();
-            self.lines[pos.r as usize].truncate(newsize);
+        let last = self.lines.len().saturating_sub(1);
+        for pos in self.marks.values_mut().chain(self.places.values_mut()){
+            if pos.r > last{
+                *pos = Position{r: last, c: 0};
+            }
         }
     }
 
-    pub(crate) fn len(&self) -> usize{
-        self.lines.len()
+    /// Starts a new undo group; `cursor` is restored when the group is undone.
+    pub fn checkpoint(&mut self, cursor: Position){
+        self.history.checkpoint(cursor);
     }
 
-    pub(crate) fn get(&self, index: usize)->Option<&String>{
-        match self.lines.get(index){
-            Some(line) =>{Some(&line)},
-            None => None
-        }
+    /// Undoes the last group of edits and returns where the cursor was before them.
+    pub fn undo(&mut self, cursor: Position) -> Option<Position>{
+        let (pos, changes) = self.history.pop_undo()?;
+        let inverse = self.revert(changes);
+        self.history.push_redo(cursor, inverse);
+        Some(pos)
     }
 
-    pub(crate) fn replace(&mut self, pos: Position){
-        //write replace mode functionality here.
-        todo!();
+    /// Redoes the last undone group of edits and returns where the cursor was before the undo.
+    pub fn redo(&mut self, cursor: Position) -> Option<Position>{
+        let (pos, changes) = self.history.pop_redo()?;
+        let inverse = self.revert(changes);
+        self.history.push_undo(cursor, inverse);
+        Some(pos)
     }
 
-    pub(crate) fn remove(&mut self, pos: Position, direction: Direction, _num_chars: i32){
-        if self.lines.len()==0 || self.lines.len() == pos.r as usize{
-            return;
+    /// Reverts `changes` in reverse order and returns the changes that would redo them.
+    fn revert(&mut self, changes: Vec<Change>) -> Vec<Change>{
+        let mut inverse = Vec::with_capacity(changes.len());
+        for change in changes.into_iter().rev(){
+            let old_len = change.old.len();
+            if let Some(edits) = &mut self.edits{
+                edits.push(Edit{row: change.row, removed: change.new_len, lines: change.old.clone()});
+            }
+            let old: Vec<String> = self.lines.splice(change.row..change.row+change.new_len, change.old).collect();
+            self.move_marks(change.row, &old, old_len);
+            inverse.push(Change{row: change.row, old, new_len: old_len});
         }
-        let line = &mut self.lines[pos.r as usize];
+        self.generation += 1;
+        inverse
+    }
+
+    /// Returns the position the cursor lands on after moving one word from `pos`.
+    /// Moving past the start or end of a line wraps onto the neighbouring line.
+    pub fn word_boundary(&self, pos: Position, direction: Direction) -> Position{
+        let (r, c) = (pos.r, pos.c);
+        let line = match self.lines.get(r){
+            Some(line) => line,
+            None => return pos,
+        };
+        let c = char_start(line, c);
         match direction{
             Direction::Forward =>{
-                if line.len() == 0{
-                    self.lines.remove(pos.r as usize);
-                    return;
-                }
-                else{
-                    if pos.c != line.len() as u16{
-                        line.remove(pos.c as usize);
-                    }
-                    else if pos.r as usize +1 != self.lines.len(){
-                            self.remove_and_concat(pos);
+                if c == line.len(){
+                    if r+1 < self.lines.len(){
+                        return Position{r: pos.r+1, c: 0};
                     }
+                    return Position{r, c};
                 }
-
+                let rest = &line[c..];
+                let mut chars = rest.char_indices().peekable();
+                while chars.next_if(|&(_,ch)| !is_word_char(ch)).is_some(){}
+                while chars.next_if(|&(_,ch)| is_word_char(ch)).is_some(){}
+                let off = chars.peek().map(|&(i,_)| i).unwrap_or(rest.len());
+                Position{r: pos.r, c: (c + off)}
             },
             Direction::Backward =>{
-                if line.len() == 0{
-                    if self.lines.len() == 1{
-                        self.lines.pop();
-                    }
-                    else if self.lines.len() ==0{
-                        return
-                    }
-                    else{
-                        self.lines.remove(pos.r as usize);
+                if c == 0{
+                    if r > 0{
+                        return Position{r: pos.r-1, c: self.lines[r-1].len()};
                     }
-                    return;
+                    return pos;
                 }
-                else if pos.c == line.len() as u16{
-                    line.pop();
+                let before = &line[..c];
+                let mut idx = before.len();
+                let mut chars = before.char_indices().rev().peekable();
+                while let Some((i,_)) = chars.next_if(|&(_,ch)| !is_word_char(ch)){
+                    idx = i;
                 }
-                else{
-                    if pos.c != 0{
-                        line.remove(pos.c as usize);
-                    }
+                while let Some((i,_)) = chars.next_if(|&(_,ch)| is_word_char(ch)){
+                    idx = i;
+                }
+                Position{r: pos.r, c: idx}
+            },
+        }
+    }
+
+    /// Returns the start and end of the word under `pos`, or `pos` twice if there is none.
+    /// A column inside a character counts from its start.
+    ///
+    /// ```
+    /// use red::{Buffer, Position};
+    ///
+    /// let buffer = Buffer::from_text("un café noir");
+    /// assert_eq!(buffer.word_at(Position{r: 0, c: 7}), (Position{r: 0, c: 3}, Position{r: 0, c: 8}));
+    /// ```
+    pub fn word_at(&self, pos: Position) -> (Position, Position){
+        let line = match self.lines.get(pos.r){
+            Some(line) => line,
+            None => return (pos, pos),
+        };
+        let c = char_start(line, pos.c);
+        let start = line[..c].char_indices().rev()
+            .take_while(|&(_,ch)| is_word_char(ch))
+            .last().map(|(i,_)| i).unwrap_or(c);
+        let end = line[c..].char_indices()
+            .find(|&(_,ch)| !is_word_char(ch))
+            .map(|(i,_)| c + i).unwrap_or(line.len());
+        (Position{r: pos.r, c: start}, Position{r: pos.r, c: end})
+    }
+
+    /// The distinct words that start with `prefix`, ignoring case, nearest to `pos` first:
+    /// the rest of its line, then the lines above and below it, one step further at a
+    /// time. The word `pos` is in is left out, since that is the one being typed.
+    pub fn words_near(&self, pos: Position, prefix: &str) -> Vec<String>{
+        let prefix = prefix.to_lowercase();
+        let mut seen = HashSet::new();
+        let mut words = vec![];
+        let rows = (0..self.lines.len()).flat_map(|d| [pos.r.checked_sub(d).filter(|_| d > 0), Some(pos.r + d)]).flatten();
+        for r in rows.filter(|&r| r < self.lines.len()){
+            let line = &self.lines[r];
+            let mut found: Vec<(usize,&str)> = vec![];
+            let mut start = None;
+            for (i, ch) in line.char_indices().chain([(line.len(), ' ')]){
+                match (start, is_word_char(ch)){
+                    (None, true) => start = Some(i),
+                    (Some(s), false) =>{
+                        if !(r == pos.r && s <= pos.c && pos.c <= i){
+                            found.push((s, &line[s..i]));
+                        }
+                        start = None;
+                    },
+                    _ =>{},
+                }
+            }
+            if r == pos.r{
+                found.sort_by_key(|&(s, _)| s.abs_diff(pos.c));
+            }
+            for (_, word) in found{
+                if word.len() > prefix.len() && word.to_lowercase().starts_with(&prefix) && seen.insert(word){
+                    words.push(word.to_string());
+                }
+            }
+        }
+        words
+    }
+
+    /// Returns the row of the blank line separating the paragraph at `row`
+    /// from the next (or previous) one, or the first/last row if there is none.
+    pub fn paragraph_boundary(&self, row: usize, direction: Direction) -> usize{
+        if self.lines.is_empty(){
+            return 0;
+        }
+        let blank = |r: usize| self.lines[r].trim().is_empty();
+        let mut r = cmp::min(row, self.lines.len()-1);
+        match direction{
+            Direction::Forward =>{
+                let last = self.lines.len()-1;
+                while r < last && blank(r){
+                    r += 1;
+                }
+                while r < last && !blank(r){
+                    r += 1;
+                }
+            },
+            Direction::Backward =>{
+                while r > 0 && blank(r){
+                    r -= 1;
+                }
+                while r > 0 && !blank(r){
+                    r -= 1;
                 }
             },
         }
+        r
     }
 
-    fn remove_and_concat(&mut self, pos: Position){
-        let next_row = self.lines[pos.r as usize+1].clone();
-        let row = &mut self.lines[pos.r as usize];
-        row.push_str(next_row.as_str());
-        self.lines.remove(pos.r as usize + 1);
+}
+
+/// The byte offset `c` in `line` moved back to the start of the character it falls in, or
+/// the end of the line if it is past it.
+pub(crate) fn char_start(line: &str, c: usize) -> usize{
+    let mut c = cmp::min(c, line.len());
+    while !line.is_char_boundary(c){
+        c -= 1;
     }
+    c
+}
+
+/// `a` and `b` with the one that comes first in the buffer first.
+fn in_order(a: Position, b: Position) -> (Position,Position){
+    if (b.r, b.c) < (a.r, a.c) {(b, a)} else {(a, b)}
+}
+
+pub(crate) fn is_word_char(c: char) -> bool{
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The last component of `path`, or the whole path if it has none.
+pub(crate) fn name_of(path: &Path) -> String{
+    match path.file_name(){
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => path.display().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
 
-}
\ No newline at end of file
+    #[test]
+    fn buffer_edits_past_the_text_or_inside_a_character_stay_in_bounds(){
+        let mut buffer = Buffer::from_text("añb\nx");
+        buffer.remove(Position{r: 5, c: 0}, Direction::Forward);
+        buffer.remove(Position{r: 0, c: 2}, Direction::Forward);
+        assert_eq!(buffer.text(), "ab\nx");
+        buffer.remove(Position{r: 1, c: 9}, Direction::Backward);
+        assert_eq!(buffer.text(), "ab\n");
+        buffer.remove(Position{r: 0, c: 9}, Direction::Forward);
+        assert_eq!(buffer.text(), "ab");
+
+        let mut buffer = Buffer::from_text("één\ntwee\ndrie");
+        assert_eq!(buffer.text_range(Position{r: 0, c: 1}, Position{r: 9, c: 9}), "één\ntwee\ndrie");
+        buffer.remove_range(Position{r: 2, c: 2}, Position{r: 0, c: 3});
+        assert_eq!(buffer.text(), "éie");
+        buffer.remove_range(Position{r: 7, c: 0}, Position{r: 8, c: 0});
+        assert_eq!(buffer.text(), "éie");
+
+        let buffer = Buffer::from_text("één twee");
+        assert_eq!(buffer.word_boundary(Position{r: 0, c: 1}, Direction::Forward), Position{r: 0, c: 5});
+        assert_eq!(buffer.word_boundary(Position{r: 0, c: 3}, Direction::Backward), Position{r: 0, c: 0});
+        assert_eq!(buffer.word_boundary(Position{r: 0, c: 99}, Direction::Forward), Position{r: 0, c: 10});
+
+        let mut empty = Buffer::default();
+        assert_eq!(empty.text_range(Position{r: 0, c: 0}, Position{r: 0, c: 5}), "");
+        empty.remove_range(Position{r: 0, c: 0}, Position{r: 1, c: 0});
+        empty.remove(Position{r: 0, c: 0}, Direction::Backward);
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn marks_stay_with_their_text_through_edits_and_undo(){
+        let mut buffer = Buffer::from_text("first line\nsecond");
+        buffer.set_mark('a', Position{r: 0, c: 6});
+        buffer.set_mark('b', Position{r: 1, c: 0});
+        buffer.set_place(7, Position{r: 1, c: 0});
+        buffer.checkpoint(Position::default());
+        // Splitting the line before the mark takes it to the new line, with the text after it.
+        buffer.insert_str(Position{r: 0, c: 5}, "\n");
+        assert_eq!(buffer.mark('a'), Some(Position{r: 1, c: 1}));
+        assert_eq!(buffer.mark('b'), Some(Position{r: 2, c: 0}));
+        buffer.remove_range(Position{r: 0, c: 0}, Position{r: 1, c: 0});
+        assert_eq!(buffer.mark('a'), Some(Position{r: 0, c: 1}));
+        assert_eq!(buffer.mark('b'), Some(Position{r: 1, c: 0}));
+        buffer.undo(Position::default());
+        assert_eq!(buffer.mark('a'), Some(Position{r: 0, c: 6}));
+        assert_eq!(buffer.mark('b'), Some(Position{r: 1, c: 0}));
+
+        // Unnamed marks move the same way, and stay out of the named ones.
+        assert_eq!(buffer.places().collect::<Vec<_>>(), [(7, Position{r: 1, c: 0})]);
+        buffer.insert_str(Position::default(), "\n");
+        assert_eq!(buffer.places().collect::<Vec<_>>(), [(7, Position{r: 2, c: 0})]);
+        assert_eq!(buffer.marks().count(), 2);
+    }
+    #[test]
+    fn text_that_is_not_utf8_is_reported_with_its_line(){
+        let path = std::env::temp_dir().join(format!("red-test-{}-latin1.txt", std::process::id()));
+        std::fs::write(&path, b"fine\ncaf\xe9\n").unwrap();
+        let opened = Buffer::open(&path);
+        std::fs::remove_file(&path).ok();
+        assert!(matches!(opened, Err(Error::Encoding{line: 1, ..})));
+    }
+}
@@ -0,0 +1,231 @@
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use crate::terminal::Backend;
+
+/// How long a clipboard tool gets to hand over what it holds before pasting gives up on it.
+const PASTE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A place copied text can be sent to and pasted back from.
+pub(crate) trait ClipboardBackend{
+    /// `terminal` is for backends that go through the terminal the editor draws on.
+    fn copy(&mut self, text: &str, terminal: &mut dyn Backend) -> io::Result<()>;
+    /// Returns `Ok(None)` if the backend cannot be read from.
+    fn paste(&mut self) -> io::Result<Option<String>>;
+}
+
+/// Copies go to every backend, pastes come from the first backend that has something.
+pub(crate) struct Clipboard{
+    backends: Vec<Box<dyn ClipboardBackend>>,
+}
+
+impl Clipboard{
+    pub(crate) fn new(backends: Vec<Box<dyn ClipboardBackend>>) -> Self{
+        Self{backends}
+    }
+
+    /// OSC 52 first since it also works over SSH, then whichever desktop clipboard
+    /// tool is installed, and the internal register last so there is always a fallback.
+    pub(crate) fn detect() -> Self{
+        let mut backends: Vec<Box<dyn ClipboardBackend>> = vec![Box::new(Osc52)];
+        if let Some(command) = CommandClipboard::detect(){
+            backends.push(Box::new(command));
+        }
+        backends.push(Box::<Register>::default());
+        Self::new(backends)
+    }
+
+    pub(crate) fn copy(&mut self, text: &str, terminal: &mut dyn Backend) -> io::Result<()>{
+        let mut result = Ok(());
+        let mut copied = false;
+        for backend in self.backends.iter_mut(){
+            match backend.copy(text, terminal){
+                Ok(()) => copied = true,
+                Err(e) => result = Err(e),
+            }
+        }
+        if copied {Ok(())} else {result}
+    }
+
+    pub(crate) fn paste(&mut self) -> Option<String>{
+        self.backends.iter_mut().find_map(|backend| backend.paste().ok().flatten())
+    }
+}
+
+/// Sets the terminal's clipboard with an OSC 52 escape sequence. Reading it back
+/// needs a reply from the terminal, so this backend is write only.
+pub(crate) struct Osc52;
+
+impl ClipboardBackend for Osc52{
+    fn copy(&mut self, text: &str, terminal: &mut dyn Backend) -> io::Result<()>{
+        terminal.write_escape(&format!("\x1b]52;c;{}\x07", base64(text.as_bytes())))
+    }
+
+    fn paste(&mut self) -> io::Result<Option<String>>{
+        Ok(None)
+    }
+}
+
+/// Shells out to `wl-copy`/`wl-paste`, `xclip` or `xsel`.
+#[derive(Debug, PartialEq)]
+pub(crate) struct CommandClipboard{
+    /// The program and arguments that take text to copy on standard input.
+    pub(crate) copy: &'static [&'static str],
+    /// The program and arguments that write the clipboard to standard output.
+    pub(crate) paste: &'static [&'static str],
+}
+
+impl CommandClipboard{
+    pub(crate) fn detect() -> Option<Self>{
+        let wayland = std::env::var_os("WAYLAND_DISPLAY").is_some();
+        let x11 = std::env::var_os("DISPLAY").is_some();
+        Self::choose(wayland, x11, in_path)
+    }
+
+    /// The tool for the display server in use, Wayland's before X11's; among the X11 tools
+    /// `xclip` before `xsel`. `installed` tells whether a program can be run.
+    pub(crate) fn choose(wayland: bool, x11: bool, installed: impl Fn(&str) -> bool) -> Option<Self>{
+        if wayland && installed("wl-copy") && installed("wl-paste"){
+            Some(Self{copy: &["wl-copy"], paste: &["wl-paste", "--no-newline"]})
+        }
+        else if x11 && installed("xclip"){
+            Some(Self{copy: &["xclip", "-selection", "clipboard"], paste: &["xclip", "-selection", "clipboard", "-o"]})
+        }
+        else if x11 && installed("xsel"){
+            Some(Self{copy: &["xsel", "--clipboard", "--input"], paste: &["xsel", "--clipboard", "--output"]})
+        }
+        else{
+            None
+        }
+    }
+}
+
+impl ClipboardBackend for CommandClipboard{
+    fn copy(&mut self, text: &str, _terminal: &mut dyn Backend) -> io::Result<()>{
+        let mut child = Command::new(self.copy[0])
+            .args(&self.copy[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take(){
+            stdin.write_all(text.as_bytes())?;
+        }
+        child.wait()?;
+        Ok(())
+    }
+
+    /// Gives up on a tool that takes longer than [`PASTE_TIMEOUT`], such as `xclip` when
+    /// whoever owns the selection doesn't answer, rather than hold up the editor.
+    fn paste(&mut self) -> io::Result<Option<String>>{
+        let mut child = Command::new(self.paste[0])
+            .args(&self.paste[1..])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move ||{
+            let mut text = String::new();
+            let _ = sender.send(stdout.read_to_string(&mut text).map(|_| text));
+        });
+        let text = match receiver.recv_timeout(PASTE_TIMEOUT){
+            Ok(text) => text?,
+            Err(_) =>{
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(io::Error::new(io::ErrorKind::TimedOut, format!("{} didn't answer", self.paste[0])));
+            },
+        };
+        if !child.wait()?.success(){
+            return Ok(None);
+        }
+        Ok(Some(text))
+    }
+}
+
+/// Clipboard that lives inside red, used when nothing else is available.
+#[derive(Default)]
+pub(crate) struct Register{
+    text: Option<String>,
+}
+
+impl ClipboardBackend for Register{
+    fn copy(&mut self, text: &str, _terminal: &mut dyn Backend) -> io::Result<()>{
+        self.text = Some(text.to_string());
+        Ok(())
+    }
+
+    fn paste(&mut self) -> io::Result<Option<String>>{
+        Ok(self.text.clone())
+    }
+}
+
+pub(crate) fn in_path(program: &str) -> bool{
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false)
+}
+
+fn base64(bytes: &[u8]) -> String{
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3){
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4{
+            if i <= chunk.len(){
+                out.push(TABLE[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            }
+            else{
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::terminal::HeadlessBackend;
+
+    #[test]
+    fn osc52_sends_the_text_base64_encoded_through_the_terminal(){
+        let mut terminal = HeadlessBackend::new(40, 12);
+        for text in ["a", "ab", "abc", "é", ""]{
+            Osc52.copy(text, &mut terminal).unwrap();
+        }
+        assert_eq!(terminal.escapes(), [
+            "\x1b]52;c;YQ==\x07",
+            "\x1b]52;c;YWI=\x07",
+            "\x1b]52;c;YWJj\x07",
+            "\x1b]52;c;w6k=\x07",
+            "\x1b]52;c;\x07",
+        ]);
+    }
+
+    #[test]
+    fn the_clipboard_tool_is_picked_for_the_display_server(){
+        let all = |_: &str| true;
+        let only = |programs: &'static [&'static str]| move |program: &str| programs.contains(&program);
+        let program = |clipboard: Option<CommandClipboard>| clipboard.map(|clipboard| clipboard.copy[0]);
+        assert_eq!(program(CommandClipboard::choose(true, true, all)), Some("wl-copy"));
+        assert_eq!(program(CommandClipboard::choose(false, true, all)), Some("xclip"));
+        assert_eq!(program(CommandClipboard::choose(true, true, only(&["wl-copy", "xsel"]))), Some("xsel"));
+        assert_eq!(program(CommandClipboard::choose(false, true, only(&["wl-copy", "wl-paste"]))), None);
+        assert_eq!(program(CommandClipboard::choose(false, false, all)), None);
+    }
+
+    #[test]
+    fn pasting_from_a_clipboard_tool_that_hangs_gives_up(){
+        let mut hanging = CommandClipboard{copy: &["true"], paste: &["sleep", "10"]};
+        let started = std::time::Instant::now();
+        let error = hanging.paste().unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,187 @@
+use std::fs;
+use std::path::{Path,PathBuf};
+use crate::buffer::{Buffer,name_of};
+use crate::error::{Error,Result};
+
+/// Lines of a listing before the first entry.
+const HEADER_LINES: usize = 1;
+
+/// What a directory entry points at.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EntryKind{
+    Directory,
+    File,
+    Symlink,
+    Other,
+}
+
+/// One entry of a [`Directory`].
+#[derive(Clone, Debug)]
+pub struct Entry{
+    pub name: String,
+    pub kind: EntryKind,
+    /// Size in bytes; zero for anything but files.
+    pub size: u64,
+}
+
+impl Entry{
+    fn line(&self) -> String{
+        let (kind, suffix) = match self.kind{
+            EntryKind::Directory => ('d', "/"),
+            EntryKind::File => ('-', ""),
+            EntryKind::Symlink => ('l', "@"),
+            EntryKind::Other => ('?', ""),
+        };
+        let size = match self.kind{
+            EntryKind::File => self.size.to_string(),
+            _ => String::new(),
+        };
+        format!("  {} {:>10}  {}{}",kind,size,self.name,suffix)
+    }
+}
+
+/// The contents of a directory on disk, shown as a read-only listing that can be browsed
+/// like a file: directories first, then files, each sorted by name, with `..` on top
+/// when there is a parent to go back to.
+///
+/// ```
+/// let dir = std::env::temp_dir().join(format!("red-doc-{}", std::process::id()));
+/// std::fs::create_dir_all(dir.join("src")).unwrap();
+/// std::fs::write(dir.join("notes.txt"), "hello\n").unwrap();
+///
+/// let listing = red::Directory::open(&dir).unwrap();
+/// let buffer = listing.listing();
+/// assert_eq!(listing.entry(1).unwrap().name, "..");
+/// assert!(buffer.get(2).unwrap().ends_with("src/"));
+/// assert!(buffer.get(3).unwrap().ends_with("6  notes.txt"));
+/// # std::fs::remove_dir_all(dir).unwrap();
+/// ```
+pub struct Directory{
+    path: PathBuf,
+    entries: Vec<Entry>,
+}
+
+impl Directory{
+    /// Reads the entries of the directory at `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self>{
+        let path = path.as_ref();
+        let path = path.canonicalize().map_err(Error::file(path))?;
+        let mut directory = Self{path, entries: vec![]};
+        directory.refresh()?;
+        Ok(directory)
+    }
+
+    /// Reads the entries again, picking up changes made on disk.
+    pub fn refresh(&mut self) -> Result<()>{
+        let mut entries = vec![];
+        for dir_entry in fs::read_dir(&self.path).map_err(Error::file(&self.path))?{
+            let dir_entry = dir_entry.map_err(Error::file(&self.path))?;
+            let file_type = dir_entry.file_type().map_err(Error::file(dir_entry.path()))?;
+            let kind = if file_type.is_dir(){
+                EntryKind::Directory
+            }
+            else if file_type.is_file(){
+                EntryKind::File
+            }
+            else if file_type.is_symlink(){
+                EntryKind::Symlink
+            }
+            else{
+                EntryKind::Other
+            };
+            let size = match kind{
+                EntryKind::File => dir_entry.metadata().map(|stats| stats.len()).unwrap_or(0),
+                _ => 0,
+            };
+            entries.push(Entry{name: dir_entry.file_name().to_string_lossy().into_owned(), kind, size});
+        }
+        entries.sort_by(|a, b| (a.kind != EntryKind::Directory, &a.name).cmp(&(b.kind != EntryKind::Directory, &b.name)));
+        if self.path.parent().is_some(){
+            entries.insert(0, Entry{name: String::from(".."), kind: EntryKind::Directory, size: 0});
+        }
+        self.entries = entries;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path{
+        self.path.as_path()
+    }
+
+    pub fn entries(&self) -> &[Entry]{
+        &self.entries
+    }
+
+    /// The entry shown on line `row` of the [`listing`](Directory::listing).
+    pub fn entry(&self, row: usize) -> Option<&Entry>{
+        self.entries.get(row.checked_sub(HEADER_LINES)?)
+    }
+
+    /// The line of the listing that shows `name`.
+    pub fn row_of(&self, name: &str) -> Option<usize>{
+        self.entries.iter().position(|entry| entry.name == name).map(|i| i + HEADER_LINES)
+    }
+
+    /// Where the entry on line `row` lives on disk.
+    pub fn entry_path(&self, row: usize) -> Option<PathBuf>{
+        let entry = self.entry(row)?;
+        match entry.name.as_str(){
+            ".." => self.path.parent().map(Path::to_path_buf),
+            name => Some(self.path.join(name)),
+        }
+    }
+
+    /// A read-only buffer with one line per entry below a header naming the directory.
+    pub fn listing(&self) -> Buffer{
+        let mut text = format!("{}:\n",self.path.display());
+        for entry in self.entries.iter(){
+            text.push_str(&entry.line());
+            text.push('\n');
+        }
+        let mut buffer = Buffer::from_text(&text);
+        buffer.name = format!("{}/",name_of(&self.path).trim_end_matches('/'));
+        buffer.read_only = true;
+        buffer
+    }
+
+    /// Creates `name` inside the directory: an empty file, or a directory when the name
+    /// ends with `/`. Existing entries are never overwritten.
+    pub fn create(&mut self, name: &str) -> Result<PathBuf>{
+        let path = self.path.join(name.trim_end_matches('/'));
+        if name.ends_with('/'){
+            fs::create_dir(&path).map_err(Error::file(&path))?;
+        }
+        else{
+            fs::OpenOptions::new().write(true).create_new(true).open(&path).map_err(Error::file(&path))?;
+        }
+        self.refresh()?;
+        Ok(path)
+    }
+
+    /// Renames the entry on line `row` to `name`, which may point into another directory.
+    /// An existing file called `name` is replaced.
+    pub fn rename(&mut self, row: usize, name: &str) -> Result<PathBuf>{
+        let from = match self.entry(row){
+            Some(entry) if entry.name != ".." => self.path.join(&entry.name),
+            _ => return Err(Error::NoEntry),
+        };
+        let to = self.path.join(name);
+        fs::rename(&from, &to).map_err(Error::file(&from))?;
+        self.refresh()?;
+        Ok(to)
+    }
+
+    /// Deletes the entry on line `row`. Directories are deleted with everything in them.
+    pub fn delete(&mut self, row: usize) -> Result<PathBuf>{
+        let entry = self.entry(row).ok_or(Error::NoEntry)?;
+        if entry.name == ".."{
+            return Err(Error::NoEntry);
+        }
+        let path = self.path.join(&entry.name);
+        match entry.kind{
+            EntryKind::Directory => fs::remove_dir_all(&path),
+            _ => fs::remove_file(&path),
+        }.map_err(Error::file(&path))?;
+        self.refresh()?;
+        Ok(path)
+    }
+}
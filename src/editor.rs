@@ -1,78 +1,325 @@
-use crate::terminal::{WindowSize,Position};
-use crate::buffer::{Buffer,Direction};
-use crossterm::{QueueableCommand,cursor, ExecutableCommand};
-use std::{io::{Write,stdout,stdin}, cmp};
-use crossterm::event::{read,Event};
-use crossterm::event::{KeyEvent,KeyCode,KeyModifiers};
-use crossterm::terminal::ClearType;
-use crossterm::execute;
-use crossterm::style::{Color,Colors,Print,SetColors};
-use colored::*;
+use crate::terminal::{WindowSize,Position,Backend,CrosstermBackend};
+use crate::buffer::{Buffer,Direction,name_of,is_word_char,char_start};
+use crate::directory::{Directory,EntryKind};
+use crate::finder::{Finder,project_root};
+use crate::git::{self,GitFile,Message,Sign};
+use crate::grep::Grep;
+use crate::quickfix::Quickfix;
+use crate::run::{Location,Run};
+use crate::session::{Place,Session,Topic};
+use crate::large::LargeFile;
+use crate::hex::{self,HexBuffer,HexView,BYTES_PER_ROW};
+use crate::lsp::{self,LanguageServer,Reply,Completion,Diagnostic,Severity};
+use crate::killring::KillRing;
+use crate::clipboard::{Clipboard,in_path};
+use crate::screen::{Frame,Overlay,Style};
+use crate::shell;
+use crate::error::{Error,Result};
+use std::cmp;
+use std::collections::HashMap;
+use std::path::{Path,PathBuf};
+use crossterm::event::Event;
+use crossterm::event::{KeyEvent,KeyEventKind,KeyCode,KeyModifiers};
+use crossterm::event::{MouseEvent,MouseEventKind,MouseButton};
+use std::time::{Duration,Instant};
+use crossterm::style::Color;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(PartialEq)]
+#[allow(dead_code)]
 enum EditorMode{
     Edit,
     Replace,
     Mark,
 }
 
+/// Something shown over the text next to the cursor until a key closes it.
+enum Popup{
+    /// Lines to read, like the hover text for the cursor's position or what a command
+    /// complained about.
+    Text(Vec<String>),
+    /// Completions for the word starting at `start`; only the ones matching what has been
+    /// typed since are offered.
+    Completion{items: Vec<Completion>, start: Position, selected: usize},
+}
 
-pub struct Editor{
+/// The terminal editor. It owns a single [`Buffer`] and draws it through a [`Backend`],
+/// the real terminal by default.
+pub struct Editor<B: Backend = CrosstermBackend>{
+    backend: B,
     should_close: bool,
     window_size: WindowSize,
     cursor_pos: Position,
     buffer: Buffer,
+    /// Set while `buffer` is the listing of a directory.
+    directory: Option<Directory>,
+    /// The fuzzy file finder, while it is open.
+    finder: Option<Finder>,
+    /// The last project-wide search, kept for stepping through its hits from any buffer.
+    grep: Option<Grep>,
+    /// Set while `buffer` shows the hits of `grep`.
+    showing_hits: bool,
+    /// The last command run, kept so it can be run again with one key.
+    run: Option<Run>,
+    /// Set while `buffer` shows the output of `run`.
+    showing_output: bool,
+    /// What failed filters and formatters said, oldest first, for the `*messages*` buffer.
+    messages: Vec<String>,
+    /// While `buffer` shows the messages, the buffer they cover with its cursor and scroll,
+    /// which comes back when they are closed.
+    underneath: Option<(Buffer,Position,usize)>,
+    /// The places named in the output of the last command or in loaded errors.
+    quickfix: Option<Quickfix>,
+    /// Set while the current file is too big to load and is viewed through an index instead.
+    large: Option<LargeFile>,
+    /// Files at least this many bytes are opened as large files.
+    pub(crate) large_file_size: u64,
+    /// Set while the current file is binary and shown byte by byte instead of as text.
+    hex: Option<HexView>,
+    /// Shell commands that format files with these extensions before they are written.
+    formatters: HashMap<String,String>,
+    /// Language server commands by the extension of the files they serve.
+    language_servers: HashMap<String,Vec<String>>,
+    /// The language servers started so far, by command.
+    servers: HashMap<String,LanguageServer>,
+    /// The server that was told about the current buffer, and the path it knows it by.
+    document: Option<(String,PathBuf)>,
+    popup: Option<Popup>,
+    /// The committed version of the current file, for marking what changed since.
+    git: Option<GitFile>,
+    /// The branch checked out where the current file is.
+    branch: Option<String>,
+    /// Set while the current file is one git asked to have edited, like a commit message.
+    git_message: Option<Message>,
+    /// Set when the user quit without keeping their changes.
+    aborted: bool,
     mode: EditorMode,
-    mark_delta: (Position,Position),
+    mark: Position,
     status_message: String,
-    draw_accumulator: u32,
-    write_status: bool,
+    /// When the status message was set; it is cleared after `STATUS_TIME`.
+    status_since: Instant,
     pub line_numbers: bool,
+    /// Typing this many characters of a word offers the buffer's words that complete it;
+    /// 0 leaves completion to Alt + /.
+    pub complete_after: usize,
+    viewer: bool,
+    /// The pattern of the last `/` search in the viewer, repeated by `n`.
+    last_search: Option<String>,
     offset: Position,
+    goal_col: Option<usize>,
+    kill_ring: KillRing,
+    /// Where files were left and the answers given to prompts.
+    session: Session,
+    /// Where `session` is read from and saved to on quitting, if anywhere.
+    session_path: Option<PathBuf>,
+    last_action: LastAction,
+    clipboard: Clipboard,
+    scroll: usize,
+    last_click: Option<(Instant,Position)>,
+    dirty: bool,
+    prompting: bool,
+}
 
+const VIEW_ONLY: &str = "Viewing only: nothing can be changed.";
+const STATUS_TIME: Duration = Duration::from_secs(10);
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(400);
+const WHEEL_LINES: usize = 3;
+/// Files this big are viewed without loading them.
+const LARGE_FILE_SIZE: u64 = 64 << 20;
+/// Most matches the file finder shows at once.
+const FINDER_ROWS: usize = 10;
+/// Word characters typed before completions are offered unasked.
+const COMPLETE_AFTER: usize = 3;
+/// Most lines a popup shows at once.
+const POPUP_ROWS: usize = 10;
+/// Language servers used for these extensions when they are installed.
+const DEFAULT_SERVERS: &[(&str,&str)] = &[
+    ("rs","rust-analyzer"),
+    ("c","clangd"),
+    ("h","clangd"),
+    ("cpp","clangd"),
+    ("py","pylsp"),
+    ("go","gopls"),
+];
+const LICENSE: &str = include_str!("../LICENSE");
+
+/// What the previous keypress did, so that consecutive kills append and
+/// yank-pop knows which text to replace.
+#[derive(Default, Clone, Copy)]
+enum LastAction{
+    #[default]
+    Other,
+    Typing,
+    Kill,
+    Yank(Position,Position),
 }
 
 impl Default for Editor{
     fn default()->Self{
+        Self::new(CrosstermBackend::default())
+    }
+}
+
+impl<B: Backend> Editor<B>{
+    /// An editor with an empty scratch buffer that draws through `backend`.
+    pub fn new(backend: B)->Self{
+        let mut editor = Self::with_backend(backend,Clipboard::detect());
+        for (extension,program) in DEFAULT_SERVERS{
+            if in_path(program){
+                editor.set_language_server(extension,vec![program.to_string()]);
+            }
+        }
+        editor
+    }
+
+    pub(crate) fn with_backend(backend: B, clipboard: Clipboard)->Self{
         Self{
+            backend,
             should_close: false,
             window_size: (0,0).into(),
             cursor_pos: (0,0).into(),
             buffer: Default::default(),
+            directory: None,
+            finder: None,
+            grep: None,
+            showing_hits: false,
+            run: None,
+            showing_output: false,
+            messages: vec![],
+            underneath: None,
+            quickfix: None,
+            large: None,
+            large_file_size: LARGE_FILE_SIZE,
+            hex: None,
+            formatters: HashMap::new(),
+            language_servers: HashMap::new(),
+            servers: HashMap::new(),
+            document: None,
+            popup: None,
+            git: None,
+            branch: None,
+            git_message: None,
+            aborted: false,
             mode: EditorMode::Edit,
-            mark_delta: (Default::default(),Default::default()),
+            mark: Default::default(),
             status_message: String::new(),
-            draw_accumulator: 0,
-            write_status: true, // Initialized to true because the "scratch" buffer will be "written" to since there is no data
+            status_since: Instant::now(),
             line_numbers: false,
+            complete_after: COMPLETE_AFTER,
+            viewer: false,
+            last_search: None,
             offset: (0,0).into(),
+            goal_col: None,
+            kill_ring: Default::default(),
+            session: Default::default(),
+            session_path: None,
+            last_action: Default::default(),
+            clipboard,
+            scroll: 0,
+            last_click: None,
+            dirty: true,
+            prompting: false,
         }
     }
-}
 
-impl Editor{
-    fn init(&mut self){
-        execute!(stdout(),
-                crossterm::terminal::EnterAlternateScreen
-        ).expect("red: error: failed to enter alternate screen");
+    /// Takes over the terminal and processes input until the user quits. The terminal is
+    /// restored before returning, whether or not something went wrong.
+    pub fn run(&mut self)-> Result<()>{
+        self.backend.init().map_err(Error::Terminal)?;
+        let result = self.size_and_loop();
+        let restored = self.backend.restore();
+        result.and(restored.map_err(Error::Terminal))?;
+        self.save_session()
+    }
 
-        crossterm::terminal::enable_raw_mode().expect("red: error: failed to enable raw mode");
+    /// Reads the session saved at `path`, if there is one, and saves the session there on
+    /// quitting. The kill ring and prompt answers carry over straight away, and files
+    /// opened from now on get their cursor back where it was left.
+    pub fn load_session(&mut self, path: impl Into<PathBuf>)-> Result<()>{
+        let path = path.into();
+        self.session = Session::load(&path)?;
+        self.kill_ring = KillRing::from_entries(std::mem::take(&mut self.session.kills));
+        self.session_path = Some(path);
+        Ok(())
+    }
+
+    /// Opens the file that was open when the session was saved, where it was left.
+    pub fn restore_session(&mut self)-> Result<()>{
+        match self.session.last_place().map(|place| place.path.clone()){
+            Some(path) if path.is_file() => self.open_file(&path.to_string_lossy()),
+            _ => Ok(()),
+        }
+    }
+
+    fn save_session(&mut self)-> Result<()>{
+        let path = match &self.session_path{
+            Some(path) => path.clone(),
+            None => return Ok(()),
+        };
+        self.remember_place();
+        self.session.kills = self.kill_ring.entries().map(String::from).collect();
+        self.session.save(&path)
+    }
 
-        let mut stdout = stdout();
-        stdout.execute(cursor::MoveTo(self.cursor_pos.r as u16,self.cursor_pos.c as u16)).expect(format!("red: error: failed to move cursor to {},{}",self.cursor_pos.r,self.cursor_pos.c).as_str());
-        self.window_size = crossterm::terminal::size().unwrap().into();
+    /// Notes where the cursor is in the current file, to come back to it when the file
+    /// is opened again. Files git hands over to be edited are new each time, so they are
+    /// left out.
+    fn remember_place(&mut self){
+        if self.directory.is_some() || self.large.is_some() || self.hex.is_some() || self.git_message.is_some(){
+            return;
+        }
+        if let Ok(path) = self.buffer.path().canonicalize(){
+            if path.is_file(){
+                self.session.remember(Place{path, cursor: self.cursor_pos, scroll: self.scroll, marks: self.buffer.marks().collect()});
+            }
+        }
+    }
+
+    /// Puts the cursor back where it was when the current file was last left.
+    fn restore_place(&mut self){
+        if self.git_message.is_some(){
+            return;
+        }
+        let place = match self.session.place(self.buffer.path()){
+            Some(place) => place.clone(),
+            None => return,
+        };
+        for &(name, pos) in &place.marks{
+            self.buffer.set_mark(name, pos);
+        }
+        let r = cmp::min(place.cursor.r,self.buffer.len().saturating_sub(1));
+        let line = self.buffer.get(r).unwrap_or_default();
+        let mut c = cmp::min(place.cursor.c,line.len());
+        while !line.is_char_boundary(c){
+            c -= 1;
+        }
+        self.cursor_pos = Position{r, c};
+        self.scroll = cmp::min(place.scroll,r);
     }
-    pub(crate) fn run(&mut self)-> Result<(),std::io::Error>{
-        self.init();
+
+    fn size_and_loop(&mut self)-> Result<()>{
+        self.window_size = self.backend.size().map_err(Error::Terminal)?;
         loop{
             if self.should_close{
-                if self.write_status == false{
-                    match self.prompt(format!("Open buffer {} contains data, write to disk (yes/no/cancel)? ",self.buffer.name).as_str()).trim().to_lowercase().as_str(){
-                        "n" | "no"  => break,
+                if self.is_modified() && !self.aborted{
+                    match self.prompt(format!("Open buffer {} contains data, write to disk (yes/no/cancel)? ",self.buffer.name).as_str())?.trim().to_lowercase().as_str(){
+                        "n" | "no"  =>{
+                            // Only a program waiting on a commit message or rebase plan
+                            // cares that the changes were thrown away.
+                            self.aborted = self.git_message.is_some();
+                            break;
+                        },
                         "y" | "yes" => {
-                            self.write_to_disk();
-                            break
+                            if self.write_to_disk()?{
+                                break
+                            }
+                            self.should_close = false;
                         },
                         "c" | "cancel" =>{
                             self.should_close = false;
+                            self.update_status("");
                         }
                         _ =>{},
 
@@ -81,262 +328,2176 @@ impl Editor{
                 else{
                     break;
                 }
+            }
+            // Wake up more often while background work is still coming in, to show it.
+            let mut busy = false;
+            if let Some(finder) = &mut self.finder{
+                if finder.poll(){
+                    self.dirty = true;
+                }
+                busy |= finder.walking();
+            }
+            self.poll_grep();
+            busy |= self.grep.as_ref().is_some_and(Grep::running);
+            self.poll_run();
+            busy |= self.run.as_ref().is_some_and(Run::running);
+            self.poll_servers()?;
+            // Edits made while a server was starting go out once it is up.
+            self.sync_document();
+            busy |= self.servers.values().any(LanguageServer::busy);
+            if let Some(large) = &mut self.large{
+                if large.poll(){
+                    self.dirty = true;
+                }
+                busy |= large.indexing();
+            }
+            let timeout = if busy{ 50 }else{ 500 };
+            if let Some(event) = self.backend.poll_event(Duration::from_millis(timeout)).map_err(Error::Terminal)?{
+                self.process_event(event)?;
+            }
+            if self.dirty{
+                self.draw()?;
+                self.dirty = false;
+            }
+            // Timed rather than counted, since the loop runs faster while work is coming in.
+            if !self.status_message.is_empty() && self.status_since.elapsed() >= STATUS_TIME{
+                self.status_message.truncate(0);
+                self.dirty = true;
+            }
+        }
+        Ok(())
+    }
+
+    fn process_event(&mut self, event: Event)-> Result<()>{
+        self.dirty = true;
+        match event{
+            Event::Key(k) if k.kind != KeyEventKind::Release =>{
+                self.process_keypress(k)?;
+                self.scroll_to_cursor();
+                self.update_popup();
+                self.complete_while_typing(k);
+            },
+            Event::Paste(text) if self.finder.is_some() =>{
+                if let Some(finder) = &mut self.finder{
+                    finder.push(text.lines().next().unwrap_or_default());
+                }
+            },
+            Event::Paste(text) =>{
+                self.paste(&text);
+                self.scroll_to_cursor();
+            },
+            Event::Mouse(m) =>{
+                self.process_mouse(m);
+            },
+            Event::Resize(width,height) =>{
+                self.window_size.resize((width,height));
+                self.backend.invalidate();
+            }
+            _=>{},
+        }
+        self.sync_document();
+        Ok(())
+    }
+
+    /// Saves the buffer, reporting the outcome on the status line. Returns whether it was written.
+    fn write_to_disk(&mut self)-> Result<bool>{
+        if self.viewer{
+            self.update_status(VIEW_ONLY);
+            return Ok(false);
+        }
+        if self.directory.is_some(){
+            self.update_status("Directory listings can't be written.");
+            return Ok(false);
+        }
+        if self.large.is_some(){
+            self.update_status("Large files are read-only.");
+            return Ok(false);
+        }
+        if let Some(hex) = &self.hex{
+            let file_name = match hex.buffer.path().as_os_str().is_empty(){
+                true => Some(self.prompt("File name to write: ")?.trim().to_string()),
+                false => None,
+            };
+            let hex = self.hex.as_mut().expect("checked above");
+            return match hex.buffer.write(file_name){
+                Ok(message) =>{
+                    self.buffer.name = hex.buffer.name.clone();
+                    self.update_status(message.as_str());
+                    Ok(true)
+                },
+                Err(e) =>{
+                    self.update_status(format!("Error: failed to write to disk: {}",e).as_str());
+                    Ok(false)
+                },
+            };
+        }
+        // Buffers that came from a file are written back to its path; scratch buffers need a name.
+        let file_name: Option<String> = match self.buffer.path().as_os_str().is_empty(){
+            true =>{
+                 Some(self.prompt("File name to write: ")?.trim().to_string())
+            },
+            false => None,
+        };
+        let path = match &file_name{
+            Some(name) => PathBuf::from(name),
+            None => self.buffer.path().to_path_buf(),
+        };
+        let (generation, cursor) = (self.buffer.generation(), self.cursor_pos);
+        if !self.format(&path){
+            return Ok(false);
+        }
+        match self.buffer.write(file_name){
+            Ok(message) =>{
+                self.update_status(message.as_str());
+                self.document_saved();
+                self.open_git();
+                self.watch_errors();
+                Ok(true)
+            },
+            Err(e) =>{
+                // What wasn't written stays as it was typed rather than as it was formatted.
+                if self.buffer.generation() != generation{
+                    self.buffer.undo(cursor);
+                    self.cursor_pos = cursor;
+                }
+                self.update_status(format!("Error: failed to write to disk: {}",e).as_str());
+                Ok(false)
+            },
+        }
+    }
+
+    /// Asks whether to write the changed buffer before leaving it, and returns whether
+    /// it is safe to go on: the buffer was written or the user chose to drop the changes.
+    /// Cancelling, escaping or a failed write keep the buffer where it is.
+    fn prompt_write(&mut self)-> Result<bool>{
+        match self.prompt(format!("Open buffer {} contains data, write to disk (yes/no/cancel)? ",self.buffer.name).as_str())?.trim().to_lowercase().as_str(){
+            "y" | "yes" => self.write_to_disk(),
+            "n" | "no" => Ok(true),
+            _ =>{
                 self.update_status("");
+                Ok(false)
+            },
+        }
+    }
+
+    fn new_buffer(&mut self)-> Result<()>{
+        if self.is_modified() && !self.prompt_write()?{
+            return Ok(());
+        }
+        self.switch_to(Default::default());
+        self.update_status("Created a new scratch buffer.");
+        Ok(())
+    }
+
+    /// Reads a line of input on the status line. Escape cancels and returns an empty string.
+    fn prompt(&mut self, message: &str)-> Result<String>{
+        self.prompt_with_history(message, None)
+    }
+
+    /// Asks with `message` like [`prompt`](Editor::prompt), with Up/Down going back
+    /// through the earlier answers about `topic`. The answer is added to them.
+    fn prompt_about(&mut self, message: &str, topic: Topic)-> Result<String>{
+        let answer = self.prompt_with_history(message, Some(topic))?;
+        if !answer.trim().is_empty(){
+            self.session.add_history(topic, answer.trim());
+        }
+        Ok(answer)
+    }
+
+    fn prompt_with_history(&mut self, message: &str, topic: Option<Topic>)-> Result<String>{
+        let mut input = String::new();
+        // What was typed before going back through the history, and how far back it went.
+        let mut draft = String::new();
+        let mut back = 0;
+        self.prompting = true;
+        let result = loop{
+            self.update_status(format!("{}{}",message,input).as_str());
+            if let Err(e) = self.draw(){
+                break Err(e);
+            }
+            let event = match self.backend.poll_event(Duration::from_millis(500)){
+                Ok(Some(event)) => event,
+                Ok(None) => continue,
+                Err(e) => break Err(Error::Terminal(e)),
+            };
+            match event{
+                Event::Key(KeyEvent{kind: KeyEventKind::Release, ..}) =>{},
+                Event::Key(KeyEvent{code: KeyCode::Enter, ..}) => break Ok(input),
+                Event::Key(KeyEvent{code: KeyCode::Esc, ..}) => break Ok(String::new()),
+                Event::Key(KeyEvent{code: KeyCode::Backspace, ..}) =>{
+                    input.pop();
+                },
+                Event::Key(KeyEvent{code: code @ (KeyCode::Up | KeyCode::Down), ..}) if topic.is_some() =>{
+                    let history = self.session.history(topic.expect("checked above"));
+                    if back == 0{
+                        draft = input.clone();
+                    }
+                    back = match code{
+                        KeyCode::Up => cmp::min(back + 1, history.len()),
+                        _ => back.saturating_sub(1),
+                    };
+                    input = match back{
+                        0 => draft.clone(),
+                        back => history[history.len() - back].clone(),
+                    };
+                },
+                Event::Key(KeyEvent{code: KeyCode::Char(c), modifiers, ..}) if (modifiers - KeyModifiers::SHIFT).is_empty() =>{
+                    input.push(c);
+                },
+                Event::Paste(text) =>{
+                    input.push_str(text.lines().next().unwrap_or_default());
+                },
+                Event::Resize(width,height) =>{
+                    self.window_size.resize((width,height));
+                    self.backend.invalidate();
+                },
+                _ =>{},
             }
-            if crossterm::event::poll(std::time::Duration::from_millis(500)).unwrap(){
-                match read()?{
-                    Event::Key(k) =>{
-                        self.process_keypress(k);
+        };
+        self.prompting = false;
+        self.update_status("");
+        self.dirty = true;
+        result
+    }
+
+    /// Replaces the current buffer with the file at `file_name`, asking to save unwritten
+    /// changes first and offering to create the file if it doesn't exist. Problems with the
+    /// file are shown on the status line; only terminal failures are returned.
+    pub fn open_file(&mut self, file_name: &str)-> Result<()>{
+        let file_name = file_name.replace("\"","");
+        let file_name = file_name.trim();
+        if self.is_modified() && !self.prompt_write()?{
+            return Ok(());
+        }
+        if Path::new(file_name).is_dir(){
+            self.open_directory(file_name);
+            return Ok(());
+        }
+        if std::fs::metadata(file_name).is_ok_and(|stats| stats.len() >= self.large_file_size){
+            self.open_large(file_name);
+            return Ok(());
+        }
+        if hex::is_binary(Path::new(file_name)){
+            self.open_binary(file_name);
+            return Ok(());
+        }
+        match Buffer::open(file_name){
+            Ok(buffer) =>{
+                self.switch_to(buffer);
+                if !self.buffer.read_only{
+                    self.update_status(format!("Successfully opened file {}",file_name).as_str());
+                }
+                self.open_document();
+                self.open_git();
+                self.open_git_message();
+                self.watch_errors();
+                self.restore_place();
+            },
+            Err(e) if e.is_not_found() =>{
+                match self.prompt(format!("Failed to open file {}. Create a file with the same name? ",file_name).as_str())?.trim().to_lowercase().as_str(){
+                    "yes" | "y" =>{
+                        self.switch_to(Buffer::with_path(file_name));
+                        if let Err(e) = self.buffer.write(None){
+                            self.update_status(format!("Failed to create file {}: {}",file_name,e).as_str());
+                        }
+                    },
+                    "n" | "no" =>{
+                        self.update_status("");
                     },
-                    Event::Resize(width,height) =>{
-                        self.window_size = (width,height).into();
+                    _ =>{},
+                }
+            },
+            // Binary files without a NUL byte only show up once their text fails to decode.
+            Err(Error::Encoding{..}) =>{
+                self.open_binary(file_name);
+            },
+            Err(e) =>{
+                self.update_status(format!("Error: {}",e).as_str());
+            },
+        }
+        self.backend.invalidate();
+        Ok(())
+    }
+
+    /// Opens `file_name` in the hex view.
+    fn open_binary(&mut self, file_name: &str){
+        match HexBuffer::open(file_name){
+            Ok(buffer) => self.open_hex(buffer),
+            Err(e) => self.update_status(format!("Error: {}",e).as_str()),
+        }
+        self.backend.invalidate();
+    }
+
+    /// Shows red's license in a read-only buffer.
+    fn show_license(&mut self)-> Result<()>{
+        if self.is_modified() && !self.prompt_write()?{
+            return Ok(());
+        }
+        let mut license = Buffer::from_text(LICENSE);
+        license.name = String::from("LICENSE");
+        license.read_only = true;
+        self.switch_to(license);
+        Ok(())
+    }
+
+    /// Turns viewer mode on or off. In viewer mode every buffer is read-only, nothing is
+    /// written, and keys work as in `less`: Space and `b` page, `g` and `G` go to the top
+    /// and bottom, `/` searches, `n` finds the next match and `q` quits.
+    pub fn set_viewer(&mut self, viewer: bool){
+        self.viewer = viewer;
+        self.buffer.read_only |= viewer;
+    }
+
+    /// Whether [`run`](Editor::run) ended with the user aborting a commit message or rebase
+    /// plan, with Ctrl + C or by quitting without writing it. Programs that run an editor,
+    /// like git, take a nonzero exit status to mean just that. Quitting other files without
+    /// writing them doesn't count.
+    pub fn aborted(&self) -> bool{
+        self.aborted
+    }
+
+    /// Makes `buffer` the current buffer, for text that doesn't come from a file, like
+    /// standard input. Unsaved changes to the current buffer are lost.
+    pub fn open_buffer(&mut self, buffer: Buffer){
+        self.switch_to(buffer);
+        self.open_document();
+        self.open_git();
+        self.open_git_message();
+        self.watch_errors();
+    }
+
+    /// Runs `command` as the language server for files ending in `.extension`; an empty
+    /// command turns language services off for them. A server is started when the first
+    /// of its files is opened and serves every file with the same command after that.
+    pub fn set_language_server(&mut self, extension: &str, command: Vec<String>){
+        if command.is_empty(){
+            self.language_servers.remove(extension);
+        }
+        else{
+            self.language_servers.insert(extension.to_string(),command);
+        }
+    }
+
+    /// Turns off language services for every extension, including the servers used when
+    /// they are installed. Servers can be set again with
+    /// [`set_language_server`](Editor::set_language_server).
+    pub fn clear_language_servers(&mut self){
+        self.language_servers.clear();
+    }
+
+    /// Formats files ending in `.extension` with the shell command `command` each time
+    /// they are written: the text goes to its standard input and what it prints is what
+    /// gets written, e.g. `rustfmt --emit stdout` for `rs`. An empty command stops
+    /// formatting them.
+    pub fn set_formatter(&mut self, extension: &str, command: &str){
+        if command.trim().is_empty(){
+            self.formatters.remove(extension);
+        }
+        else{
+            self.formatters.insert(extension.to_string(),command.to_string());
+        }
+    }
+
+    /// Runs the formatter for files like `path` over the buffer, if there is one, keeping
+    /// the cursor on the same text. Returns false if the formatter failed, after showing why.
+    fn format(&mut self, path: &Path)-> bool{
+        if self.buffer.read_only{
+            return true;
+        }
+        let command = match path.extension().and_then(|extension| self.formatters.get(extension.to_string_lossy().as_ref())){
+            Some(command) => command.clone(),
+            None => return true,
+        };
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let output = match shell::filter(&command,&format!("{}\n",self.buffer.text()),dir){
+            Ok(output) => output,
+            Err(problems) =>{
+                self.show_messages(format!("Formatting {} with {} failed:",path.display(),command),problems);
+                self.update_status(format!("Not written: formatting with {} failed.",command).as_str());
+                return false;
+            },
+        };
+        let new: Vec<String> = output.lines().map(String::from).collect();
+        let hunks = git::diff(self.buffer.lines(),&new);
+        let old_line = self.buffer.get(self.cursor_pos.r).map(String::from);
+        // The formatting is undone on its own, which is also how a failed write takes it back.
+        self.buffer.checkpoint(self.cursor_pos);
+        // Only the lines that changed are replaced, bottom up so the rows above stay put.
+        for hunk in hunks.iter().rev(){
+            self.buffer.replace_lines(hunk.old_start,hunk.removed,new[hunk.start..hunk.start + hunk.added].to_vec());
+        }
+        // Inside a changed stretch, the cursor's line is looked for with its spacing ignored.
+        let row = hunks.iter()
+            .find(|hunk| (hunk.old_start..hunk.old_start + hunk.removed).contains(&self.cursor_pos.r))
+            .and_then(|hunk| (hunk.start..hunk.start + hunk.added).find(|&row| Some(new[row].trim()) == old_line.as_deref().map(str::trim)))
+            .unwrap_or_else(|| git::follow_row(&hunks,self.cursor_pos.r));
+        self.cursor_pos.r = cmp::min(row,self.buffer.len().saturating_sub(1));
+        // Reindenting shouldn't move the cursor off the text it was on.
+        let line = self.buffer.get(self.cursor_pos.r).unwrap_or_default();
+        let indent = |line: &str| line.len() - line.trim_start().len();
+        let c = match &old_line{
+            Some(old) if self.cursor_pos.c >= indent(old) => indent(line) + self.cursor_pos.c - indent(old),
+            _ => self.cursor_pos.c,
+        };
+        self.cursor_pos.c = cmp::min(c,line.len());
+        while !line.is_char_boundary(self.cursor_pos.c){
+            self.cursor_pos.c -= 1;
+        }
+        self.goal_col = None;
+        true
+    }
+
+    /// Tells the language server for the current buffer's file about it, starting the
+    /// server if it isn't running yet.
+    fn open_document(&mut self){
+        if self.document.is_some(){
+            return;
+        }
+        let extension = match self.buffer.path().extension(){
+            Some(extension) => extension.to_string_lossy().into_owned(),
+            None => return,
+        };
+        let command = match self.language_servers.get(&extension){
+            Some(command) => command.clone(),
+            None => return,
+        };
+        // Servers know files by absolute path, and report them that way.
+        let path = match self.buffer.path().canonicalize().or_else(|_| std::path::absolute(self.buffer.path())){
+            Ok(path) => path,
+            Err(_) => return,
+        };
+        let key = command.join(" ");
+        if !self.servers.contains_key(&key){
+            let root = project_root(path.parent().unwrap_or(Path::new("/")));
+            match LanguageServer::start(&command, &root){
+                Ok(server) =>{
+                    self.servers.insert(key.clone(), server);
+                },
+                Err(e) =>{
+                    self.update_status(format!("Couldn't start {}: {}",key,e).as_str());
+                    return;
+                },
+            }
+        }
+        if let Some(server) = self.servers.get_mut(&key){
+            server.open(&path, lsp::language_id(&extension), &self.buffer);
+            self.buffer.track_edits();
+            self.buffer.take_edits();
+            self.document = Some((key, path));
+        }
+    }
+
+    fn close_document(&mut self){
+        if let Some((key, path)) = self.document.take(){
+            if let Some(server) = self.servers.get_mut(&key){
+                server.close(&path);
+            }
+        }
+    }
+
+    /// Passes the edits made to the current buffer on to its language server.
+    fn sync_document(&mut self){
+        if let Some((key, path)) = &self.document{
+            if let Some(server) = self.servers.get_mut(key){
+                server.change(path, &mut self.buffer);
+            }
+        }
+    }
+
+    /// Lets the language server know the buffer was written, or tells one about it now
+    /// that it has a file name.
+    fn document_saved(&mut self){
+        match &self.document{
+            Some((key, path)) =>{
+                if let Some(server) = self.servers.get_mut(key){
+                    server.saved(path);
+                }
+            },
+            None => self.open_document(),
+        }
+    }
+
+    /// Whether the current buffer has a language server that is up and running.
+    fn server_ready(&self) -> bool{
+        self.document.as_ref().and_then(|(key, _)| self.servers.get(key)).is_some_and(LanguageServer::ready)
+    }
+
+    /// Asks the current buffer's language server something about the cursor's position.
+    /// The answer comes in through `poll_servers`.
+    fn ask_server(&mut self, ask: fn(&mut LanguageServer, &Path, Position, &str)){
+        let (key, path) = match &self.document{
+            Some(document) => document,
+            None =>{
+                self.update_status("No language server for this file.");
+                return;
+            },
+        };
+        match self.servers.get_mut(key){
+            Some(server) if !server.ready() => self.update_status("The language server is still starting."),
+            Some(server) => ask(server, path, self.cursor_pos, self.buffer.get(self.cursor_pos.r).unwrap_or_default()),
+            None =>{},
+        }
+    }
+
+    /// Acts on what the language servers sent since the last call.
+    fn poll_servers(&mut self)-> Result<()>{
+        let mut replies = vec![];
+        for (key, server) in self.servers.iter_mut(){
+            replies.extend(server.poll().into_iter().map(|reply| (key.clone(), reply)));
+        }
+        for (key, reply) in replies{
+            match reply{
+                // Other files' diagnostics aren't shown anywhere.
+                Reply::Diagnostics(path) if self.document.as_ref().is_none_or(|(_, current)| *current != path) => continue,
+                Reply::Diagnostics(_) =>{},
+                Reply::Hover(text) => self.show_hover(&text),
+                Reply::Definition(Some((path, pos))) => self.goto_definition(&key, &path, pos)?,
+                Reply::Definition(None) => self.update_status("No definition found."),
+                Reply::Completions(items) => self.show_completions(items),
+                Reply::Message(message) => self.update_status(&message),
+                Reply::Exited =>{
+                    self.servers.remove(&key);
+                    if self.document.as_ref().is_some_and(|(document, _)| *document == key){
+                        self.document = None;
                     }
-                    _=>{},
+                    self.update_status(format!("Language server {} exited.",key).as_str());
+                },
+            }
+            self.dirty = true;
+        }
+        Ok(())
+    }
+
+    /// The current buffer's diagnostics, with columns as byte offsets.
+    fn diagnostics(&self) -> Vec<Diagnostic>{
+        let (server, path) = match &self.document{
+            Some((key, path)) => match self.servers.get(key){
+                Some(server) => (server, path),
+                None => return vec![],
+            },
+            None => return vec![],
+        };
+        let column = |pos: Position| Position{r: pos.r, c: server.column(self.buffer.get(pos.r).unwrap_or_default(), pos.c)};
+        server.diagnostics(path).iter()
+            .map(|diagnostic| Diagnostic{start: column(diagnostic.start), end: column(diagnostic.end), ..diagnostic.clone()})
+            .collect()
+    }
+
+    /// Shows the diagnostics at the cursor along with the server's hover text.
+    fn show_hover(&mut self, text: &str){
+        let cursor = self.cursor_pos;
+        let mut lines: Vec<String> = self.diagnostics().into_iter()
+            .filter(|diagnostic| diagnostic.start <= cursor && cursor <= diagnostic.end)
+            .map(|diagnostic| format!("{}: {}",diagnostic.severity.name(),diagnostic.message))
+            .collect();
+        // Markdown code fences only get in the way as plain text.
+        lines.extend(text.lines().filter(|line| !line.starts_with("```")).map(String::from));
+        match lines.is_empty(){
+            true => self.update_status("Nothing known about this."),
+            false => self.popup = Some(Popup::Text(lines)),
+        }
+    }
+
+    /// Moves to where a server said something is defined, opening its file if need be.
+    /// `units` counts columns the way the server `key` does.
+    fn goto_definition(&mut self, key: &str, path: &Path, units: Position)-> Result<()>{
+        if self.document.as_ref().is_none_or(|(_, current)| current != path){
+            self.open_file(&path.to_string_lossy())?;
+            if !same_file(self.buffer.path(), path){
+                return Ok(());
+            }
+        }
+        let r = cmp::min(units.r, self.buffer.len().saturating_sub(1));
+        let line = self.buffer.get(r).unwrap_or_default();
+        let c = match self.servers.get(key){
+            Some(server) => server.column(line, units.c),
+            None => cmp::min(units.c, line.len()),
+        };
+        self.cursor_pos = Position{r, c};
+        self.goal_col = None;
+        self.scroll_to_cursor();
+        Ok(())
+    }
+
+    fn show_completions(&mut self, items: Vec<Completion>){
+        self.popup = Some(Popup::Completion{items, start: self.word_start(), selected: 0});
+        self.update_popup();
+        if self.popup.is_none(){
+            self.update_status("No completions.");
+        }
+    }
+
+    /// The words of the buffer that complete the one from `start` to the cursor, nearest
+    /// first.
+    fn word_completions(&self, start: Position) -> Vec<Completion>{
+        self.buffer.words_near(self.cursor_pos, &self.buffer.text_range(start, self.cursor_pos)).into_iter()
+            .map(|word| Completion{label: word.clone(), text: word, detail: String::new()})
+            .collect()
+    }
+
+    /// Opens the completion list by itself once `complete_after` characters of a word
+    /// have been typed, if the buffer has words to offer.
+    fn complete_while_typing(&mut self, key_event: KeyEvent){
+        let typed = matches!(key_event.code, KeyCode::Char(c) if is_word_char(c)) && (key_event.modifiers - KeyModifiers::SHIFT).is_empty();
+        if !typed || self.complete_after == 0 || self.popup.is_some() || self.buffer.read_only
+            || self.finder.is_some() || self.directory.is_some() || self.hex.is_some() || self.large.is_some(){
+            return;
+        }
+        let start = self.word_start();
+        if self.buffer.text_range(start, self.cursor_pos).chars().count() != self.complete_after{
+            return;
+        }
+        self.popup = Some(Popup::Completion{items: self.word_completions(start), start, selected: 0});
+        self.update_popup();
+    }
+
+    /// Where the word that ends at the cursor starts.
+    fn word_start(&self) -> Position{
+        let line = self.buffer.get(self.cursor_pos.r).unwrap_or_default();
+        let c = cmp::min(self.cursor_pos.c, line.len());
+        let start = line[..c].char_indices().rev()
+            .take_while(|&(_, ch)| is_word_char(ch))
+            .last()
+            .map_or(c, |(i, _)| i);
+        Position{r: self.cursor_pos.r, c: start}
+    }
+
+    /// The completions that start with what has been typed since the popup opened,
+    /// ignoring case. Ones that match in case too come first.
+    fn matching_completions(&self) -> Vec<&Completion>{
+        match &self.popup{
+            Some(Popup::Completion{items, start, ..}) if start.r == self.cursor_pos.r && start.c <= self.cursor_pos.c =>{
+                let typed = self.buffer.text_range(*start, self.cursor_pos);
+                let lower = typed.to_lowercase();
+                // Offering what is already there would complete nothing.
+                let mut matching: Vec<&Completion> = items.iter().filter(|item| item.text != typed && item.text.to_lowercase().starts_with(&lower)).collect();
+                // Ones typed with the same case come first, otherwise the order is kept.
+                matching.sort_by_key(|item| !item.text.starts_with(&typed));
+                matching
+            },
+            _ => vec![],
+        }
+    }
+
+    /// Closes the completion popup once nothing matches any more.
+    fn update_popup(&mut self){
+        let count = self.matching_completions().len();
+        if let Some(Popup::Completion{selected, ..}) = &mut self.popup{
+            if count == 0{
+                self.popup = None;
+            }
+            else{
+                *selected = cmp::min(*selected, count - 1);
+            }
+        }
+    }
+
+    /// Handles keys while a popup is shown. Returns false for keys that should go on to do
+    /// what they normally do; apart from typing, which narrows completions down, those
+    /// close the popup.
+    fn process_popup_key(&mut self, key_event: KeyEvent) -> bool{
+        let count = self.matching_completions().len();
+        let typing = (key_event.modifiers - KeyModifiers::SHIFT).is_empty();
+        match (&mut self.popup, key_event.code){
+            (_, KeyCode::Esc) =>{
+                self.popup = None;
+                true
+            },
+            (Some(Popup::Completion{selected, ..}), KeyCode::Up) =>{
+                *selected = selected.saturating_sub(1);
+                true
+            },
+            (Some(Popup::Completion{selected, ..}), KeyCode::Down) =>{
+                *selected = cmp::min(*selected + 1, count.saturating_sub(1));
+                true
+            },
+            (Some(Popup::Completion{..}), KeyCode::Enter | KeyCode::Tab) =>{
+                self.accept_completion();
+                true
+            },
+            (Some(Popup::Completion{..}), KeyCode::Char(_) | KeyCode::Backspace) if typing => false,
+            _ =>{
+                self.popup = None;
+                false
+            },
+        }
+    }
+
+    /// Replaces the word before the cursor with the selected completion.
+    fn accept_completion(&mut self){
+        let (start, selected) = match &self.popup{
+            Some(Popup::Completion{start, selected, ..}) => (*start, *selected),
+            _ => return,
+        };
+        let text = self.matching_completions().get(selected).map(|item| item.text.clone());
+        self.popup = None;
+        if let Some(text) = text{
+            self.buffer.remove_range(start, self.cursor_pos);
+            self.cursor_pos = self.buffer.insert_str(start, &text);
+            self.goal_col = None;
+        }
+    }
+
+    /// Looks up the current file's committed version and branch, to show what changed.
+    fn open_git(&mut self){
+        let path = self.buffer.path().to_path_buf();
+        if path.as_os_str().is_empty(){
+            return;
+        }
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        self.branch = git::branch(dir);
+        self.git = self.branch.as_ref().and_then(|_| GitFile::open(&path));
+    }
+
+    /// Notices files git asked to have edited, and says how to finish them.
+    fn open_git_message(&mut self){
+        self.git_message = Message::of(self.buffer.path());
+        if let Some(message) = self.git_message{
+            self.update_status(message.hint());
+        }
+    }
+
+    /// Handles the keys that finish or abort a file git asked to have edited, and cycling
+    /// the commands of a rebase plan. Returns false for any other key.
+    fn process_git_message_key(&mut self, key_event: KeyEvent)-> Result<bool>{
+        let message = match self.git_message{
+            Some(message) => message,
+            None => return Ok(false),
+        };
+        match (key_event.modifiers,key_event.code){
+            // Finishing always writes, so git gets the file as it is shown.
+            (KeyModifiers::CONTROL,KeyCode::Char('q')) =>{
+                if self.buffer.read_only || !self.is_modified() || self.write_to_disk()?{
+                    self.should_close = true;
+                }
+            },
+            (KeyModifiers::CONTROL,KeyCode::Char('c')) =>{
+                self.aborted = true;
+                self.should_close = true;
+            },
+            (KeyModifiers::NONE,KeyCode::Tab) | (_,KeyCode::BackTab) if message == Message::RebaseTodo =>{
+                let direction = if key_event.code == KeyCode::Tab{ Direction::Forward }else{ Direction::Backward };
+                let row = self.cursor_pos.r;
+                match self.buffer.get(row).and_then(|line| git::cycle_verb(line,direction)){
+                    Some(line) =>{
+                        self.buffer.replace_lines(row,1,vec![line]);
+                        self.cursor_pos.c = cmp::min(self.cursor_pos.c,self.buffer.get(row).unwrap_or_default().len());
+                    },
+                    None => self.update_status("This line has no command to change."),
+                }
+            },
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Moves to the start of the next (or previous) change since the last commit.
+    fn goto_hunk(&mut self, direction: Direction){
+        let git = match &mut self.git{
+            Some(git) => git,
+            None =>{
+                self.update_status("This file isn't committed to git.");
+                return;
+            },
+        };
+        git.update(&self.buffer);
+        let row = self.cursor_pos.r;
+        let mut starts = git.hunks().iter().map(|hunk| hunk.rows().start);
+        let found = match direction{
+            Direction::Forward => starts.find(|&start| start > row),
+            Direction::Backward => starts.rfind(|&start| start < row),
+        };
+        match found{
+            Some(start) =>{
+                self.cursor_pos = Position{r: start, c: 0};
+                self.goal_col = None;
+            },
+            None => self.update_status("No more changes."),
+        }
+    }
+
+    /// Puts back the committed lines in place of the change at the cursor.
+    fn revert_hunk(&mut self){
+        let git = match &mut self.git{
+            Some(git) => git,
+            None =>{
+                self.update_status("This file isn't committed to git.");
+                return;
+            },
+        };
+        git.update(&self.buffer);
+        let row = self.cursor_pos.r;
+        let hunk = match git.hunks().iter().find(|hunk| hunk.rows().contains(&row)){
+            Some(hunk) => hunk.clone(),
+            None =>{
+                self.update_status("No change here to revert.");
+                return;
+            },
+        };
+        self.buffer.replace_lines(hunk.start, hunk.added, git.old_lines(&hunk));
+        self.cursor_pos = Position{r: cmp::min(hunk.start, self.buffer.len().saturating_sub(1)), c: 0};
+        self.goal_col = None;
+        self.update_status("Reverted the change.");
+    }
+
+    /// Sends the region, or the whole buffer without one, through a shell command and
+    /// puts what it prints in its place as one edit. If the command fails, what it said is
+    /// shown instead and the text is left alone.
+    fn prompt_filter(&mut self)-> Result<()>{
+        let command = self.prompt_about("Filter through command: ", Topic::Filters)?;
+        let command = command.trim();
+        if command.is_empty(){
+            return Ok(());
+        }
+        let whole = self.region().is_none();
+        let (start,end) = self.region().unwrap_or_else(||{
+            let last = self.buffer.len().saturating_sub(1);
+            (Position::default(),Position{r: last, c: self.buffer.get(last).unwrap_or_default().len()})
+        });
+        let dir = self.buffer.path().parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new(".")).to_path_buf();
+        // Tools expect lines to end in a newline, and print one at the end in turn.
+        let input = format!("{}\n",self.buffer.text_range(start,end));
+        match shell::filter(command,&input,&dir){
+            Ok(output) =>{
+                let output = output.strip_suffix('\n').unwrap_or(&output);
+                if whole{
+                    self.buffer.replace_lines(0,self.buffer.len(),output.split('\n').map(String::from).collect());
+                    self.cursor_pos.r = cmp::min(self.cursor_pos.r,self.buffer.len().saturating_sub(1));
+                    self.cursor_pos.c = cmp::min(self.cursor_pos.c,self.buffer.get(self.cursor_pos.r).unwrap_or_default().len());
+                }
+                else{
+                    self.buffer.remove_range(start,end);
+                    self.cursor_pos = self.buffer.insert_str(start,output);
+                    self.mode = EditorMode::Edit;
+                }
+                self.goal_col = None;
+                self.update_status(format!("Replaced with the output of {}.",command).as_str());
+            },
+            Err(problems) =>{
+                self.show_messages(format!("Filtering through {} failed:",command),problems);
+                self.update_status("The command failed, so the text was left as it was.");
+            },
+        }
+        Ok(())
+    }
+
+    /// Adds `title` and `problems` to the messages and shows them all in a read-only
+    /// `*messages*` buffer over the current one, which is kept as it is until they close.
+    fn show_messages(&mut self, title: String, problems: Vec<String>){
+        if !self.messages.is_empty(){
+            self.messages.push(String::new());
+        }
+        let row = self.messages.len();
+        self.messages.push(title);
+        self.messages.extend(problems);
+        let underneath = match self.underneath.take(){
+            Some(underneath) => underneath,
+            None => (std::mem::take(&mut self.buffer),self.cursor_pos,self.scroll),
+        };
+        let mut buffer = Buffer::from_text(&self.messages.join("\n"));
+        buffer.name = String::from("*messages*");
+        buffer.read_only = true;
+        self.switch_to(buffer);
+        self.underneath = Some(underneath);
+        self.cursor_pos.r = row;
+    }
+
+    /// Handles keys while the messages are showing. Keys that move around read them, Esc
+    /// and `q` close them, and any other key closes them and then goes to the buffer they
+    /// covered. Returns false for keys that are left to the usual handling.
+    fn process_messages_key(&mut self, key_event: KeyEvent)-> bool{
+        if self.underneath.is_none(){
+            return false;
+        }
+        if matches!(key_event.code,KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right | KeyCode::Home | KeyCode::End | KeyCode::PageUp | KeyCode::PageDown){
+            return false;
+        }
+        if let Some((buffer,cursor,scroll)) = self.underneath.take(){
+            self.open_buffer(buffer);
+            self.cursor_pos = cursor;
+            self.scroll = scroll;
+        }
+        matches!((key_event.modifiers,key_event.code),(_,KeyCode::Esc) | (KeyModifiers::NONE,KeyCode::Char('q')))
+    }
+
+    /// Shows `buffer` byte by byte in a hex view, for content that isn't text. Unsaved
+    /// changes to the current buffer are lost.
+    pub fn open_hex(&mut self, buffer: HexBuffer){
+        // The text buffer stands in for the bytes in the modeline.
+        let mut stand_in = Buffer::with_path(buffer.path());
+        stand_in.name = buffer.name.clone();
+        stand_in.read_only = buffer.read_only;
+        self.switch_to(stand_in);
+        self.hex = Some(HexView::new(buffer));
+        self.update_status("Binary file: type hex digits to overwrite bytes, Tab switches to characters.");
+    }
+
+    /// Handles keys in the hex view: motion moves by bytes and typing overwrites them.
+    /// Returns false for keys that work the same in any buffer, like opening files.
+    fn process_hex_key(&mut self, key_event: KeyEvent)-> bool{
+        let page = cmp::max(self.text_rows(),1) * BYTES_PER_ROW;
+        let read_only = self.buffer.read_only;
+        let hex = match &mut self.hex{
+            Some(hex) => hex,
+            None => return false,
+        };
+        let offset = hex.offset();
+        let row_start = offset - offset % BYTES_PER_ROW;
+        match (key_event.modifiers - KeyModifiers::SHIFT,key_event.code){
+            (KeyModifiers::CONTROL,KeyCode::Home) => hex.move_to(0),
+            (KeyModifiers::CONTROL,KeyCode::End) => hex.move_to(usize::MAX),
+            (_,KeyCode::Up) => hex.move_to(offset.checked_sub(BYTES_PER_ROW).unwrap_or(offset)),
+            (_,KeyCode::Down) if offset + BYTES_PER_ROW < hex.buffer.len() => hex.move_to(offset + BYTES_PER_ROW),
+            (_,KeyCode::Down) => hex.move_to(offset),
+            (_,KeyCode::Left) => hex.move_to(offset.saturating_sub(1)),
+            (_,KeyCode::Right) => hex.move_to(offset + 1),
+            (_,KeyCode::Home) => hex.move_to(row_start),
+            (_,KeyCode::End) => hex.move_to(row_start + BYTES_PER_ROW - 1),
+            (_,KeyCode::PageUp) => hex.move_to(offset.saturating_sub(page)),
+            (_,KeyCode::PageDown) => hex.move_to(offset.saturating_add(page)),
+            (_,KeyCode::Tab) => hex.toggle_text(),
+            (KeyModifiers::NONE,KeyCode::Char(_)) if read_only =>{
+                self.update_status(if self.viewer{ VIEW_ONLY }else{ "File is read-only." });
+            },
+            (KeyModifiers::NONE,KeyCode::Char(c)) =>{
+                if !hex.type_char(c){
+                    let message = if hex.text(){ "Only ASCII characters fit here." }else{ "Type a hex digit, 0-9 or a-f." };
+                    self.update_status(message);
+                }
+            },
+            (KeyModifiers::CONTROL,KeyCode::Char('q' | 'w' | 'o' | 'n' | 'p' | 'j')) |
+            (KeyModifiers::ALT,KeyCode::Char('g' | 'n' | 'p')) |
+            (_,KeyCode::F(_) | KeyCode::Esc) => return false,
+            _ =>{
+                self.update_status("Bytes can only be overwritten in a binary file.");
+            },
+        }
+        if let Some(hex) = &self.hex{
+            self.cursor_pos = hex.position();
+        }
+        self.goal_col = None;
+        true
+    }
+
+    /// Handles the `less` keys of viewer mode. Returns false for keys it leaves alone.
+    fn process_pager_key(&mut self, key_event: KeyEvent)-> Result<bool>{
+        if !(key_event.modifiers - KeyModifiers::SHIFT).is_empty(){
+            return Ok(false);
+        }
+        let last = self.line_count().saturating_sub(1);
+        let page = cmp::max(self.text_rows(),1);
+        let row = match key_event.code{
+            KeyCode::Char(' ' | 'f') | KeyCode::PageDown => cmp::min(self.cursor_pos.r + page, last),
+            KeyCode::Char('b') | KeyCode::PageUp => self.cursor_pos.r.saturating_sub(page),
+            KeyCode::Char('j' | 'e') => cmp::min(self.cursor_pos.r + 1, last),
+            KeyCode::Char('k' | 'y') => self.cursor_pos.r.saturating_sub(1),
+            KeyCode::Char('g' | '<') => 0,
+            KeyCode::Char('G' | '>') => last,
+            KeyCode::Char('q') =>{
+                self.should_close = true;
+                return Ok(true);
+            },
+            KeyCode::Char('/') =>{
+                let pattern = self.prompt_about("/", Topic::Search)?;
+                if !pattern.is_empty(){
+                    self.last_search = Some(pattern);
+                    self.search_forward();
+                }
+                return Ok(true);
+            },
+            KeyCode::Char('n') =>{
+                self.search_forward();
+                return Ok(true);
+            },
+            _ => return Ok(false),
+        };
+        // Paging moves the cursor along so the page-wise scrolling follows it.
+        self.cursor_pos = Position{r: row, c: 0};
+        self.goal_col = None;
+        Ok(true)
+    }
+
+    /// Moves to the next match of the last `/` search after the cursor.
+    fn search_forward(&mut self){
+        let pattern = match &self.last_search{
+            Some(pattern) => pattern.clone(),
+            None =>{
+                self.update_status("No previous search.");
+                return;
+            },
+        };
+        // Start just past the cursor so that repeating the search moves on.
+        if self.large.is_some(){
+            // A byte on is enough here: a match can't start inside a character.
+            self.search_large(&pattern, Position{r: self.cursor_pos.r, c: self.cursor_pos.c + 1});
+            return;
+        }
+        let line = self.buffer.get(self.cursor_pos.r).unwrap_or_default();
+        let step = line.get(self.cursor_pos.c..).and_then(|rest| rest.chars().next()).map_or(1, char::len_utf8);
+        let from = Position{r: self.cursor_pos.r, c: self.cursor_pos.c + step};
+        match self.buffer.find_from(&pattern, from){
+            Some(pos) =>{
+                self.cursor_pos = pos;
+                self.goal_col = None;
+            },
+            None => self.update_status(format!("Pattern not found: {}",pattern).as_str()),
+        }
+    }
+
+    /// Views `path` without loading it. The buffer stands in for the file in the modeline
+    /// and is never written.
+    fn open_large(&mut self, path: &str){
+        match LargeFile::open(Path::new(path)){
+            Ok(large) =>{
+                let mut buffer = Buffer::with_path(path);
+                buffer.read_only = true;
+                self.switch_to(buffer);
+                self.large = Some(large);
+                self.update_status(format!("{} is large, it is opened read-only.",path).as_str());
+            },
+            Err(e) => self.update_status(format!("Error: {}",e).as_str()),
+        }
+        self.backend.invalidate();
+    }
+
+    /// Handles keys while viewing a large file: motion works as usual and editing is refused.
+    /// Returns false for keys that work the same in any buffer, like opening files.
+    fn process_large_key(&mut self, key_event: KeyEvent)-> bool{
+        let page = self.text_rows();
+        let large = match &mut self.large{
+            Some(large) => large,
+            None => return false,
+        };
+        let count = large.line_count();
+        let last = count.saturating_sub(1);
+        let row = match (key_event.modifiers,key_event.code){
+            (KeyModifiers::CONTROL,KeyCode::Home) => 0,
+            (KeyModifiers::CONTROL,KeyCode::End) => last,
+            (_,KeyCode::Up) => self.cursor_pos.r.saturating_sub(1),
+            (_,KeyCode::Down) => cmp::min(self.cursor_pos.r + 1, last),
+            (_,KeyCode::PageUp) => self.cursor_pos.r.saturating_sub(page),
+            (_,KeyCode::PageDown) => cmp::min(self.cursor_pos.r + page, last),
+            (_,KeyCode::Left | KeyCode::Right | KeyCode::Home | KeyCode::End) => self.cursor_pos.r,
+            (KeyModifiers::CONTROL,KeyCode::Char('q' | 'w' | 'o' | 'n' | 'p' | 'j' | 'f')) |
+            (KeyModifiers::ALT,KeyCode::Char('g' | 'n' | 'p')) |
+            (_,KeyCode::F(_) | KeyCode::Esc) => return false,
+            _ =>{
+                self.update_status("Large files are read-only.");
+                return true;
+            },
+        };
+        let len = large.line(row).map(|line| line.len()).unwrap_or(0);
+        let goal = self.goal_col.unwrap_or(self.cursor_pos.c);
+        self.cursor_pos.c = match key_event.code{
+            KeyCode::Left => self.cursor_pos.c.saturating_sub(1),
+            KeyCode::Right => cmp::min(self.cursor_pos.c + 1, len),
+            KeyCode::Home => 0,
+            KeyCode::End => len,
+            _ => cmp::min(goal, len),
+        };
+        self.goal_col = match key_event.code{
+            KeyCode::Up | KeyCode::Down | KeyCode::PageUp | KeyCode::PageDown => Some(goal),
+            _ => None,
+        };
+        self.cursor_pos.r = row;
+        true
+    }
+
+    /// Number of lines in the current buffer, indexed so far in a large file, or of bytes
+    /// in the hex view.
+    fn line_count(&self) -> usize{
+        match (&self.large,&self.hex){
+            (Some(large),_) => large.line_count(),
+            (_,Some(hex)) => hex.buffer.rows(),
+            _ => self.buffer.len(),
+        }
+    }
+
+    /// Whether the current buffer, or the bytes in the hex view, have unwritten changes.
+    fn is_modified(&self) -> bool{
+        self.buffer.is_modified() || self.hex.as_ref().is_some_and(|hex| hex.buffer.is_modified())
+    }
+
+    /// Makes `buffer` the current buffer, starting at its top.
+    fn switch_to(&mut self, mut buffer: Buffer){
+        self.remember_place();
+        if let Some(quickfix) = &mut self.quickfix{
+            quickfix.follow(&self.buffer);
+        }
+        self.close_document();
+        self.popup = None;
+        self.git = None;
+        self.branch = None;
+        self.git_message = None;
+        buffer.read_only |= self.viewer;
+        self.buffer = buffer;
+        self.directory = None;
+        self.showing_hits = false;
+        self.showing_output = false;
+        self.underneath = None;
+        self.large = None;
+        self.hex = None;
+        self.cursor_pos = Default::default();
+        self.goal_col = None;
+        self.scroll = 0;
+        self.mode = EditorMode::Edit;
+    }
+
+    /// Shows a listing of the directory at `path`. Unsaved changes must be dealt with first.
+    fn open_directory(&mut self, path: impl AsRef<Path>){
+        match Directory::open(path){
+            Ok(directory) =>{
+                let first = directory.entries().iter().find(|entry| entry.name != "..").map(|entry| entry.name.clone());
+                self.switch_to(directory.listing());
+                self.update_status(format!("{}: Enter opens, c creates, r renames, d deletes, g refreshes",directory.path().display()).as_str());
+                self.directory = Some(directory);
+                // Start on the first entry rather than on the header or `..`.
+                if let Some(name) = first{
+                    self.select_entry(&name);
+                }
+            },
+            Err(e) => self.update_status(format!("Error: {}",e).as_str()),
+        }
+        self.backend.invalidate();
+    }
+
+    /// Handles the keys that act on the entry under the cursor in a directory listing.
+    /// Returns false for keys that should get their usual meaning, like motion.
+    fn process_directory_key(&mut self, key_event: KeyEvent)-> Result<bool>{
+        let directory = match self.directory.as_mut(){
+            Some(directory) => directory,
+            None => return Ok(false),
+        };
+        if !(key_event.modifiers - KeyModifiers::SHIFT).is_empty(){
+            return Ok(false);
+        }
+        let row = self.cursor_pos.r;
+        let name = directory.entry(row).map(|entry| entry.name.clone());
+        if self.viewer && matches!(key_event.code, KeyCode::Char('c' | 'r' | 'd')){
+            self.update_status(VIEW_ONLY);
+            return Ok(true);
+        }
+        match key_event.code{
+            KeyCode::Enter =>{
+                match (directory.entry(row).map(|entry| entry.kind), directory.entry_path(row)){
+                    (Some(EntryKind::Directory), Some(path)) => self.open_directory(path),
+                    (Some(_), Some(path)) => self.open_file(&path.to_string_lossy())?,
+                    _ =>{},
+                }
+            },
+            KeyCode::Backspace | KeyCode::Char('^') =>{
+                let current = directory.path().to_path_buf();
+                if let Some(parent) = current.parent(){
+                    self.open_directory(parent);
+                    self.select_entry(&name_of(&current));
+                }
+            },
+            KeyCode::Char('g') =>{
+                let result = directory.refresh();
+                self.show_directory_result(result.map(|_| None), name.as_deref());
+            },
+            KeyCode::Char('c') =>{
+                let new_name = self.prompt("Create (end with / for a directory): ")?;
+                let new_name = new_name.trim();
+                if !new_name.is_empty(){
+                    let result = self.directory.as_mut().map(|directory| directory.create(new_name)).transpose();
+                    self.show_directory_result(result, Some(new_name.trim_end_matches('/')));
+                }
+            },
+            KeyCode::Char('r') =>{
+                let name = match name{
+                    Some(name) if name != ".." => name,
+                    _ => return Ok(true),
+                };
+                let new_name = self.prompt(format!("Rename {} to: ",name).as_str())?;
+                let new_name = new_name.trim();
+                if new_name.is_empty(){
+                    return Ok(true);
+                }
+                let target_exists = self.directory.as_ref().is_some_and(|directory| directory.path().join(new_name).exists());
+                if target_exists && !self.confirm(format!("{} exists, overwrite it (yes/no)? ",new_name).as_str())?{
+                    return Ok(true);
+                }
+                let result = self.directory.as_mut().map(|directory| directory.rename(row,new_name)).transpose();
+                self.show_directory_result(result, Some(new_name));
+            },
+            KeyCode::Char('d') =>{
+                let name = match name{
+                    Some(name) if name != ".." => name,
+                    _ => return Ok(true),
+                };
+                let question = match directory.entry(row).map(|entry| entry.kind){
+                    Some(EntryKind::Directory) => format!("Delete {} and everything in it (yes/no)? ",name),
+                    _ => format!("Delete {} (yes/no)? ",name),
+                };
+                if self.confirm(&question)?{
+                    let result = self.directory.as_mut().map(|directory| directory.delete(row)).transpose();
+                    self.show_directory_result(result, None);
+                }
+            },
+            // The listing can't be edited, so every other plain key is swallowed.
+            KeyCode::Char(_) | KeyCode::Delete | KeyCode::Tab =>{},
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Redraws the directory listing after an operation and reports how it went. The cursor
+    /// goes to `select` if it is still listed and stays on the same line otherwise.
+    fn show_directory_result(&mut self, result: Result<Option<PathBuf>>, select: Option<&str>){
+        let directory = match self.directory.as_ref(){
+            Some(directory) => directory,
+            None => return,
+        };
+        let row = self.cursor_pos.r;
+        self.buffer = directory.listing();
+        self.cursor_pos = Position{r: cmp::min(row, self.buffer.len().saturating_sub(1)), c: 0};
+        if let Some(name) = select{
+            self.select_entry(name);
+        }
+        match result{
+            Ok(Some(path)) => self.update_status(format!("Done: {}",path.display()).as_str()),
+            Ok(None) => self.update_status(""),
+            Err(e) => self.update_status(format!("Error: {}",e).as_str()),
+        }
+    }
+
+    /// Moves the cursor to the listing line for `name`, if there is one.
+    fn select_entry(&mut self, name: &str){
+        if let Some(row) = self.directory.as_ref().and_then(|directory| directory.row_of(name)){
+            self.cursor_pos = Position{r: row, c: 0};
+        }
+    }
+
+    /// The project holding the current file, or the working directory for buffers that
+    /// aren't files.
+    fn project_dir(&self) -> PathBuf{
+        let start = match &self.directory{
+            Some(directory) => directory.path().to_path_buf(),
+            None => match self.buffer.path().parent(){
+                Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+                _ => std::env::current_dir().unwrap_or_default(),
+            },
+        };
+        let start = start.canonicalize().unwrap_or(start);
+        project_root(&start)
+    }
+
+    fn open_finder(&mut self){
+        self.finder = Some(Finder::new(self.project_dir()));
+    }
+
+    /// Asks what to search the project for and shows the hits as they come in. An empty
+    /// answer brings back the hits of the last search.
+    fn prompt_grep(&mut self)-> Result<()>{
+        let pattern = self.prompt_about("Search project for: ", Topic::ProjectSearch)?;
+        if pattern.is_empty() && self.grep.is_none(){
+            return Ok(());
+        }
+        if self.is_modified() && !self.prompt_write()?{
+            return Ok(());
+        }
+        if !pattern.is_empty(){
+            self.grep = Some(Grep::new(self.project_dir(), &pattern));
+        }
+        self.show_hits();
+        Ok(())
+    }
+
+    /// Makes the hits of the last search the current buffer, one `path:line: text` per hit.
+    fn show_hits(&mut self){
+        let grep = match &self.grep{
+            Some(grep) => grep,
+            None => return,
+        };
+        let mut buffer = Buffer::from_text(&grep.header());
+        buffer.append_lines(grep.hits().iter().map(|hit| hit.line()));
+        buffer.name = String::from("*grep*");
+        buffer.read_only = true;
+        let current = grep.current();
+        self.switch_to(buffer);
+        self.showing_hits = true;
+        if let Some(current) = current{
+            self.cursor_pos.r = current + crate::grep::HEADER_LINES;
+        }
+    }
+
+    /// Takes in new hits, adding them to the results buffer if it is showing.
+    fn poll_grep(&mut self){
+        let grep = match &mut self.grep{
+            Some(grep) => grep,
+            None => return,
+        };
+        let new = match grep.poll(){
+            Some(new) => new,
+            None => return,
+        };
+        let hits = grep.hits();
+        if self.showing_hits{
+            self.buffer.append_lines(hits[hits.len() - new..].iter().map(|hit| hit.line()));
+        }
+        if !grep.running(){
+            let message = match hits.len(){
+                0 => format!("No hits for \"{}\".",grep.pattern()),
+                1 => String::from("1 hit."),
+                n => format!("{} hits.",n),
+            };
+            self.update_status(&message);
+        }
+        self.dirty = true;
+    }
+
+    /// Goes to the next (or previous) hit of the last search.
+    fn step_hit(&mut self, forward: bool)-> Result<()>{
+        match self.grep.as_ref().map(|grep| grep.step(forward)){
+            Some(Some(index)) => self.goto_hit(index),
+            Some(None) =>{
+                self.update_status(if forward{ "No more hits." }else{ "No earlier hits." });
+                Ok(())
+            },
+            None =>{
+                self.update_status("No search yet, Alt+G searches the project.");
+                Ok(())
+            },
+        }
+    }
+
+    /// Opens the file of hit `index` with the cursor on the match.
+    fn goto_hit(&mut self, index: usize)-> Result<()>{
+        let (path, pos, count) = match self.grep.as_mut(){
+            Some(grep) =>{
+                let root = grep.root().to_path_buf();
+                let count = grep.hits().len();
+                match grep.visit(index){
+                    Some(hit) => (root.join(&hit.path), Position{r: hit.line, c: hit.column}, count),
+                    None => return Ok(()),
+                }
+            },
+            None => return Ok(()),
+        };
+        if !same_file(self.buffer.path(), &path){
+            self.open_file(&path.to_string_lossy())?;
+            if !same_file(self.buffer.path(), &path){
+                return Ok(());
+            }
+        }
+        let r = cmp::min(pos.r, self.line_count().saturating_sub(1));
+        // The file may have changed since it was searched. Large files aren't in the
+        // buffer, so their lines can't be checked here.
+        let c = match self.buffer.get(r){
+            Some(line) if self.large.is_none() => char_start(line, pos.c),
+            _ => pos.c,
+        };
+        self.cursor_pos = Position{r, c};
+        self.goal_col = None;
+        self.update_status(format!("Hit {} of {}",index + 1,count).as_str());
+        Ok(())
+    }
+
+    /// Asks for a shell command and runs it in the project, showing what it writes as it
+    /// comes in. An empty answer brings back the output of the last command.
+    fn prompt_run(&mut self)-> Result<()>{
+        let command = self.prompt_about("Run command: ", Topic::Commands)?;
+        if command.trim().is_empty(){
+            if self.run.is_some(){
+                if self.is_modified() && !self.prompt_write()?{
+                    return Ok(());
                 }
+                self.show_output();
             }
-            self.draw_lines();
-            self.draw_status();
-            self.draw_accumulator += 1;
+            return Ok(());
+        }
+        if self.is_modified() && !self.prompt_write()?{
+            return Ok(());
         }
+        self.start_run(self.project_dir(), command.trim());
         Ok(())
     }
 
-    fn write_to_disk(&mut self){
-        let file_name: String = match self.buffer.name.as_str(){
-            "scratch" =>{
-                 self.prompt(&"File name to write:  ".blue())
+    /// Runs the last command again, in the directory it ran in before.
+    fn rerun(&mut self)-> Result<()>{
+        let (dir, command) = match &self.run{
+            Some(run) => (run.dir().to_path_buf(), run.command().to_string()),
+            None =>{
+                self.update_status("No command yet, Alt + ! runs one.");
+                return Ok(());
             },
-            _ => {self.buffer.name.clone()},
         };
-        let file_name = file_name.trim().to_string();
-        let message = self.buffer.write(Some(file_name.clone()));
-        self.update_status(message.unwrap_or("Error failed to write to disk!".into()).as_str());
-        self.write_status = true;
+        if self.is_modified() && !self.prompt_write()?{
+            return Ok(());
+        }
+        self.start_run(dir, &command);
+        Ok(())
     }
 
-    fn prompt_write(&mut self){
-        match self.prompt(format!("Open buffer {} contains data, write to disk (yes/no)? ",self.buffer.name).as_str()).trim().to_lowercase().as_str(){
-            "y" | "yes" => self.write_to_disk(),
-            "n" | "no"=> {
-                return
+    /// Stops the last command if it is still going and starts `command` in its place.
+    fn start_run(&mut self, dir: PathBuf, command: &str){
+        self.run = None;
+        self.quickfix = Some(Quickfix::new(dir.clone()));
+        self.run = Some(Run::new(dir, command));
+        self.show_output();
+        self.update_status(format!("Running {}",command).as_str());
+    }
+
+    /// Makes the output of the last command the current buffer, with the cursor on its
+    /// last line so that it follows the output as it comes in.
+    fn show_output(&mut self){
+        let run = match &self.run{
+            Some(run) => run,
+            None => return,
+        };
+        let mut buffer = Buffer::from_text(&run.header());
+        buffer.append_lines(run.lines().iter().cloned());
+        buffer.append_lines(run.outcome());
+        buffer.name = String::from("*output*");
+        buffer.read_only = true;
+        self.switch_to(buffer);
+        self.showing_output = true;
+        self.cursor_pos.r = self.buffer.len() - 1;
+    }
+
+    /// Takes in what the command wrote, adding it to the output buffer if it is showing.
+    /// A cursor on the last line stays there.
+    fn poll_run(&mut self){
+        let run = match &mut self.run{
+            Some(run) => run,
+            None => return,
+        };
+        let new = match run.poll(){
+            Some(new) => new,
+            None => return,
+        };
+        let lines = run.lines();
+        let outcome = run.outcome();
+        if let Some(quickfix) = &mut self.quickfix{
+            quickfix.extend(lines[lines.len() - new..].iter().map(String::as_str));
+            // The file being edited may have just gained errors to keep track of.
+            if !self.showing_output{
+                quickfix.watch(&mut self.buffer);
+            }
+        }
+        if self.showing_output{
+            let follow = self.cursor_pos.r + 1 >= self.buffer.len();
+            self.buffer.append_lines(lines[lines.len() - new..].iter().cloned());
+            self.buffer.append_lines(outcome.clone());
+            if follow{
+                self.cursor_pos = Position{r: self.buffer.len() - 1, c: 0};
+                self.goal_col = None;
+            }
+        }
+        if let Some(outcome) = outcome{
+            match self.quickfix.as_ref().map_or(0, |quickfix| quickfix.entries().len()){
+                0 => self.update_status(&outcome),
+                1 => self.update_status(format!("{} F8 goes to the place it named.",outcome).as_str()),
+                count => self.update_status(format!("{} F8 goes through the {} places it named.",outcome,count).as_str()),
+            }
+        }
+        self.dirty = true;
+    }
+
+    /// Opens the file named on the output line under the cursor, at the line and column
+    /// given with it. Stepping through the errors goes on from there.
+    fn goto_output_location(&mut self)-> Result<()>{
+        let row = self.cursor_pos.r.checked_sub(1);
+        if let Some(index) = self.quickfix.as_ref().zip(row).and_then(|(quickfix, row)| quickfix.entry_at(row)){
+            return self.goto_error(index);
+        }
+        let dir = match &self.run{
+            Some(run) => run.dir().to_path_buf(),
+            None => return Ok(()),
+        };
+        let location = match self.buffer.get(self.cursor_pos.r).and_then(Location::find){
+            Some(location) => location,
+            None =>{
+                self.update_status("No file:line on this line.");
+                return Ok(());
+            },
+        };
+        self.goto_location(&dir.join(&location.path), &location)?;
+        Ok(())
+    }
+
+    /// Opens the file at `path` with the cursor at `location` in it, and says whether it got
+    /// there.
+    fn goto_location(&mut self, path: &Path, location: &Location)-> Result<bool>{
+        if !path.is_file(){
+            self.update_status(format!("There is no file {}.",path.display()).as_str());
+            return Ok(false);
+        }
+        if !same_file(self.buffer.path(), path){
+            self.open_file(&path.to_string_lossy())?;
+            if !same_file(self.buffer.path(), path){
+                return Ok(false);
+            }
+        }
+        let r = cmp::min(location.line, self.line_count().saturating_sub(1));
+        let c = self.buffer.get(r).map_or(0, |line| location.offset_in(line));
+        self.cursor_pos = Position{r, c};
+        self.goal_col = None;
+        Ok(true)
+    }
+
+    /// Reads the places named in `output`, such as a compiler's errors or grep's matches,
+    /// with relative paths taken to be in `dir`, and goes to the first one.
+    pub fn load_errors(&mut self, output: &str, dir: impl AsRef<Path>)-> Result<()>{
+        let mut quickfix = Quickfix::new(dir.as_ref().to_path_buf());
+        quickfix.extend(output.lines());
+        self.quickfix = Some(quickfix);
+        self.watch_errors();
+        self.step_error(Direction::Forward)
+    }
+
+    /// Goes to the next (or previous) place named by the last command or loaded errors.
+    fn step_error(&mut self, direction: Direction)-> Result<()>{
+        match self.quickfix.as_ref().map(|quickfix| quickfix.step(direction)){
+            Some(Some(index)) => self.goto_error(index),
+            Some(None) =>{
+                let message = match (direction, self.quickfix.as_ref().is_some_and(|quickfix| quickfix.entries().is_empty())){
+                    (_, true) => "No errors to go through.",
+                    (Direction::Forward, false) => "No more errors.",
+                    (Direction::Backward, false) => "No earlier errors.",
+                };
+                self.update_status(message);
+                Ok(())
+            },
+            None =>{
+                self.update_status("No errors yet, Alt + ! runs a command.");
+                Ok(())
+            },
+        }
+    }
+
+    /// Opens the file of error `index` at its place, wherever edits have moved it since.
+    fn goto_error(&mut self, index: usize)-> Result<()>{
+        let quickfix = match &mut self.quickfix{
+            Some(quickfix) => quickfix,
+            None => return Ok(()),
+        };
+        quickfix.follow(&self.buffer);
+        let count = quickfix.entries().len();
+        let (path, location, message) = match quickfix.visit(index){
+            Some(entry) => (entry.path.clone(), entry.location.clone(), entry.message.clone()),
+            None => return Ok(()),
+        };
+        if self.goto_location(&path, &location)?{
+            self.update_status(format!("{} of {}: {}",index + 1,count,message).as_str());
+        }
+        Ok(())
+    }
+
+    /// Moves the errors of the current file along with edits from now on.
+    fn watch_errors(&mut self){
+        if let Some(quickfix) = &mut self.quickfix{
+            quickfix.watch(&mut self.buffer);
+        }
+    }
+
+    fn process_finder_key(&mut self, key_event: KeyEvent)-> Result<()>{
+        let finder = match &mut self.finder{
+            Some(finder) => finder,
+            None => return Ok(()),
+        };
+        match (key_event.modifiers,key_event.code){
+            (_,KeyCode::Esc) | (KeyModifiers::CONTROL,KeyCode::Char('g')) =>{
+                self.finder = None;
+            },
+            (_,KeyCode::Enter) =>{
+                let selection = finder.selection();
+                self.finder = None;
+                match selection{
+                    Some(path) => self.open_file(&path.to_string_lossy())?,
+                    None => self.update_status("No matching file."),
+                }
+            },
+            (_,KeyCode::Up) | (KeyModifiers::CONTROL,KeyCode::Char('p')) => finder.select(-1),
+            (_,KeyCode::Down) | (KeyModifiers::CONTROL,KeyCode::Char('n')) => finder.select(1),
+            (_,KeyCode::Backspace) => finder.pop(),
+            (modifiers,KeyCode::Char(c)) if (modifiers - KeyModifiers::SHIFT).is_empty() =>{
+                finder.push(c.encode_utf8(&mut [0;4]));
             },
             _ =>{},
+        }
+        Ok(())
+    }
 
-        }
+    /// Asks a yes/no question on the status line.
+    fn confirm(&mut self, question: &str)-> Result<bool>{
+        Ok(matches!(self.prompt(question)?.trim().to_lowercase().as_str(), "y" | "yes"))
     }
 
-    fn new_buffer(&mut self){
-        if self.write_status == false{
-            self.prompt_write();
-        }
-        // assuming that if the user answers no, all data is discarded.
-        self.buffer = Default::default();
-        self.cursor_pos = (0,0).into();
-        self.update_status("Created a new scratch buffer.");
-        self.write_status = true;
+    fn update_status(&mut self, message: &str){
+        self.status_message = message.into();
+        self.status_since = Instant::now();
     }
 
-    fn prompt(&mut self, message: &str) -> String{
-        self.update_status(message);
-        execute!(
-                stdout(),
-                cursor::MoveTo(0 as u16,self.window_size.rows),
-                crossterm::terminal::Clear(ClearType::CurrentLine),
-                Print(self.status_message.clone()),
-                cursor::MoveTo(message.len() as u16,self.window_size.rows),
-                ).ok();
-
-        let mut buf = String::new();
-        crossterm::terminal::disable_raw_mode().ok();
-        stdin().read_line(&mut buf).expect("red: error: failed to read in from stdin");
-        crossterm::terminal::enable_raw_mode().ok();
-        buf
-    }
-
-    pub fn open_file(&mut self, file_name: &str){
-        let file_name = file_name.replace("\"","");
-        if self.write_status ==false{
-            self.prompt_write();
+    fn process_keypress(&mut self, key_event: KeyEvent)-> Result<()>{
+        if self.process_messages_key(key_event){
+            return Ok(());
+        }
+        let last_action = std::mem::take(&mut self.last_action);
+        // Each command is undone on its own, except runs of typed characters.
+        let typing = matches!(key_event.code,KeyCode::Char(_)) && (key_event.modifiers - KeyModifiers::SHIFT).is_empty();
+        if !(typing && matches!(last_action,LastAction::Typing)){
+            self.buffer.checkpoint(self.cursor_pos);
         }
-        let new_buffer = Buffer::open(file_name.trim().as_ref());
-        if !new_buffer.1{
-            self.buffer = new_buffer.0;
-            self.cursor_pos = Default::default();
-            if !self.buffer.read_only{
-                self.update_status(format!("Successfully opened file {}",file_name).as_str());
+        if self.finder.is_some(){
+            return self.process_finder_key(key_event);
+        }
+        if self.popup.is_some() && self.process_popup_key(key_event){
+            return Ok(());
+        }
+        if self.process_directory_key(key_event)?{
+            return Ok(());
+        }
+        if self.viewer && self.process_pager_key(key_event)?{
+            // Paging moves by rows, which in the hex view start at a byte.
+            if let Some(hex) = &mut self.hex{
+                hex.move_to(self.cursor_pos.r * BYTES_PER_ROW);
+                self.cursor_pos = hex.position();
             }
+            return Ok(());
         }
-        else{
-            match self.prompt(format!("Failed to open file {}. Create a file with the same name? ",file_name).as_str()).trim().to_lowercase().as_str(){
-                "yes" | "y" =>{
-                    let file_name = file_name;
-                    self.buffer = new_buffer.0;
-                    self.buffer.write(None);
-                },
-                "n" | "no" =>{
-                    self.update_status("");
-                },
-                _ =>{},
+        if self.process_hex_key(key_event){
+            return Ok(());
+        }
+        if self.process_large_key(key_event){
+            return Ok(());
+        }
+        if self.showing_hits && key_event.code == KeyCode::Enter{
+            if let Some(index) = self.grep.as_ref().and_then(|grep| grep.hit_at(self.cursor_pos.r)){
+                self.goto_hit(index)?;
             }
+            return Ok(());
+        }
+        if self.showing_output && key_event.code == KeyCode::Enter{
+            self.goto_output_location()?;
+            return Ok(());
+        }
+        if self.process_git_message_key(key_event)?{
+            return Ok(());
+        }
+        if self.buffer.read_only && is_edit(&key_event){
+            self.update_status(if self.viewer{ VIEW_ONLY }else{ "Buffer is read-only." });
+            return Ok(());
         }
-        stdout().execute(crossterm::terminal::Clear(ClearType::All)).ok();
-    }
-
-
-    fn update_status(&mut self, message: &str){
-        self.status_message = message.into();
-        self.draw_accumulator = 0;
-    }
-
-    fn process_keypress(&mut self, key_event: KeyEvent){
         match (key_event.modifiers,key_event.code){
             (KeyModifiers::CONTROL,KeyCode::Char('q'))=>{
                 self.should_close = true;
             },
             (KeyModifiers::CONTROL,KeyCode::Char('w'))=> {
-                self.write_to_disk();
+                self.write_to_disk()?;
             },
             (KeyModifiers::CONTROL,KeyCode::Char('o'))=> {
-                let mut file_name = self.prompt("File to be opened: ");
-                file_name = file_name.trim().to_string();
-                self.open_file(file_name.as_str());
+                let file_name = self.prompt_about("File to be opened: ", Topic::Files)?;
+                if !file_name.trim().is_empty(){
+                    self.open_file(file_name.trim())?;
+                }
             },
             (KeyModifiers::CONTROL,KeyCode::Char('n'))=> {
-                self.new_buffer();
+                self.new_buffer()?;
+            },
+            (KeyModifiers::CONTROL,KeyCode::Char('p'))=> {
+                self.open_finder();
+            },
+            (KeyModifiers::ALT,KeyCode::Char('g'))=> {
+                self.prompt_grep()?;
+            },
+            (KeyModifiers::ALT,KeyCode::Char('n'))=> {
+                self.step_hit(true)?;
+            },
+            (KeyModifiers::ALT,KeyCode::Char('p'))=> {
+                self.step_hit(false)?;
             },
             (KeyModifiers::CONTROL,KeyCode::Char('j'))=> {
-                self.prompt_jump();
+                self.prompt_jump()?;
+            },
+            (modifiers,KeyCode::Char('|')) if modifiers.contains(KeyModifiers::ALT) => {
+                self.prompt_filter()?;
+            },
+            (modifiers,KeyCode::Char('!')) if modifiers.contains(KeyModifiers::ALT) => {
+                self.prompt_run()?;
+            },
+            (KeyModifiers::ALT,KeyCode::Down)=> {
+                self.goto_hunk(Direction::Forward);
+            },
+            (KeyModifiers::ALT,KeyCode::Up)=> {
+                self.goto_hunk(Direction::Backward);
+            },
+            (KeyModifiers::ALT,KeyCode::Char('r'))=> {
+                self.revert_hunk();
+            },
+            (KeyModifiers::ALT,KeyCode::Char('m'))=> {
+                self.prompt_set_mark()?;
+            },
+            (KeyModifiers::ALT,KeyCode::Char('j'))=> {
+                self.prompt_goto_mark()?;
+            },
+            (KeyModifiers::ALT,KeyCode::Char('k'))=> {
+                self.ask_server(LanguageServer::hover);
+            },
+            (KeyModifiers::ALT,KeyCode::Char('.'))=> {
+                self.ask_server(LanguageServer::definition);
+            },
+            (KeyModifiers::ALT,KeyCode::Char('/'))=> {
+                // The buffer's words stand in while there is no server to ask.
+                if self.server_ready(){
+                    self.ask_server(LanguageServer::completion);
+                }
+                else{
+                    self.show_completions(self.word_completions(self.word_start()));
+                }
+            },
+            (KeyModifiers::CONTROL,KeyCode::Char(' '))=>{
+                self.set_mark(self.cursor_pos);
+                self.update_status("Mark set.");
+            },
+            (_,KeyCode::Esc)=>{
+                self.mode = EditorMode::Edit;
+            },
+            (KeyModifiers::ALT,KeyCode::Char('w'))=>{
+                self.copy_region();
+            },
+            (KeyModifiers::CONTROL,KeyCode::Char('k'))=>{
+                match self.region(){
+                    Some((start,end)) => self.kill(start,end,Direction::Forward,false),
+                    None => self.kill_line(matches!(last_action,LastAction::Kill)),
+                }
+            },
+            (KeyModifiers::CONTROL,KeyCode::Char('y'))=>{
+                self.yank();
+            },
+            (KeyModifiers::ALT,KeyCode::Char('y'))=>{
+                self.yank_pop(last_action);
+            },
+            (KeyModifiers::CONTROL,KeyCode::Char('z'))=>{
+                match self.buffer.undo(self.cursor_pos){
+                    Some(pos) =>{
+                        self.cursor_pos = pos;
+                    },
+                    None => self.update_status("Nothing to undo."),
+                }
+            },
+            (KeyModifiers::ALT,KeyCode::Char('z'))=>{
+                match self.buffer.redo(self.cursor_pos){
+                    Some(pos) =>{
+                        self.cursor_pos = pos;
+                    },
+                    None => self.update_status("Nothing to redo."),
+                }
             },
             (KeyModifiers::CONTROL,KeyCode::Char('f'))=>{
-                let search_text = self.prompt("Find: ");
-                self.search(&search_text);
+                let search_text = self.prompt_about("Find: ", Topic::Search)?;
+                if !search_text.is_empty(){
+                    self.search(&search_text);
+                }
             }
             (_,KeyCode::F(num)) =>{
                 match num {
                     2=>{
-                        let path = std::env::current_exe().unwrap().into_os_string().into_string().unwrap();
-                        self.open_file((path +"/LICENSE").as_str());
+                        self.show_license()?;
                     },
                     4=>{
                         self.line_numbers = !self.line_numbers;
                     },
+                    5=>{
+                        self.rerun()?;
+                    },
+                    8 if key_event.modifiers.contains(KeyModifiers::SHIFT) =>{
+                        self.step_error(Direction::Backward)?;
+                    },
+                    8=>{
+                        self.step_error(Direction::Forward)?;
+                    },
+                    12=>{
+                        self.ask_server(LanguageServer::definition);
+                    },
                     _ => {},
                 }
             },
 
+            (KeyModifiers::CONTROL,KeyCode::Left)=>{
+                self.move_word(Direction::Backward);
+            },
+            (KeyModifiers::CONTROL,KeyCode::Right)=>{
+                self.move_word(Direction::Forward);
+            },
+            (KeyModifiers::CONTROL,KeyCode::Up)=>{
+                self.move_paragraph(Direction::Backward);
+            },
+            (KeyModifiers::CONTROL,KeyCode::Down)=>{
+                self.move_paragraph(Direction::Forward);
+            },
+            (KeyModifiers::CONTROL,KeyCode::Home)=>{
+                self.goal_col = None;
+                self.cursor_pos = Default::default();
+            },
+            (KeyModifiers::CONTROL,KeyCode::End)=>{
+                self.goal_col = None;
+                self.cursor_pos.r = self.buffer.len().saturating_sub(1);
+                self.move_cursor(KeyCode::End);
+            },
+            // Ctrl + H is what many terminals send for Backspace, so it isn't taken for words.
+            (KeyModifiers::CONTROL | KeyModifiers::ALT,KeyCode::Backspace)=>{
+                self.kill_word(Direction::Backward,matches!(last_action,LastAction::Kill));
+            },
+            (KeyModifiers::CONTROL,KeyCode::Delete)=>{
+                self.kill_word(Direction::Forward,matches!(last_action,LastAction::Kill));
+            },
             (_,KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right| KeyCode::Home | KeyCode::End) =>{
                 self.move_cursor(key_event.code);
             },
             (_,KeyCode::Delete) =>{
-                self.buffer.remove(self.cursor_pos,Direction::Forward,1);
+                self.mode = EditorMode::Edit;
+                self.buffer.remove(self.cursor_pos,Direction::Forward);
             },
-            (_,KeyCode::Backspace) =>{
+            (_,KeyCode::Backspace) | (KeyModifiers::CONTROL,KeyCode::Char('h')) =>{
+                self.mode = EditorMode::Edit;
                 let len = self.buffer.len();
-                self.buffer.remove(self.cursor_pos,Direction::Backward,1);
+                self.buffer.remove(self.cursor_pos,Direction::Backward);
                 if len  != self.buffer.len(){
                     self.move_cursor(KeyCode::Up);
+                    self.move_cursor(KeyCode::End);
                 }
                 else {
                     self.move_cursor(KeyCode::Left);
                 }
             },
             (_,KeyCode::Enter)=>{
-                if !self.buffer.read_only{
-                    self.write_status = false;
-                }
+                self.mode = EditorMode::Edit;
                 self.buffer.insert(self.cursor_pos,'\n');
                 self.move_cursor(KeyCode::Down);
+                self.move_cursor(KeyCode::Home);
             },
             (_,KeyCode::Char(c))=>{
-                if !self.buffer.read_only{
-                    self.write_status = false;
-                }                    self.buffer.insert(self.cursor_pos,c);
+                self.mode = EditorMode::Edit;
+                self.buffer.insert(self.cursor_pos,c);
                 self.move_cursor(KeyCode::Right);
+                self.last_action = LastAction::Typing;
             },
             (_,KeyCode::Tab)=>{
-                if !self.buffer.read_only{
-                    self.write_status = false;
-                }
+                self.mode = EditorMode::Edit;
                 self.buffer.insert(self.cursor_pos,'\t');
                 self.move_cursor(KeyCode::End);
             }
             _=>{},
         }
+        Ok(())
+    }
+
+    /// Builds the next frame and sends whatever changed since the last one to the terminal.
+    fn draw(&mut self)-> Result<()>{
+        let mut frame = Frame::new(self.window_size.cols,self.window_size.rows);
+        self.draw_lines(&mut frame);
+        // Popups go over the text, but never over the modeline or status line.
+        self.draw_popup(&mut frame);
+        self.draw_modeline(&mut frame);
+        self.draw_status(&mut frame);
+        frame.cursor = if let Some(finder) = &self.finder{
+            Self::draw_finder(finder,&mut frame)
+        }
+        else if self.prompting{
+            Some((self.status_message.chars().count() as u16,self.window_size.rows.saturating_sub(1)))
+        }
+        else{
+            self.screen_cursor()
+        };
+        self.backend.draw(frame).map_err(Error::Terminal)?;
+        Ok(())
+    }
+
+    fn draw_lines(&mut self, frame: &mut Frame){
+        let offset = self.scroll;
+        let region = self.region();
+        let count = self.line_count();
+        // Diagnostics are marked in a column of their own, left of the line numbers.
+        let signs = if self.document.is_some(){ 2 }else{ 0 };
+        let numbers = if self.line_numbers && count > 0{
+            ((((count - 1) as f32).log10()) as usize) + 2
+        }
+        else{
+            0
+        };
+        // Changes since the last commit are marked right of the line numbers.
+        let changes = if self.git.is_some(){ 2 }else{ 0 };
+        // Named marks come last, next to the text.
+        let named_marks: Vec<(char,Position)> = self.buffer.marks().collect();
+        let marks = if named_marks.is_empty(){ 0 }else{ 2 };
+        self.offset.c = signs + numbers + changes + marks;
+
+        if let Some(hex) = &self.hex{
+            self.offset.c = 0;
+            for i in 0..self.text_rows(){
+                if offset + i >= hex.buffer.rows(){
+                    break;
+                }
+                frame.put_str(0,i as u16,&hex.buffer.row(offset + i),Style::default());
+            }
+            // Mark the cursor's byte in the column it isn't in as well.
+            let row = hex.offset() / BYTES_PER_ROW;
+            if let Some(byte) = hex.buffer.get(hex.offset()).filter(|_| row >= offset && row < offset + self.text_rows()){
+                frame.put_str(hex.column(!hex.text()) as u16,(row - offset) as u16,&hex::counterpart(byte,hex.text()),Style::reversed());
+            }
+            return;
+        }
+
+        let gutter = self.offset.c as u16;
+        if let Some(large) = &mut self.large{
+            for i in 0..self.window_size.rows.saturating_sub(2) as usize{
+                let line = match large.line(offset + i){
+                    Some(line) => line,
+                    None => break,
+                };
+                if numbers > 0{
+                    let size = numbers - 1;
+                    frame.put_str(0,i as u16,&format!("{:>size$}",i + offset),Style::colors(Color::DarkYellow,Color::Black));
+                }
+                frame.put_str(gutter,i as u16,&line,Style::default());
+            }
+            return;
+        }
+
+        let diagnostics = self.diagnostics();
+        // The hunks are only worked out again once the buffer has changed.
+        if let Some(git) = &mut self.git{
+            git.update(&self.buffer);
+        }
+        let hunks = self.git.as_ref().map_or(&[][..], GitFile::hunks);
+        for i in 0..self.text_rows(){
+            let line = match self.buffer.get(offset + i){
+                Some(line) => line,
+                None => break,
+            };
+            let y = i as u16;
+            if numbers > 0{
+                let size = numbers - 1;
+                frame.put_str(signs as u16,y,&format!("{:>size$}",i + offset),Style::colors(Color::DarkYellow,Color::Black));
+            }
+            match region{
+                Some((start,end)) if start.r <= offset+i && offset+i <= end.r =>{
+                    let from = if start.r == offset+i {cmp::min(start.c,line.len())} else {0};
+                    let to = cmp::max(from,if end.r == offset+i {cmp::min(end.c,line.len())} else {line.len()});
+                    let x = frame.put_str(gutter,y,&line[..from],Style::default());
+                    let x = frame.put_str(x,y,&line[from..to],Style::reversed());
+                    frame.put_str(x,y,&line[to..],Style::default());
+                },
+                _ =>{
+                    frame.put_str(gutter,y,line,Style::default());
+                },
+            }
+            let row = offset + i;
+            if self.git_message.is_some(){
+                if git::is_comment(line){
+                    frame.restyle(gutter,y,line.chars().count() as u16,|style| Style{fg: Color::DarkGrey, ..style});
+                }
+                frame.restyle(gutter + git::RULER as u16,y,1,|style| Style{bg: Color::DarkGrey, ..style});
+            }
+            if let Some(hunk) = hunks.iter().find(|hunk| hunk.rows().contains(&row)){
+                let (sign, color) = change_sign(hunk.sign());
+                frame.put_str((signs + numbers) as u16,y,sign,Style::colors(color,Color::Reset));
+            }
+            if let Some((name,_)) = named_marks.iter().find(|(_,pos)| pos.r == row){
+                frame.put_str((signs + numbers + changes) as u16,y,&name.to_string(),Style::colors(Color::Magenta,Color::Reset));
+            }
+            if let Some(severity) = diagnostics.iter().filter(|d| d.start.r == row).map(|d| d.severity).min(){
+                let (sign, color) = severity_sign(severity);
+                frame.put_str(0,y,sign,Style::colors(color,Color::Reset));
+            }
+            for diagnostic in diagnostics.iter().filter(|d| d.start.r <= row && row <= d.end.r){
+                let from = if diagnostic.start.r == row {cmp::min(diagnostic.start.c,line.len())} else {0};
+                let to = if diagnostic.end.r == row {cmp::min(diagnostic.end.c,line.len())} else {line.len()};
+                let x = gutter + line[..from].chars().count() as u16;
+                // Empty ranges still get one cell, so there is something to see.
+                let width = cmp::max(line[from..cmp::max(from,to)].chars().count(),1) as u16;
+                let color = severity_sign(diagnostic.severity).1;
+                frame.restyle(x,y,width,|style| Style{fg: color, underline: true, ..style});
+            }
+        }
+    }
+
+    /// Draws the popup under the cursor, or above it when there is more room there.
+    fn draw_popup(&self, frame: &mut Frame){
+        let overlay = match &self.popup{
+            Some(Popup::Text(lines)) => Overlay{lines: lines.clone(), selected: None, max_rows: POPUP_ROWS},
+            Some(Popup::Completion{selected, ..}) =>{
+                let lines = self.matching_completions().iter()
+                    .map(|item| if item.detail.is_empty(){ item.label.clone() }else{ format!("{}  {}",item.label,item.detail) })
+                    .collect();
+                Overlay{lines, selected: Some(*selected), max_rows: POPUP_ROWS}
+            },
+            None => return,
+        };
+        let (mut x, y) = match self.screen_cursor(){
+            Some(cursor) => cursor,
+            None => return,
+        };
+        // Completions line up with the word they complete, past the popup's padding.
+        if let Some(Popup::Completion{start, ..}) = &self.popup{
+            x = (x as usize + start.c).saturating_sub(self.cursor_pos.c + 1) as u16;
+        }
+        frame.overlay(&overlay,x,y,self.text_rows() as u16);
     }
 
-    fn draw_lines(&mut self){
-        let mut stdout = stdout();
+    /// Number of screen rows available for text, leaving room for the modeline and status line.
+    fn text_rows(&self) -> usize{
+        self.window_size.rows.saturating_sub(2) as usize
+    }
 
-        execute!(stdout,
-                cursor::MoveTo(0,0),
-                cursor::Hide,
-                crossterm::terminal::Clear(ClearType::FromCursorDown),
-        ).ok();
+    /// Where the cursor is on screen, or `None` if it has been scrolled out of view.
+    fn screen_cursor(&self) -> Option<(u16,u16)>{
+        if self.cursor_pos.r < self.scroll || self.cursor_pos.r >= self.scroll + self.text_rows(){
+            return None;
+        }
+        let line = self.buffer.get(self.cursor_pos.r).unwrap_or("");
+        let chars = line.get(..self.cursor_pos.c).map_or(self.cursor_pos.c, |before| before.chars().count());
+        let column = cmp::min(chars + self.offset.c, u16::MAX as usize) as u16;
+        Some((column, (self.cursor_pos.r - self.scroll) as u16))
+    }
 
-        let offset = (self.cursor_pos.r/(self.window_size.rows-1))*(self.window_size.rows-1);
+    /// Scrolls a page at a time so the cursor stays on screen.
+    fn scroll_to_cursor(&mut self){
+        let rows = cmp::max(self.text_rows(),1);
+        if self.cursor_pos.r < self.scroll || self.cursor_pos.r >= self.scroll + rows{
+            self.scroll = (self.cursor_pos.r/rows)*rows;
+        }
+    }
 
-        for i in 0..self.window_size.rows as u16{
-            if ((offset + i) as usize) < self.buffer.len(){
-                let line = self.buffer.get((offset + i) as usize).unwrap();
-                if self.line_numbers {
-                    self.offset.c = ((((self.buffer.len() - 1) as f32).log10()) as u16) + 2;
-                    let size = self.offset.c as usize - 1;
-                    execute!(
-                        stdout,
-                        cursor::MoveTo(0,i),
-                        SetColors(Colors::new(Color::DarkYellow,Color::Black)),
+    /// Maps a screen cell to the buffer position under it, or `None` outside the text area.
+    fn screen_to_buffer(&self, column: u16, row: u16) -> Option<Position>{
+        if row as usize >= self.text_rows(){
+            return None;
+        }
+        if self.buffer.is_empty(){
+            return Some(Default::default());
+        }
+        let r = cmp::min(self.scroll + row as usize, self.buffer.len() -1);
+        let line = self.buffer.get(r).unwrap_or("");
+        // Each character takes one column, so count characters rather than bytes.
+        let column = (column as usize).saturating_sub(self.offset.c);
+        let c = line.char_indices().nth(column).map_or(line.len(), |(i, _)| i);
+        Some(Position{r, c})
+    }
 
-                        Print(match self.line_numbers{
-                            true => format!("{:>size$}",i + offset),
-                            false => String::default(),
-                        }),
-                        SetColors(Colors::new(Color::Reset,Color::Reset)),
-                        cursor::MoveTo(self.offset.c,i + self.offset.r),
-                    ).unwrap();
+    fn process_mouse(&mut self, mouse_event: MouseEvent){
+        // Large files are only ever partly in memory, so they are driven by keys alone, and
+        // so is the hex view.
+        if self.large.is_some() || self.hex.is_some(){
+            return;
+        }
+        match mouse_event.kind{
+            MouseEventKind::Down(MouseButton::Left) =>{
+                let pos = match self.screen_to_buffer(mouse_event.column,mouse_event.row){
+                    Some(pos) => pos,
+                    None => return,
+                };
+                let double_click = matches!(self.last_click,
+                    Some((time,last)) if time.elapsed() < DOUBLE_CLICK_TIME && last.r == pos.r && last.c == pos.c);
+                self.goal_col = None;
+                if double_click{
+                    let (start,end) = self.buffer.word_at(pos);
+                    self.set_mark(start);
+                    self.cursor_pos = end;
+                    self.last_click = None;
                 }
                 else{
-                    self.offset.c = 0;
-                    execute!(
-                        stdout,
-                        cursor::MoveTo(0,i + self.offset.r),
-                    ).unwrap();
+                    self.mode = EditorMode::Edit;
+                    self.cursor_pos = pos;
+                    self.last_click = Some((Instant::now(),pos));
                 }
-                if line.len() > self.window_size.cols as usize{
-                    // write!(stdout,"{}",line.as_str()[0..self.window_size.cols as usize]).ok();
+            },
+            MouseEventKind::Drag(MouseButton::Left) =>{
+                let row = cmp::min(mouse_event.row, self.text_rows().saturating_sub(1) as u16);
+                if let Some(pos) = self.screen_to_buffer(mouse_event.column,row){
+                    if self.mode != EditorMode::Mark{
+                        self.set_mark(self.cursor_pos);
+                    }
+                    self.cursor_pos = pos;
+                    self.goal_col = None;
+                    self.scroll_to_cursor();
                 }
-                else{
+            },
+            MouseEventKind::ScrollUp =>{
+                self.scroll = self.scroll.saturating_sub(WHEEL_LINES);
+            },
+            MouseEventKind::ScrollDown =>{
+                let max = self.buffer.len().saturating_sub(1);
+                self.scroll = cmp::min(self.scroll + WHEEL_LINES, max);
+            },
+            _ =>{},
+        }
+    }
 
-                    write!(stdout,"{}",line).ok();
-                }
-            }
+    fn set_mark(&mut self, pos: Position){
+        self.mode = EditorMode::Mark;
+        self.mark = pos;
+    }
 
+    /// The marked region in buffer order, if Mark mode is active.
+    fn region(&self) -> Option<(Position,Position)>{
+        if self.mode != EditorMode::Mark{
+            return None;
         }
-
-        self.draw_modeline();
-        stdout.queue(cursor::MoveTo(self.cursor_pos.c + self.offset.c,self.cursor_pos.r%(self.window_size.rows-1))).ok();
-        stdout.queue(cursor::Show).ok();
-        stdout.flush().ok();
+        let (mark,cursor) = (self.mark,self.cursor_pos);
+        if (mark.r,mark.c) <= (cursor.r,cursor.c) {Some((mark,cursor))} else {Some((cursor,mark))}
     }
 
-    fn draw_status(&mut self){
+    fn copy_region(&mut self){
+        if let Some((start,end)) = self.region(){
+            let text = self.buffer.text_range(start,end);
+            self.clipboard.copy(&text, &mut self.backend).ok();
+            self.kill_ring.push(text);
+            self.mode = EditorMode::Edit;
+            self.update_status("Copied region.");
+        }
+    }
 
-        execute!(stdout(),
-        cursor::MoveTo(0 as u16,self.window_size.rows),
-        crossterm::terminal::Clear(ClearType::CurrentLine),
-        Print(self.status_message.trim()),
-        cursor::MoveTo(self.cursor_pos.c + self.offset.c,self.cursor_pos.r%(self.window_size.rows-1)),
-        ).ok();
+    /// Draws the finder's matches over the bottom of the text and its pattern on the status
+    /// line. Returns where the cursor goes.
+    fn draw_finder(finder: &Finder, frame: &mut Frame)-> Option<(u16,u16)>{
+        let status_row = frame.rows().saturating_sub(1);
+        let rows = cmp::min(FINDER_ROWS, frame.rows().saturating_sub(2) as usize);
+        let rows = cmp::min(rows, finder.match_count());
+        let list_style = Style::colors(Color::White,Color::DarkGrey);
+        // The list sits right above the modeline.
+        let first_row = status_row.saturating_sub(1 + rows as u16);
+        // Keep the selection on screen when it is further down than the list is tall.
+        let skip = (finder.selected() + 1).saturating_sub(rows);
+        for (i,file) in finder.matches().skip(skip).take(rows).enumerate(){
+            let style = if skip + i == finder.selected(){ Style::reversed() }else{ list_style };
+            let y = first_row + i as u16;
+            let x = frame.put_str(0,y,&format!(" {}",file),style);
+            frame.fill(x,y,style);
+        }
+        let walking = if finder.walking(){ "..." }else{ "" };
+        let prompt = format!("Find file: {}",finder.pattern());
+        let x = frame.put_str(0,status_row,&prompt,Style::default());
+        let count = format!("  {}/{}{} in {}",finder.match_count(),finder.file_count(),walking,finder.root().display());
+        frame.put_str(x,status_row,&count,Style::colors(Color::DarkGrey,Color::Reset));
+        Some((x,status_row))
+    }
 
-        if self.draw_accumulator == 20{
-            self.status_message.truncate(0);
-            self.draw_accumulator = 0;
+    fn draw_status(&self, frame: &mut Frame){
+        let row = self.window_size.rows.saturating_sub(1);
+        // With nothing else to say, say what is wrong with the cursor's line.
+        if self.status_message.trim().is_empty(){
+            let cursor = self.cursor_pos.r;
+            if let Some(diagnostic) = self.diagnostics().into_iter().filter(|d| d.start.r == cursor).min_by_key(|d| d.severity){
+                let message = format!("{}: {}",diagnostic.severity.name(),diagnostic.message.lines().next().unwrap_or_default());
+                frame.put_str(0,row,&message,Style::colors(Color::DarkGrey,Color::Reset));
+                return;
+            }
         }
+        frame.put_str(0,row,self.status_message.trim(),Style::default());
     }
 
-    fn draw_modeline(&self){
+    fn draw_modeline(&self, frame: &mut Frame){
         let len = self.window_size.cols as usize;
-        let file_status_str = match self.write_status{
-            true =>{
+        let indexing = match &self.large{
+            Some(large) if large.indexing() => format!("indexing {}%",large.progress()),
+            _ => String::new(),
+        };
+        let file_status_str = match self.is_modified(){
+            false if !indexing.is_empty() => indexing.as_str(),
+            false =>{
                 if self.buffer.read_only{
                     "readonly"
                 }
@@ -344,118 +2505,362 @@ impl Editor{
                     ""
                 }
             },
-            false =>{
+            true =>{
                 "modified"
             },
         };
 
-        let bpos= if file_status_str.len() > 0{
-            len - (file_status_str.len()*4) +1
+        let bpos= if !file_status_str.is_empty(){
+            (len + 1).saturating_sub(file_status_str.len()*4)
         }
         else {
             0
         };
-        let modeline = format!("{:<20}{:>5}:{:<5}{:>bpos$}",self.buffer.name,
-                                                    self.cursor_pos.r,
-                                                    self.cursor_pos.c,
-                                                    file_status_str);
-        let modeline = format!("{:len$}",modeline);
-        execute!(
-                stdout(),
-        cursor::MoveTo(0,self.window_size.rows-2),
-        SetColors(Colors::new(Color::Black,Color::White)),
-        Print(modeline),
-        SetColors(Colors::new(Color::Reset,Color::Reset)),
-        ).unwrap();
-
+        // The hex view shows the cursor's byte offset instead of a line and column.
+        let position = match &self.hex{
+            Some(hex) => format!("{:>11}",format!("{:#x}",hex.offset())),
+            None => format!("{:>5}:{:<5}",self.cursor_pos.r,self.cursor_pos.c),
+        };
+        let name = match &self.branch{
+            Some(branch) => format!("{} [{}]",self.buffer.name,branch),
+            None => self.buffer.name.clone(),
+        };
+        let modeline = format!("{:<20}{}{:>bpos$}",name,position,file_status_str);
+        let row = self.window_size.rows.saturating_sub(2);
+        let style = Style::colors(Color::Black,Color::White);
+        let x = frame.put_str(0,row,&modeline,style);
+        frame.fill(x,row,style);
     }
 
-
     fn move_cursor(&mut self, code: KeyCode) {
-
-
         match code{
-            KeyCode::Up =>{
-                if self.cursor_pos.r != 0{
-                    self.cursor_pos.r = cmp::max(self.cursor_pos.r-1,0);
+            KeyCode::Up | KeyCode::Down =>{
+                if self.buffer.is_empty(){
+                    return
                 }
-                match self.buffer.get(self.cursor_pos.r.into()){
-                    Some(line)=>{
-                        self.cursor_pos.c = cmp::max(0,line.len() as u16)
-                    }
-                    None => self.cursor_pos.c =0
+                // Up/Down remember the column the vertical motion started from so that
+                // passing over shorter lines doesn't lose the cursor's place.
+                let goal = *self.goal_col.get_or_insert(self.cursor_pos.c);
+                if code == KeyCode::Up{
+                    self.cursor_pos.r = self.cursor_pos.r.saturating_sub(1);
                 }
-            },
-            KeyCode::Down =>{
-                if self.buffer.len() == 0{
-                    return
+                else{
+                    self.cursor_pos.r = cmp::min(self.cursor_pos.r+1,self.buffer.len() -1);
                 }
-                self.cursor_pos.r = cmp::min(self.cursor_pos.r+1,self.buffer.len() as u16 -1);
-                match self.buffer.get(self.cursor_pos.r.into()){
+                match self.buffer.get(self.cursor_pos.r){
                     Some(line)=>{
-                        self.cursor_pos.c = cmp::max(0,line.len() as u16)
+                        self.cursor_pos.c = char_start(line,goal)
                     }
                     None => self.cursor_pos.c =0
                 }
+                return;
             },
+            // Left and Right step over whole characters, however many bytes they take.
             KeyCode::Left =>{
-                if self.cursor_pos.c != 0{
-                    self.cursor_pos.c = cmp::max(self.cursor_pos.c-1,0);
-                }
+                let step = self.buffer.get(self.cursor_pos.r)
+                    .and_then(|line| line.get(..self.cursor_pos.c))
+                    .and_then(|before| before.chars().next_back())
+                    .map_or(1, char::len_utf8);
+                self.cursor_pos.c = self.cursor_pos.c.saturating_sub(step);
             },
             KeyCode::Right =>{
-                let line =self.buffer.get(self.cursor_pos.r as usize);
-                if line == None{
-                    return
-                };
-                let line = line.unwrap();
-                self.cursor_pos.c = cmp::min(self.cursor_pos.c+1, line.len()as u16);
+                if let Some(line) = self.buffer.get(self.cursor_pos.r){
+                    let step = line.get(self.cursor_pos.c..).and_then(|rest| rest.chars().next()).map_or(1, char::len_utf8);
+                    self.cursor_pos.c = cmp::min(self.cursor_pos.c+step, line.len());
+                }
             },
             KeyCode::Home =>{
-                let line =self.buffer.get(self.cursor_pos.r as usize);
-                if line == None{
-                    return
-                };
                 self.cursor_pos.c = 0;
             },
             KeyCode::End =>{
-                let line =self.buffer.get(self.cursor_pos.r as usize);
-                if line == None{
-                    return
-                };
-                self.cursor_pos.c = line.unwrap().len() as u16;
+                if let Some(line) = self.buffer.get(self.cursor_pos.r){
+                    self.cursor_pos.c = line.len();
+                }
             },
             _=>{},
         }
+        self.goal_col = None;
+    }
+
+    fn move_word(&mut self, direction: Direction){
+        self.goal_col = None;
+        self.cursor_pos = self.buffer.word_boundary(self.cursor_pos, direction);
+    }
+
+    fn move_paragraph(&mut self, direction: Direction){
+        self.goal_col = None;
+        self.cursor_pos.r = self.buffer.paragraph_boundary(self.cursor_pos.r, direction);
+        self.cursor_pos.c = 0;
+    }
+
+    fn kill_word(&mut self, direction: Direction, append: bool){
+        let boundary = self.buffer.word_boundary(self.cursor_pos, direction);
+        match direction{
+            Direction::Forward => self.kill(self.cursor_pos, boundary, direction, append),
+            Direction::Backward => self.kill(boundary, self.cursor_pos, direction, append),
+        }
+    }
+
+    fn kill_line(&mut self, append: bool){
+        let line_len = match self.buffer.get(self.cursor_pos.r){
+            Some(line) => line.len(),
+            None => return,
+        };
+        // At the end of a line the newline itself is killed, joining the next line.
+        let end = if self.cursor_pos.c >= line_len && self.cursor_pos.r + 1 < self.buffer.len(){
+            Position{r: self.cursor_pos.r+1, c: 0}
+        }
+        else{
+            Position{r: self.cursor_pos.r, c: line_len}
+        };
+        self.kill(self.cursor_pos, end, Direction::Forward, append);
+    }
+
+    /// Removes the text between `start` and `end` and saves it in the kill ring.
+    fn kill(&mut self, start: Position, end: Position, direction: Direction, append: bool){
+        if self.buffer.read_only{
+            return;
+        }
+        let text = self.buffer.text_range(start, end);
+        if append{
+            self.kill_ring.append(&text, direction == Direction::Forward);
+        }
+        else{
+            self.kill_ring.push(text);
+        }
+        if let Some(killed) = self.kill_ring.current(){
+            self.clipboard.copy(killed, &mut self.backend).ok();
+        }
+        self.buffer.remove_range(start, end);
+        self.cursor_pos = start;
+        self.goal_col = None;
+        self.mode = EditorMode::Edit;
+        self.last_action = LastAction::Kill;
+    }
+
+    /// Inserts text delivered by the terminal's bracketed paste as a single edit.
+    fn paste(&mut self, text: &str){
+        if self.hex.is_some(){
+            self.update_status("Bytes can only be overwritten in a binary file.");
+            return;
+        }
+        if self.buffer.read_only{
+            self.update_status(if self.viewer{ VIEW_ONLY }else{ "Buffer is read-only." });
+            return;
+        }
+        let text = text.replace("\r\n","\n").replace('\r',"\n");
+        self.buffer.checkpoint(self.cursor_pos);
+        self.cursor_pos = self.buffer.insert_str(self.cursor_pos, &text);
+        self.goal_col = None;
+        self.last_action = LastAction::Other;
+    }
+
+    fn yank(&mut self){
+        // Text copied in another program takes precedence over the last kill. The clipboard
+        // also holds red's own kills, which are in the ring already and stay where they are.
+        if let Some(pasted) = self.clipboard.paste(){
+            if !pasted.is_empty() && !self.kill_ring.entries().any(|kill| kill == pasted){
+                self.kill_ring.push(pasted);
+            }
+        }
+        let text = match self.kill_ring.current(){
+            Some(text) => text.to_string(),
+            None =>{
+                self.update_status("Kill ring is empty.");
+                return;
+            },
+        };
+        self.insert_yanked(&text);
+    }
+
+    fn yank_pop(&mut self, last_action: LastAction){
+        let (start, end) = match last_action{
+            LastAction::Yank(start, end) => (start, end),
+            _ =>{
+                self.update_status("Previous command was not a yank.");
+                return;
+            },
+        };
+        let text = match self.kill_ring.rotate(){
+            Some(text) => text.to_string(),
+            None => return,
+        };
+        self.buffer.remove_range(start, end);
+        self.cursor_pos = start;
+        self.insert_yanked(&text);
+    }
+
+    fn insert_yanked(&mut self, text: &str){
+        if self.buffer.read_only{
+            return;
+        }
+        let start = self.cursor_pos;
+        self.cursor_pos = self.buffer.insert_str(start, text);
+        self.goal_col = None;
+        self.last_action = LastAction::Yank(start, self.cursor_pos);
     }
 
-    fn search(&mut self, s: &String){
-        let line = self.buffer.find(s);
-        if line.is_ok(){
+    fn search(&mut self, s: &str){
+        if self.large.is_some(){
+            self.search_large(s, self.cursor_pos);
+            return;
+        }
+        if let Some(pos) = self.buffer.find(s){
             self.status_message = "found string ".to_string();
-            let line = line.unwrap();
-            let substr = self.buffer.get(line).unwrap();
-            if let Some(index) = substr.find(s) {
-                self.cursor_pos.r = line as u16;
-                self.cursor_pos.c = index as u16;
+            self.cursor_pos = pos;
+        }
+    }
+
+    /// Large files are searched onwards from `from`, reading as little as possible.
+    fn search_large(&mut self, pattern: &str, from: Position){
+        if let Some(large) = &mut self.large{
+            match large.find(pattern, from){
+                Some(pos) =>{
+                    self.cursor_pos = pos;
+                    self.goal_col = None;
+                },
+                None if large.indexing() => self.update_status("Not found in the part indexed so far."),
+                None => self.update_status("Not found."),
+            }
+        }
+    }
+
+
+    /// Asks for a letter and puts the mark of that name at the cursor. Capital letters
+    /// name marks that can be gone to from any file, so there is one of each.
+    fn prompt_set_mark(&mut self)-> Result<()>{
+        if self.large.is_some() || self.hex.is_some(){
+            self.update_status("Marks can only be set in text.");
+            return Ok(());
+        }
+        let name = match self.read_letter("Set mark (a-z in this file, A-Z across files): ")?{
+            Some(name) => name,
+            None => return Ok(()),
+        };
+        if name.is_ascii_uppercase(){
+            if !self.buffer.path().is_file(){
+                self.update_status("Marks across files need a file; write this one first.");
+                return Ok(());
+            }
+            self.session.forget_mark(name);
+        }
+        self.buffer.set_mark(name,self.cursor_pos);
+        self.update_status(format!("Mark {} set.",name).as_str());
+        Ok(())
+    }
+
+    /// Asks for a letter and goes to the mark of that name, opening its file first for a
+    /// mark set in another one.
+    fn prompt_goto_mark(&mut self)-> Result<()>{
+        let name = match self.read_letter("Go to mark: ")?{
+            Some(name) => name,
+            None => return Ok(()),
+        };
+        if self.buffer.mark(name).is_none() && name.is_ascii_uppercase(){
+            if let Some(path) = self.session.place_with_mark(name).map(|place| place.path.clone()){
+                self.open_file(&path.to_string_lossy())?;
+                if !same_file(self.buffer.path(), &path){
+                    return Ok(());
+                }
             }
         }
+        match self.buffer.mark(name).filter(|_| self.large.is_none() && self.hex.is_none()){
+            Some(pos) =>{
+                let r = cmp::min(pos.r,self.buffer.len().saturating_sub(1));
+                let line = self.buffer.get(r).unwrap_or_default();
+                let mut c = cmp::min(pos.c,line.len());
+                while !line.is_char_boundary(c){
+                    c -= 1;
+                }
+                self.cursor_pos = Position{r, c};
+                self.goal_col = None;
+            },
+            None => self.update_status(format!("No mark {}.",name).as_str()),
+        }
+        Ok(())
     }
 
+    /// Shows `message` and waits for a key, giving back the letter typed. Any other key,
+    /// like Esc, gives `None`.
+    fn read_letter(&mut self, message: &str)-> Result<Option<char>>{
+        self.prompting = true;
+        self.update_status(message);
+        let result = loop{
+            if let Err(e) = self.draw(){
+                break Err(e);
+            }
+            match self.backend.poll_event(Duration::from_millis(500)){
+                Ok(None) | Ok(Some(Event::Key(KeyEvent{kind: KeyEventKind::Release, ..}))) =>{},
+                Ok(Some(Event::Key(KeyEvent{code: KeyCode::Char(c), modifiers, ..}))) if (modifiers - KeyModifiers::SHIFT).is_empty() =>{
+                    break Ok(Some(c).filter(char::is_ascii_alphabetic));
+                },
+                Ok(Some(Event::Key(_))) => break Ok(None),
+                Ok(Some(Event::Resize(width,height))) =>{
+                    self.window_size.resize((width,height));
+                    self.backend.invalidate();
+                },
+                Ok(Some(_)) =>{},
+                Err(e) => break Err(Error::Terminal(e)),
+            }
+        };
+        self.prompting = false;
+        self.update_status("");
+        self.dirty = true;
+        result
+    }
 
-    fn prompt_jump(&mut self) {
-        let result = self.prompt("Line to jump to: ");
+    fn prompt_jump(&mut self)-> Result<()>{
+        let result = self.prompt_about("Line to jump to: ", Topic::Lines)?;
         let result = result.trim();
-        let mut res_i  = result.parse::<u16>().unwrap_or(self.cursor_pos.r);
-        if res_i > (self.buffer.len()-1) as u16{
+        let mut res_i  = result.parse::<usize>().unwrap_or(self.cursor_pos.r);
+        if res_i > self.line_count().saturating_sub(1){
             res_i = self.cursor_pos.r
         }
         self.cursor_pos.r = res_i;
+        if self.large.is_some(){
+            self.cursor_pos.c = 0;
+        }
+        // The hex view's rows start at a byte, which is where its cursor really is.
+        if let Some(hex) = &mut self.hex{
+            hex.move_to(res_i * BYTES_PER_ROW);
+            self.cursor_pos = hex.position();
+        }
+        Ok(())
+    }
+}
+
+/// Whether `a` and `b` name the same existing file.
+fn same_file(a: &Path, b: &Path) -> bool{
+    match (a.canonicalize(), b.canonicalize()){
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// The gutter mark for diagnostics of `severity`, and their color.
+fn severity_sign(severity: Severity) -> (&'static str,Color){
+    match severity{
+        Severity::Error => ("E",Color::Red),
+        Severity::Warning => ("W",Color::Yellow),
+        Severity::Information => ("I",Color::Blue),
+        Severity::Hint => ("H",Color::DarkGrey),
+    }
+}
+
+/// How the lines of a change since the last commit are marked in the gutter.
+fn change_sign(sign: Sign) -> (&'static str,Color){
+    match sign{
+        Sign::Added => ("+",Color::Green),
+        Sign::Modified => ("~",Color::Blue),
+        Sign::Deleted => ("_",Color::Red),
     }
 }
 
-pub fn cleanup(){
-    execute!(stdout(),
-    crossterm::terminal::LeaveAlternateScreen
-    ).expect("red: error: failed to enter alternate screen");
+/// Whether `key_event` would change the buffer.
+fn is_edit(key_event: &KeyEvent) -> bool{
+    matches!((key_event.modifiers - KeyModifiers::SHIFT,key_event.code),
+        (_,KeyCode::Enter | KeyCode::Tab | KeyCode::Backspace | KeyCode::Delete) |
+        (KeyModifiers::NONE,KeyCode::Char(_)) |
+        (KeyModifiers::CONTROL,KeyCode::Char('k' | 'y' | 'z' | 'h')) |
+        (KeyModifiers::ALT,KeyCode::Char('y' | 'z' | '/' | 'r' | '|')))
 }
@@ -0,0 +1,1562 @@
+use super::*;
+use crate::clipboard::{ClipboardBackend,Osc52,Register};
+use crate::terminal::HeadlessBackend;
+use crossterm::event::KeyEventState;
+
+fn ctrl(c: char) -> KeyEvent{
+    KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL)
+}
+
+fn headless() -> HeadlessBackend{
+    HeadlessBackend::new(40, 12)
+}
+
+fn editor(backend: HeadlessBackend) -> Editor<HeadlessBackend>{
+    Editor::with_backend(backend, Clipboard::new(vec![Box::<Register>::default()]))
+}
+
+/// Runs the script in `backend` to completion and hands back the editor.
+fn run(backend: HeadlessBackend) -> Editor<HeadlessBackend>{
+    finish(editor(backend))
+}
+
+fn finish(mut editor: Editor<HeadlessBackend>) -> Editor<HeadlessBackend>{
+    match editor.run(){
+        Err(Error::Terminal(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof =>{},
+        result => result.unwrap(),
+    }
+    editor
+}
+
+fn lines(editor: &Editor<HeadlessBackend>) -> Vec<String>{
+    (0..editor.buffer.len()).map(|i| editor.buffer.get(i).unwrap().to_string()).collect()
+}
+
+fn mouse(kind: MouseEventKind, column: u16, row: u16) -> Event{
+    Event::Mouse(MouseEvent{kind, column, row, modifiers: KeyModifiers::NONE})
+}
+
+#[test]
+fn typed_text_is_drawn_with_the_modeline(){
+    let editor = run(headless().keys("hello\nworld"));
+    let screen = editor.backend.screen();
+    assert_eq!(screen[0], "hello");
+    assert_eq!(screen[1], "world");
+    assert!(screen[10].starts_with("scratch"));
+    assert!(screen[10].ends_with("modified"));
+    assert_eq!(editor.backend.cursor(), Some((5,1)));
+}
+
+#[test]
+fn enter_splits_the_line_and_backspace_on_an_empty_line_joins_back(){
+    let editor = run(headless()
+        .keys("abcd")
+        .key(KeyCode::Left, KeyModifiers::NONE)
+        .key(KeyCode::Left, KeyModifiers::NONE)
+        .keys("\n"));
+    assert_eq!(lines(&editor), vec!["ab", "cd"]);
+    assert_eq!(editor.backend.cursor(), Some((0,1)));
+
+    let editor = run(headless()
+        .keys("ab\n")
+        .key(KeyCode::Backspace, KeyModifiers::NONE)
+        .keys("c"));
+    assert_eq!(lines(&editor), vec!["abc"]);
+}
+
+#[test]
+fn word_motion_and_word_deletion(){
+    let editor = run(headless()
+        .keys("one two three")
+        .key(KeyCode::Home, KeyModifiers::NONE)
+        .key(KeyCode::Right, KeyModifiers::CONTROL)
+        .key(KeyCode::Right, KeyModifiers::CONTROL)
+        .keys("!")
+        .key(KeyCode::Backspace, KeyModifiers::CONTROL));
+    assert_eq!(lines(&editor), vec!["one  three"]);
+
+    let editor = run(headless()
+        .keys("one two three")
+        .key(KeyCode::Home, KeyModifiers::NONE)
+        .key(KeyCode::Delete, KeyModifiers::CONTROL));
+    assert_eq!(lines(&editor), vec![" two three"]);
+
+    // Ctrl + H is Backspace in many terminals, so it deletes one character.
+    let editor = run(headless().keys("one two").event(Event::Key(ctrl('h'))));
+    assert_eq!(lines(&editor), vec!["one tw"]);
+    let editor = run(headless().keys("one two").key(KeyCode::Backspace, KeyModifiers::ALT));
+    assert_eq!(lines(&editor), vec!["one "]);
+}
+
+#[test]
+fn vertical_motion_keeps_the_goal_column(){
+    let editor = run(headless()
+        .keys("long line here\nab\nanother long line")
+        .key(KeyCode::Home, KeyModifiers::CONTROL)
+        .key(KeyCode::End, KeyModifiers::NONE)
+        .key(KeyCode::Left, KeyModifiers::NONE)
+        .key(KeyCode::Down, KeyModifiers::NONE)
+        .key(KeyCode::Down, KeyModifiers::NONE));
+    assert_eq!(editor.backend.cursor(), Some((13,2)));
+}
+
+#[test]
+fn the_cursor_moves_over_whole_characters(){
+    let editor = run(headless()
+        .keys("ab\néx")
+        .key(KeyCode::Up, KeyModifiers::NONE)
+        .key(KeyCode::Home, KeyModifiers::NONE)
+        .key(KeyCode::Right, KeyModifiers::NONE)
+        .key(KeyCode::Down, KeyModifiers::NONE)
+        .keys("!"));
+    assert_eq!(lines(&editor), vec!["ab", "!éx"]);
+
+    let editor = run(headless()
+        .keys("aé€b")
+        .key(KeyCode::Left, KeyModifiers::NONE)
+        .key(KeyCode::Left, KeyModifiers::NONE)
+        .keys("1")
+        .key(KeyCode::Home, KeyModifiers::NONE)
+        .key(KeyCode::Right, KeyModifiers::NONE)
+        .key(KeyCode::Right, KeyModifiers::NONE)
+        .keys("2"));
+    assert_eq!(lines(&editor), vec!["aé21€b"]);
+    assert_eq!(editor.backend.cursor(), Some((3,0)));
+}
+
+#[test]
+fn paragraph_and_buffer_jumps(){
+    let editor = run(headless()
+        .keys("a\nb\n\nc\nd")
+        .key(KeyCode::Home, KeyModifiers::CONTROL)
+        .key(KeyCode::Down, KeyModifiers::CONTROL));
+    assert_eq!(editor.backend.cursor(), Some((0,2)));
+
+    let editor = run(headless()
+        .keys("a\nb\n\nc\nd")
+        .key(KeyCode::Up, KeyModifiers::CONTROL));
+    assert_eq!(editor.backend.cursor(), Some((0,2)));
+
+    let editor = run(headless()
+        .keys("a\nbcd")
+        .key(KeyCode::Home, KeyModifiers::CONTROL)
+        .key(KeyCode::End, KeyModifiers::CONTROL));
+    assert_eq!(editor.backend.cursor(), Some((3,1)));
+}
+
+#[test]
+fn consecutive_kills_append_and_yank_inserts_them(){
+    let editor = run(headless()
+        .keys("first\nsecond\nthird")
+        .key(KeyCode::Home, KeyModifiers::CONTROL)
+        .event(Event::Key(ctrl('k')))
+        .event(Event::Key(ctrl('k')))
+        .event(Event::Key(ctrl('k')))
+        .key(KeyCode::End, KeyModifiers::CONTROL)
+        .keys("\n")
+        .event(Event::Key(ctrl('y'))));
+    assert_eq!(lines(&editor), vec!["", "third", "first", "second"]);
+}
+
+#[test]
+fn yank_pop_cycles_to_older_kills(){
+    let editor = run(headless()
+        .keys("aaa bbb")
+        .key(KeyCode::Backspace, KeyModifiers::CONTROL)
+        .key(KeyCode::Left, KeyModifiers::NONE)
+        .key(KeyCode::Backspace, KeyModifiers::CONTROL)
+        .event(Event::Key(ctrl('y')))
+        .event(Event::Key(KeyEvent::new(KeyCode::Char('y'), KeyModifiers::ALT))));
+    assert_eq!(lines(&editor), vec!["bbb "]);
+}
+
+#[test]
+fn yank_pop_wraps_around_and_only_follows_a_yank(){
+    let editor = run(headless().event(Event::Key(ctrl('y'))));
+    assert_eq!(editor.backend.screen()[11], "Kill ring is empty.");
+
+    let editor = run(headless()
+        .keys("aaa bbb")
+        .key(KeyCode::Backspace, KeyModifiers::CONTROL)
+        .key(KeyCode::Backspace, KeyModifiers::CONTROL)
+        .keys("x")
+        .key(KeyCode::Backspace, KeyModifiers::CONTROL)
+        .event(Event::Key(ctrl('y')))
+        .event(Event::Key(alt('y')))
+        .event(Event::Key(alt('y'))));
+    assert_eq!(lines(&editor), vec!["x"]);
+
+    let editor = run(headless()
+        .keys("aaa")
+        .key(KeyCode::Backspace, KeyModifiers::CONTROL)
+        .keys("b")
+        .event(Event::Key(alt('y'))));
+    assert_eq!(lines(&editor), vec!["b"]);
+    assert_eq!(editor.backend.screen()[11], "Previous command was not a yank.");
+
+    // Yanking after yank-pop inserts the kill it came to without adding it again.
+    let editor = run(headless()
+        .keys("one ")
+        .key(KeyCode::Backspace, KeyModifiers::CONTROL)
+        .keys("x")
+        .key(KeyCode::Backspace, KeyModifiers::CONTROL)
+        .event(Event::Key(ctrl('y')))
+        .event(Event::Key(alt('y')))
+        .keys(" ")
+        .event(Event::Key(ctrl('y'))));
+    assert_eq!(lines(&editor), vec!["one  one "]);
+    assert_eq!(editor.kill_ring.entries().collect::<Vec<_>>(), ["x", "one "]);
+}
+
+/// Stands in for the desktop clipboard; clones share what it holds.
+#[derive(Clone, Default)]
+struct SystemClipboard(std::rc::Rc<std::cell::RefCell<Option<String>>>);
+
+impl ClipboardBackend for SystemClipboard{
+    fn copy(&mut self, text: &str, _terminal: &mut dyn Backend) -> std::io::Result<()>{
+        *self.0.borrow_mut() = Some(text.to_string());
+        Ok(())
+    }
+
+    fn paste(&mut self) -> std::io::Result<Option<String>>{
+        Ok(self.0.borrow().clone())
+    }
+}
+
+fn with_clipboard(system: &SystemClipboard, backend: HeadlessBackend) -> Editor<HeadlessBackend>{
+    let clipboard = Clipboard::new(vec![Box::new(Osc52), Box::new(system.clone()), Box::<Register>::default()]);
+    finish(Editor::with_backend(backend, clipboard))
+}
+
+#[test]
+fn kills_reach_the_system_clipboard_and_the_terminal(){
+    let system = SystemClipboard::default();
+    let editor = with_clipboard(&system, headless()
+        .keys("gone\nkept")
+        .key(KeyCode::Home, KeyModifiers::CONTROL)
+        .event(Event::Key(ctrl('k'))));
+    assert_eq!(lines(&editor), vec!["", "kept"]);
+    assert_eq!(system.0.borrow().as_deref(), Some("gone"));
+    assert_eq!(editor.backend.escapes(), ["\x1b]52;c;Z29uZQ==\x07"]);
+}
+
+#[test]
+fn ctrl_y_pastes_what_another_program_copied(){
+    let system = SystemClipboard::default();
+    *system.0.borrow_mut() = Some("from elsewhere".to_string());
+    let editor = with_clipboard(&system, headless()
+        .keys("> ")
+        .event(Event::Key(ctrl('y'))));
+    assert_eq!(lines(&editor), vec!["> from elsewhere"]);
+}
+
+#[test]
+fn undo_reverts_a_run_of_typing_and_redo_restores_it(){
+    let editor = run(headless()
+        .keys("hello")
+        .key(KeyCode::Enter, KeyModifiers::NONE)
+        .keys("world")
+        .event(Event::Key(ctrl('z'))));
+    assert_eq!(lines(&editor), vec!["hello", ""]);
+
+    let editor = run(headless()
+        .keys("hello world")
+        .event(Event::Key(ctrl('z')))
+        .event(Event::Key(KeyEvent::new(KeyCode::Char('z'), KeyModifiers::ALT))));
+    assert_eq!(lines(&editor), vec!["hello world"]);
+}
+
+#[test]
+fn undoing_back_to_the_saved_text_leaves_the_buffer_unmodified(){
+    let dir = temp_dir("undo-saved", &["notes.txt"]);
+    let path = dir.join("notes.txt");
+    let modified = |editor: &Editor<HeadlessBackend>| editor.backend.screen()[10].contains("modified");
+
+    let editor = run(open(&path).keys("a").event(Event::Key(ctrl('z'))));
+    assert!(!modified(&editor));
+
+    let editor = run(open(&path)
+        .keys("a")
+        .event(Event::Key(ctrl('w')))
+        .keys("b")
+        .event(Event::Key(ctrl('z'))));
+    assert_eq!(lines(&editor), vec!["anotes.txt"]);
+    assert!(!modified(&editor));
+
+    let editor = run(open(&path)
+        .keys("c")
+        .event(Event::Key(ctrl('w')))
+        .event(Event::Key(ctrl('z'))));
+    assert!(modified(&editor));
+
+    let editor = run(open(&path)
+        .keys("d")
+        .event(Event::Key(ctrl('w')))
+        .event(Event::Key(ctrl('z')))
+        .event(Event::Key(alt('z'))));
+    assert!(!modified(&editor));
+
+    // Typing after an undo drops the redo that led back to the saved text.
+    let editor = run(open(&path)
+        .keys("e")
+        .event(Event::Key(ctrl('w')))
+        .event(Event::Key(ctrl('z')))
+        .keys("f")
+        .event(Event::Key(ctrl('z'))));
+    assert!(modified(&editor));
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn bracketed_paste_is_one_undoable_edit(){
+    let editor = run(headless()
+        .keys("<>")
+        .key(KeyCode::Left, KeyModifiers::NONE)
+        .event(Event::Paste("one\r\ntwo\r\nthree".into())));
+    assert_eq!(lines(&editor), vec!["<one", "two", "three>"]);
+    assert_eq!(editor.backend.cursor(), Some((5,2)));
+
+    let editor = run(headless()
+        .keys("<>")
+        .key(KeyCode::Left, KeyModifiers::NONE)
+        .event(Event::Paste("one\ntwo\nthree".into()))
+        .event(Event::Key(ctrl('z'))));
+    assert_eq!(lines(&editor), vec!["<>"]);
+}
+
+#[test]
+fn click_places_the_cursor_past_the_line_number_gutter(){
+    let editor = run(headless()
+        .keys("0\n1\n2\n3\n4\n5\n6\n7\n8\n9\nten and more")
+        .key(KeyCode::F(4), KeyModifiers::NONE)
+        .key(KeyCode::Home, KeyModifiers::CONTROL)
+        .event(mouse(MouseEventKind::Down(MouseButton::Left), 5, 0)));
+    let screen = editor.backend.screen();
+    assert_eq!(screen[0], " 0 0");
+    assert_eq!(editor.cursor_pos.r, 0);
+    assert_eq!(editor.cursor_pos.c, 1);
+}
+
+#[test]
+fn clicks_on_a_line_with_accented_letters_land_between_characters(){
+    let editor = run(headless()
+        .event(Event::Paste("héllo wörld".into()))
+        .event(mouse(MouseEventKind::Down(MouseButton::Left), 4, 0))
+        .keys("X"));
+    assert_eq!(lines(&editor), vec!["héllXo wörld"]);
+    assert_eq!(editor.backend.cursor(), Some((5,0)));
+
+    let editor = run(headless()
+        .event(Event::Paste("héllo".into()))
+        .event(mouse(MouseEventKind::Down(MouseButton::Left), 30, 0))
+        .keys("!"));
+    assert_eq!(lines(&editor), vec!["héllo!"]);
+}
+
+#[test]
+fn wheel_scrolls_without_moving_the_cursor(){
+    let text: String = (0..30).map(|i| format!("line {}\n",i)).collect();
+    let editor = run(headless()
+        .event(Event::Paste(text))
+        .key(KeyCode::Home, KeyModifiers::CONTROL)
+        .event(mouse(MouseEventKind::ScrollDown, 0, 0))
+        .event(mouse(MouseEventKind::ScrollDown, 0, 0)));
+    assert_eq!(editor.backend.screen()[0], "line 6");
+    assert_eq!(editor.cursor_pos.r, 0);
+    assert_eq!(editor.backend.cursor(), None);
+}
+
+#[test]
+fn drag_selects_a_region_that_can_be_copied_and_yanked(){
+    let editor = run(headless()
+        .keys("copy this text")
+        .event(mouse(MouseEventKind::Down(MouseButton::Left), 5, 0))
+        .event(mouse(MouseEventKind::Drag(MouseButton::Left), 9, 0))
+        .event(Event::Key(KeyEvent::new(KeyCode::Char('w'), KeyModifiers::ALT)))
+        .key(KeyCode::End, KeyModifiers::NONE)
+        .keys(" ")
+        .event(Event::Key(ctrl('y'))));
+    assert_eq!(lines(&editor), vec!["copy this text this"]);
+}
+
+#[test]
+fn double_click_selects_a_word(){
+    let editor = run(headless()
+        .keys("alpha beta gamma")
+        .event(mouse(MouseEventKind::Down(MouseButton::Left), 7, 0))
+        .event(mouse(MouseEventKind::Down(MouseButton::Left), 7, 0))
+        .event(Event::Key(ctrl('k'))));
+    assert_eq!(lines(&editor), vec!["alpha  gamma"]);
+}
+
+#[test]
+fn jump_to_line_reads_the_prompt_from_key_events(){
+    let editor = run(headless()
+        .keys("a\nb\nc\nd")
+        .event(Event::Key(ctrl('j')))
+        .keys("1\n"));
+    assert_eq!(editor.cursor_pos.r, 1);
+}
+
+#[test]
+fn escape_cancels_a_prompt(){
+    let editor = run(headless()
+        .keys("a\nb\nc")
+        .event(Event::Key(ctrl('j')))
+        .keys("0")
+        .key(KeyCode::Esc, KeyModifiers::NONE));
+    assert_eq!(editor.cursor_pos.r, 2);
+}
+
+#[test]
+fn find_moves_to_the_match(){
+    let editor = run(headless()
+        .keys("one\ntwo needle\nthree")
+        .event(Event::Key(ctrl('f')))
+        .keys("needle\n"));
+    assert_eq!((editor.cursor_pos.r, editor.cursor_pos.c), (1,4));
+}
+
+#[test]
+fn writing_the_scratch_buffer_prompts_for_a_file_name(){
+    let path = std::env::temp_dir().join(format!("red-test-{}.txt", std::process::id()));
+    let editor = run(headless()
+        .keys("saved text")
+        .event(Event::Key(ctrl('w')))
+        .keys(&format!("{}\n", path.display())));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "saved text\n");
+    assert!(!editor.backend.screen()[10].contains("modified"));
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn quitting_a_modified_buffer_asks_first(){
+    let dir = temp_dir("quit-asks", &["file.txt"]);
+    let path = dir.join("file.txt");
+    let quit = |answer: &str| run(open(&path).keys("new ").event(Event::Key(ctrl('q'))).keys(answer));
+
+    let editor = run(HeadlessBackend::new(80, 12)
+        .event(Event::Key(ctrl('o')))
+        .keys(&format!("{}\nnew ", path.display()))
+        .event(Event::Key(ctrl('q'))));
+    assert_eq!(editor.backend.screen()[11], "Open buffer file.txt contains data, write to disk (yes/no/cancel)?");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "file.txt\n");
+
+    let editor = quit("no\n");
+    assert!(!editor.aborted());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "file.txt\n");
+
+    let editor = quit("cancel\n");
+    assert!(!editor.aborted());
+    assert!(!editor.should_close);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "file.txt\n");
+
+    let editor = quit("yes\n");
+    assert!(!editor.aborted());
+    assert!(!editor.is_modified());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "new file.txt\n");
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn leaving_a_modified_buffer_needs_it_written_or_dropped(){
+    let dir = temp_dir("leave-asks", &["other.txt"]);
+    let missing = dir.join("missing").join("file.txt");
+    let asked = || headless()
+        .keys("text")
+        .event(Event::Key(ctrl('o')))
+        .keys(&format!("{}\n", dir.join("other.txt").display()));
+    let leave = |backend: HeadlessBackend|{
+        let mut editor = editor(backend);
+        editor.open_buffer(Buffer::with_path(&missing));
+        finish(editor)
+    };
+
+    // The write fails, so the text stays where it is along with the reason.
+    let editor = leave(asked().keys("yes\n"));
+    assert_eq!(lines(&editor), ["text"]);
+    assert_eq!(editor.buffer.path(), missing);
+    assert!(editor.status_message.starts_with("Error: failed to write to disk"));
+
+    for answered in [asked().keys("cancel\n"), asked().key(KeyCode::Esc, KeyModifiers::NONE)]{
+        let editor = leave(answered);
+        assert_eq!(lines(&editor), ["text"]);
+        assert!(editor.is_modified());
+    }
+
+    let editor = leave(asked().keys("no\n"));
+    assert_eq!(lines(&editor), ["other.txt"]);
+
+    let editor = run(headless().keys("text").event(Event::Key(ctrl('n'))).keys("cancel\n"));
+    assert_eq!(lines(&editor), ["text"]);
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn key_release_events_do_not_insert_text(){
+    let release = KeyEvent{code: KeyCode::Char('x'), modifiers: KeyModifiers::NONE, kind: KeyEventKind::Release, state: KeyEventState::NONE};
+    let editor = run(headless().keys("a").event(Event::Key(release)));
+    assert_eq!(lines(&editor), vec!["a"]);
+}
+
+#[test]
+fn a_failed_write_is_reported_and_leaves_the_buffer_modified(){
+    let path = std::env::temp_dir().join(format!("red-test-{}-missing", std::process::id())).join("file.txt");
+    let editor = run(headless()
+        .keys("text")
+        .event(Event::Key(ctrl('w')))
+        .keys(&format!("{}\n", path.display())));
+    let screen = editor.backend.screen();
+    assert!(screen[11].starts_with("Error: failed to write to disk"));
+    assert!(screen[10].contains("modified"));
+}
+
+#[test]
+fn f2_shows_the_license_read_only(){
+    let editor = run(headless().key(KeyCode::F(2), KeyModifiers::NONE).keys("x"));
+    assert_eq!(editor.buffer.name, "LICENSE");
+    assert!(editor.buffer.read_only);
+    assert_eq!(editor.buffer.get(0), LICENSE.lines().next());
+}
+
+#[test]
+fn jumping_in_an_empty_buffer_does_not_panic(){
+    let editor = run(headless().event(Event::Key(ctrl('j'))).keys("5\n"));
+    assert_eq!(editor.cursor_pos, Position{r: 0, c: 0});
+}
+
+/// A fresh directory under the system temp dir holding `files`, each containing its own name.
+fn temp_dir(name: &str, files: &[&str]) -> std::path::PathBuf{
+    let dir = std::env::temp_dir().join(format!("red-test-{}-{}", std::process::id(), name));
+    std::fs::remove_dir_all(&dir).ok();
+    std::fs::create_dir_all(&dir).unwrap();
+    for file in files{
+        std::fs::write(dir.join(file), format!("{}\n", file)).unwrap();
+    }
+    dir
+}
+
+fn open(path: &std::path::Path) -> HeadlessBackend{
+    headless().event(Event::Key(ctrl('o'))).keys(&format!("{}\n", path.display()))
+}
+
+#[test]
+fn opening_a_directory_lists_it_and_enter_opens_a_file(){
+    let dir = temp_dir("browse", &["a.txt", "b.txt"]);
+    std::fs::create_dir(dir.join("sub")).unwrap();
+    let editor = run(open(&dir));
+    let screen = editor.backend.screen();
+    assert!(screen[1].ends_with(" ../"));
+    assert!(screen[2].ends_with(" sub/"));
+    assert!(screen[3].ends_with("6  a.txt"));
+    assert_eq!(editor.cursor_pos.r, 2);
+    assert!(editor.buffer.read_only);
+
+    let editor = run(open(&dir)
+        .key(KeyCode::Down, KeyModifiers::NONE)
+        .key(KeyCode::Down, KeyModifiers::NONE)
+        .keys("x\n"));
+    std::fs::remove_dir_all(&dir).ok();
+    assert_eq!(editor.buffer.name, "b.txt");
+    assert_eq!(lines(&editor), vec!["b.txt"]);
+    assert!(editor.directory.is_none());
+}
+
+#[test]
+fn backspace_in_a_listing_goes_to_the_parent_directory(){
+    let dir = temp_dir("parent", &[]);
+    std::fs::create_dir(dir.join("inner")).unwrap();
+    let editor = run(open(&dir.join("inner")).key(KeyCode::Backspace, KeyModifiers::NONE));
+    std::fs::remove_dir_all(&dir).ok();
+    assert!(editor.buffer.name.ends_with("parent/"));
+    assert!(editor.buffer.get(editor.cursor_pos.r).unwrap().ends_with("inner/"));
+}
+
+#[test]
+fn files_are_created_renamed_and_deleted_from_a_listing(){
+    let dir = temp_dir("manage", &["old.txt", "keep.txt"]);
+    let editor = run(open(&dir).keys("cnew.txt\n"));
+    assert!(dir.join("new.txt").is_file());
+    assert!(editor.buffer.get(editor.cursor_pos.r).unwrap().ends_with("new.txt"));
+
+    run(open(&dir).key(KeyCode::Down, KeyModifiers::NONE).key(KeyCode::Down, KeyModifiers::NONE).keys("rrenamed.txt\n"));
+    assert!(!dir.join("old.txt").exists());
+    assert_eq!(std::fs::read_to_string(dir.join("renamed.txt")).unwrap(), "old.txt\n");
+
+    run(open(&dir).keys("dno\n"));
+    assert!(dir.join("keep.txt").exists());
+    let editor = run(open(&dir).keys("dyes\n"));
+    assert!(!dir.join("keep.txt").exists());
+    assert!(editor.status_message.starts_with("Done: "));
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+/// Lets the finder's walk run for a while.
+fn idle(backend: HeadlessBackend, polls: usize) -> HeadlessBackend{
+    (0..polls).fold(backend, |backend, _| backend.idle())
+}
+
+#[test]
+fn the_finder_skips_ignored_files_and_opens_the_best_match(){
+    let dir = temp_dir("finder", &["readme.md", "notes.log", ".gitignore"]);
+    std::fs::create_dir_all(dir.join(".git")).unwrap();
+    std::fs::write(dir.join(".gitignore"), "*.log\n/target/\n").unwrap();
+    for file in ["src/main.rs", "src/editor/mod.rs", "target/main.rs", "docs/target/main.txt"]{
+        let path = dir.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, format!("{}\n", file)).unwrap();
+    }
+    let backend = idle(open(&dir.join("readme.md")).event(Event::Key(ctrl('p'))), 20);
+    let editor = run(backend.keys("log"));
+    let finder = editor.finder.as_ref().unwrap();
+    assert!(!finder.walking());
+    assert_eq!(finder.file_count(), 5);
+    assert_eq!(finder.match_count(), 0);
+
+    let backend = idle(open(&dir.join("readme.md")).event(Event::Key(ctrl('p'))), 20);
+    let editor = run(backend.keys("main"));
+    let finder = editor.finder.as_ref().unwrap();
+    assert_eq!(finder.matches().collect::<Vec<_>>(), vec!["src/main.rs", "docs/target/main.txt"]);
+    assert!(editor.backend.screen()[11].starts_with("Find file: main  2/5 in /"));
+    assert_eq!(editor.backend.screen()[8], " src/main.rs");
+    assert_eq!(editor.backend.cursor(), Some((15,11)));
+
+    let backend = idle(open(&dir.join("readme.md")).event(Event::Key(ctrl('p'))), 20);
+    let editor = run(backend.keys("edmod").key(KeyCode::Enter, KeyModifiers::NONE));
+    std::fs::remove_dir_all(&dir).ok();
+    assert!(editor.finder.is_none());
+    assert_eq!(lines(&editor), vec!["src/editor/mod.rs"]);
+}
+
+fn alt(c: char) -> KeyEvent{
+    KeyEvent::new(KeyCode::Char(c), KeyModifiers::ALT)
+}
+
+#[test]
+fn project_search_lists_hits_and_steps_through_them(){
+    let dir = temp_dir("grep", &["b.txt", "skipped.log", ".gitignore"]);
+    std::fs::create_dir_all(dir.join(".git")).unwrap();
+    std::fs::write(dir.join(".gitignore"), "*.log\n").unwrap();
+    std::fs::write(dir.join("a.txt"), "one\nsome Needle here\nthree\nneedle again\n").unwrap();
+    std::fs::write(dir.join("skipped.log"), "needle\n").unwrap();
+    std::fs::write(dir.join("b.txt"), "no match\nthe needle\n").unwrap();
+    // Binary files are left out even when the NUL comes after a match.
+    std::fs::write(dir.join("c.bin"), "the needle\n\0 later\n").unwrap();
+    let search = |backend: HeadlessBackend| idle(backend.event(Event::Key(alt('g'))).keys("needle\n"), 20);
+
+    let editor = run(search(open(&dir.join("b.txt"))));
+    assert_eq!(editor.buffer.name, "*grep*");
+    assert_eq!(lines(&editor)[1..], ["a.txt:2: some Needle here", "a.txt:4: needle again", "b.txt:2: the needle"]);
+    assert_eq!(editor.status_message, "3 hits.");
+
+    // Enter on a hit opens its file at the match.
+    let editor = run(search(open(&dir.join("b.txt"))).key(KeyCode::Down, KeyModifiers::NONE).key(KeyCode::Down, KeyModifiers::NONE).key(KeyCode::Enter, KeyModifiers::NONE));
+    assert_eq!(editor.buffer.name, "a.txt");
+    assert_eq!(editor.cursor_pos, Position{r: 3, c: 0});
+
+    // Next and previous work from the file buffers.
+    let editor = run(search(open(&dir.join("b.txt")))
+        .event(Event::Key(alt('n')))
+        .event(Event::Key(alt('n')))
+        .event(Event::Key(alt('n')))
+        .event(Event::Key(alt('n'))));
+    assert_eq!(editor.buffer.name, "b.txt");
+    assert_eq!(editor.cursor_pos, Position{r: 1, c: 4});
+    assert_eq!(editor.status_message, "No more hits.");
+    let editor = run(search(open(&dir.join("b.txt"))).event(Event::Key(alt('n'))).event(Event::Key(alt('n'))).event(Event::Key(alt('p'))));
+    std::fs::remove_dir_all(&dir).ok();
+    assert_eq!(editor.cursor_pos, Position{r: 1, c: 5});
+    assert_eq!(editor.status_message, "Hit 1 of 3");
+}
+
+#[test]
+fn hits_on_lines_edited_since_the_search_land_inside_the_line(){
+    let dir = temp_dir("grep-edited", &[]);
+    std::fs::create_dir_all(dir.join(".git")).unwrap();
+    std::fs::write(dir.join("a.txt"), "xx needle\nneedle\n").unwrap();
+    let editor = run(idle(open(&dir.join("a.txt")).event(Event::Key(alt('g'))).keys("needle\n"), 20)
+        .event(Event::Key(alt('n')))
+        .key(KeyCode::Home, KeyModifiers::NONE)
+        .event(Event::Key(ctrl('k')))
+        .event(Event::Paste("éé".into()))
+        .event(Event::Key(alt('n')))
+        .event(Event::Key(alt('p')))
+        .keys("!"));
+    std::fs::remove_dir_all(&dir).ok();
+    assert_eq!(lines(&editor), vec!["é!é", "needle"]);
+}
+
+#[test]
+fn large_files_are_viewed_through_an_index(){
+    let dir = temp_dir("large", &[]);
+    let path = dir.join("big.log");
+    let text: String = (0..20000).map(|i| format!("line {}\n", i)).collect();
+    std::fs::write(&path, &text).unwrap();
+    let large = |backend: HeadlessBackend|{
+        let mut editor = editor(backend);
+        editor.large_file_size = 1000;
+        editor
+    };
+    let script = || idle(open(&path), 5);
+
+    let editor = finish(large(script().key(KeyCode::Down, KeyModifiers::NONE).key(KeyCode::End, KeyModifiers::NONE)));
+    let screen = editor.backend.screen();
+    assert_eq!(screen[0], "line 0");
+    assert_eq!(screen[9], "line 9");
+    assert!(screen[10].starts_with("big.log"));
+    assert!(screen[10].ends_with("readonly"));
+    assert_eq!(editor.cursor_pos, Position{r: 1, c: 6});
+    assert!(editor.buffer.is_empty());
+
+    let editor = finish(large(script().event(Event::Key(ctrl('j'))).keys("12345\n")));
+    assert_eq!(editor.backend.screen()[5], "line 12345");
+    assert_eq!(editor.cursor_pos, Position{r: 12345, c: 0});
+
+    let editor = finish(large(script().event(Event::Key(ctrl('f'))).keys("line 19999\n").key(KeyCode::End, KeyModifiers::NONE)));
+    assert_eq!(editor.cursor_pos, Position{r: 19999, c: 10});
+    let editor = finish(large(script().key(KeyCode::End, KeyModifiers::NONE).event(Event::Key(ctrl('f'))).keys("7\n")));
+    assert_eq!(editor.cursor_pos, Position{r: 7, c: 5});
+    // A match right at the cursor is found too.
+    let editor = finish(large(script().event(Event::Key(ctrl('f'))).keys("line 0\n")));
+    assert_eq!(editor.cursor_pos, Position{r: 0, c: 0});
+    assert_ne!(editor.status_message, "Not found.");
+    // Searching again moves past the match at the cursor.
+    let mut viewer = large(script().keys("/line 1\nn"));
+    viewer.set_viewer(true);
+    let editor = finish(viewer);
+    assert_eq!(editor.cursor_pos, Position{r: 10, c: 0});
+
+    let editor = finish(large(script().keys("typed").event(Event::Key(ctrl('w')))));
+    assert_eq!(editor.status_message, "Large files are read-only.");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), text);
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+fn viewer(backend: HeadlessBackend, text: &str) -> Editor<HeadlessBackend>{
+    let mut editor = editor(backend);
+    editor.set_viewer(true);
+    editor.open_buffer(Buffer::from_text(text));
+    finish(editor)
+}
+
+#[test]
+fn the_viewer_refuses_every_edit(){
+    let editor = viewer(headless()
+        .keys("x")
+        .key(KeyCode::Delete, KeyModifiers::NONE)
+        .key(KeyCode::Backspace, KeyModifiers::NONE)
+        .event(Event::Key(ctrl('k')))
+        .event(Event::Paste(String::from("pasted")))
+        .event(Event::Key(ctrl('w'))), "one\ntwo");
+    assert_eq!(lines(&editor), vec!["one", "two"]);
+    assert!(!editor.buffer.is_modified());
+    assert_eq!(editor.status_message, VIEW_ONLY);
+    assert!(editor.backend.screen()[10].ends_with("readonly"));
+}
+
+#[test]
+fn the_viewer_pages_and_searches_like_less(){
+    let text: String = (0..50).map(|i| format!("row {}\n", i)).collect();
+    let editor = viewer(headless().keys("  b"), &text);
+    assert_eq!(editor.cursor_pos.r, 10);
+    assert_eq!(editor.backend.screen()[0], "row 10");
+
+    let editor = viewer(headless().keys("G"), &text);
+    assert_eq!(editor.cursor_pos.r, 49);
+    let editor = viewer(headless().keys("Gg"), &text);
+    assert_eq!(editor.cursor_pos.r, 0);
+
+    let editor = viewer(headless().keys("/row 4\nn"), &text);
+    assert_eq!(editor.cursor_pos, Position{r: 40, c: 0});
+    let editor = viewer(headless().keys("/7\nnnnnn"), &text);
+    assert_eq!(editor.cursor_pos, Position{r: 47, c: 5});
+    assert_eq!(editor.status_message, "Pattern not found: 7");
+}
+
+#[test]
+fn q_quits_the_viewer(){
+    let mut editor = editor(headless().keys("q"));
+    editor.set_viewer(true);
+    editor.run().unwrap();
+}
+
+#[test]
+fn read_only_buffers_refuse_edits_outside_the_viewer_too(){
+    let editor = run(headless()
+        .key(KeyCode::F(2), KeyModifiers::NONE)
+        .key(KeyCode::Delete, KeyModifiers::NONE)
+        .key(KeyCode::Enter, KeyModifiers::NONE));
+    assert_eq!(editor.buffer.get(0), LICENSE.lines().next());
+    assert_eq!(editor.buffer.len(), LICENSE.lines().count());
+    assert_eq!(editor.status_message, "Buffer is read-only.");
+}
+
+fn open_wide(path: &std::path::Path) -> HeadlessBackend{
+    HeadlessBackend::new(80, 12).event(Event::Key(ctrl('o'))).keys(&format!("{}\n", path.display()))
+}
+
+#[test]
+fn binary_files_open_in_a_hex_view(){
+    let dir = temp_dir("hex", &[]);
+    let path = dir.join("data.bin");
+    let bytes: Vec<u8> = (0u32..40).map(|i| (i * 7) as u8).collect();
+    std::fs::write(&path, &bytes).unwrap();
+
+    let editor = run(open_wide(&path).key(KeyCode::Down, KeyModifiers::NONE).key(KeyCode::Right, KeyModifiers::NONE));
+    let screen = editor.backend.screen();
+    assert_eq!(screen[0], "00000000  00 07 0e 15 1c 23 2a 31  38 3f 46 4d 54 5b 62 69  |.....#*18?FMT[bi|");
+    assert_eq!(screen[2], "00000020  e0 e7 ee f5 fc 03 0a 11                           |........|");
+    assert!(screen[10].starts_with("data.bin"));
+    assert!(screen[10].contains("0x11"));
+    assert_eq!(editor.backend.cursor(), Some((13,1)));
+    assert!(editor.buffer.is_empty());
+
+    // Jumping to a row puts the cursor on its first byte, where typing then goes.
+    let editor = run(open_wide(&path).event(Event::Key(ctrl('j'))).keys("2\nff"));
+    assert_eq!(editor.backend.cursor(), Some((13,2)));
+    assert!(editor.backend.screen()[2].starts_with("00000020  ff e7"));
+
+    // Pastes don't go anywhere while the bytes are showing.
+    let editor = run(open_wide(&path).event(Event::Paste("pasted".to_string())));
+    assert_eq!(editor.status_message, "Bytes can only be overwritten in a binary file.");
+    assert!(editor.buffer.is_empty());
+    assert!(!editor.is_modified());
+
+    // Files that aren't UTF-8 are binary too, even without a NUL byte.
+    let latin1 = dir.join("latin1.txt");
+    std::fs::write(&latin1, b"caf\xe9\n").unwrap();
+    let editor = run(open_wide(&latin1));
+    assert_eq!(editor.backend.screen()[0], "00000000  63 61 66 e9 0a                                    |caf..|");
+    assert_eq!(editor.buffer.name, "latin1.txt");
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn overwritten_bytes_are_written_back_exactly(){
+    let dir = temp_dir("hex-write", &[]);
+    let path = dir.join("data.bin");
+    let mut bytes: Vec<u8> = (0..=255).collect();
+    bytes.extend(b"\r\n\0\xff");
+    std::fs::write(&path, &bytes).unwrap();
+
+    let editor = run(open_wide(&path)
+        .keys("ab")
+        .key(KeyCode::Tab, KeyModifiers::NONE)
+        .keys("Z")
+        .key(KeyCode::End, KeyModifiers::CONTROL)
+        .key(KeyCode::Tab, KeyModifiers::NONE)
+        .keys("4")
+        .event(Event::Key(ctrl('w'))));
+    assert_eq!(editor.status_message, "Wrote 260 bytes to disk.");
+    bytes[0] = 0xab;
+    bytes[1] = b'Z';
+    bytes[259] = 0x4f;
+    assert_eq!(std::fs::read(&path).unwrap(), bytes);
+
+    let editor = run(open_wide(&path).keys("g").key(KeyCode::Enter, KeyModifiers::NONE));
+    assert_eq!(editor.status_message, "Bytes can only be overwritten in a binary file.");
+    assert!(!editor.is_modified());
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn the_viewer_pages_through_binary_files_without_changing_them(){
+    let bytes: Vec<u8> = (0..=255).collect();
+    let mut editor = editor(HeadlessBackend::new(80, 12).keys(" 0"));
+    editor.set_viewer(true);
+    editor.open_hex(HexBuffer::from_bytes(&bytes));
+    let editor = finish(editor);
+    assert_eq!(editor.hex.as_ref().unwrap().offset(), 10 * BYTES_PER_ROW);
+    assert_eq!(editor.status_message, VIEW_ONLY);
+    assert!(!editor.is_modified());
+}
+
+/// The stand-in language server in `examples/mock_lsp.rs`, which `cargo test` builds
+/// along with the tests.
+fn mock_server() -> Vec<String>{
+    let exe = std::env::current_exe().unwrap();
+    let server = exe.parent().unwrap().parent().unwrap().join("examples").join("mock_lsp");
+    assert!(server.exists(), "{} is missing, build it with `cargo build --examples`", server.display());
+    vec![server.to_string_lossy().into_owned()]
+}
+
+/// Runs the script in `backend` on `path` with the mock language server for `.rs` files.
+fn with_server(path: &std::path::Path, backend: HeadlessBackend) -> Editor<HeadlessBackend>{
+    let mut editor = editor(backend);
+    editor.set_language_server("rs", mock_server());
+    editor.open_file(&path.to_string_lossy()).unwrap();
+    finish(editor)
+}
+
+/// Runs the script in `backend` on an editor that has already run one.
+fn then(mut editor: Editor<HeadlessBackend>, backend: HeadlessBackend) -> Editor<HeadlessBackend>{
+    editor.backend.extend(backend);
+    finish(editor)
+}
+
+/// Lets the editor take in what its language servers send until `done` holds, failing the
+/// test if that takes more than a few seconds.
+fn wait_until(mut editor: Editor<HeadlessBackend>, done: impl Fn(&Editor<HeadlessBackend>) -> bool) -> Editor<HeadlessBackend>{
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    while !done(&editor){
+        assert!(std::time::Instant::now() < deadline, "timed out waiting for the language server");
+        editor = then(editor, headless().idle());
+    }
+    // The poll that took the last message in may not have drawn it yet.
+    then(editor, headless().idle())
+}
+
+/// Whether every language server is up and has answered everything it was asked.
+fn answered(editor: &Editor<HeadlessBackend>) -> bool{
+    !editor.servers.is_empty() && editor.servers.values().all(|server| server.ready() && !server.busy())
+}
+
+/// The cells of row `y` that are underlined.
+fn underlined(editor: &Editor<HeadlessBackend>, y: u16) -> String{
+    let frame = editor.backend.frame().unwrap();
+    (0..frame.cols()).filter_map(|x| frame.cell(x, y)).filter(|cell| cell.style.underline).map(|cell| cell.ch).collect()
+}
+
+#[test]
+fn diagnostics_mark_the_gutter_and_underline_the_range(){
+    let dir = temp_dir("lsp-diagnostics", &[]);
+    let path = dir.join("main.rs");
+    std::fs::write(&path, "fn main() {\n    let é = bad;\n}\n").unwrap();
+    let opened = || wait_until(with_server(&path, headless()), |editor| !editor.diagnostics().is_empty());
+    let editor = opened();
+    let screen = editor.backend.screen();
+    assert_eq!(screen[0], "  fn main() {");
+    assert_eq!(screen[1], "E     let é = bad;");
+    assert_eq!(underlined(&editor, 1), "bad");
+    assert_eq!(editor.diagnostics()[0].start, Position{r: 1, c: 13});
+
+    // Edits reach the server as edits: its copy of the file follows along.
+    let editor = wait_until(then(opened(), headless()
+        .key(KeyCode::Down, KeyModifiers::NONE)
+        .key(KeyCode::End, KeyModifiers::NONE)
+        .key(KeyCode::Backspace, KeyModifiers::NONE)
+        .keys(" // meh")
+        .key(KeyCode::Home, KeyModifiers::CONTROL)
+        .keys("meh")), |editor| editor.diagnostics().len() == 3);
+    let screen = editor.backend.screen();
+    assert_eq!(screen[0], "W mehfn main() {");
+    assert_eq!(screen[1], "E     let é = bad // meh");
+    assert_eq!(underlined(&editor, 0), "meh");
+    assert_eq!(underlined(&editor, 1), "badmeh");
+
+    // The warning typed last only shows once the server has seen every edit before it.
+    let editor = wait_until(then(opened(), headless()
+        .key(KeyCode::Down, KeyModifiers::NONE)
+        .event(Event::Key(ctrl('k')))
+        .event(Event::Key(ctrl('z')))
+        .event(Event::Key(ctrl('k')))
+        .key(KeyCode::Home, KeyModifiers::CONTROL)
+        .keys("meh")), |editor| editor.diagnostics().iter().any(|diagnostic| diagnostic.start.r == 0));
+    assert_eq!(editor.backend.screen()[1], "");
+    assert_eq!(editor.diagnostics().len(), 1);
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn language_servers_can_be_turned_off(){
+    let dir = temp_dir("lsp-off", &[]);
+    let path = dir.join("main.rs");
+    std::fs::write(&path, "fn main() {\n    let é = bad;\n}\n").unwrap();
+    let mut editor = editor(headless().idle());
+    editor.set_language_server("rs", mock_server());
+    editor.clear_language_servers();
+    editor.open_file(&path.to_string_lossy()).unwrap();
+    let editor = finish(editor);
+    assert!(editor.servers.is_empty());
+    assert_eq!(editor.backend.screen()[1], "    let é = bad;");
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn hover_shows_a_popup_until_the_next_key(){
+    let dir = temp_dir("lsp-hover", &[]);
+    let path = dir.join("main.rs");
+    std::fs::write(&path, "fn main() {\n    helper(bad);\n}\n").unwrap();
+    let hover = ||{
+        let editor = wait_until(with_server(&path, headless()), |editor| !editor.diagnostics().is_empty());
+        wait_until(then(editor, headless()
+            .key(KeyCode::Down, KeyModifiers::NONE)
+            .key(KeyCode::End, KeyModifiers::NONE)
+            .key(KeyCode::Left, KeyModifiers::NONE)
+            .key(KeyCode::Left, KeyModifiers::NONE)
+            .key(KeyCode::Left, KeyModifiers::NONE)
+            .key(KeyCode::Char('k'), KeyModifiers::ALT)), answered)
+    };
+    let editor = hover();
+    let screen = editor.backend.screen();
+    assert_eq!(screen[2], "  }             error: bad is bad");
+    assert_eq!(screen[3], "                fn bad()");
+    assert_eq!(screen[4], "                A word of the file.");
+
+    let editor = then(hover(), headless().key(KeyCode::Esc, KeyModifiers::NONE).keys("x"));
+    assert!(editor.popup.is_none());
+    assert_eq!(editor.buffer.get(1), Some("    helper(baxd);"));
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn go_to_definition_opens_the_defining_file(){
+    let dir = temp_dir("lsp-definition", &[]);
+    let path = dir.join("main.rs");
+    std::fs::write(&path, "fn main() {\n    helper();\n}\n").unwrap();
+    std::fs::write(dir.join("util.rs"), "// Helpers.\n\npub fn helper() {}\n").unwrap();
+    let opened = || wait_until(with_server(&path, headless()), answered);
+    let editor = wait_until(then(opened(), headless()
+        .key(KeyCode::Down, KeyModifiers::NONE)
+        .key(KeyCode::End, KeyModifiers::NONE)
+        .key(KeyCode::Left, KeyModifiers::CONTROL)
+        .key(KeyCode::F(12), KeyModifiers::NONE)), answered);
+    assert_eq!(editor.buffer.name, "util.rs");
+    assert_eq!(editor.cursor_pos, Position{r: 2, c: 7});
+
+    let editor = wait_until(then(opened(), headless().key(KeyCode::Char('.'), KeyModifiers::ALT)), answered);
+    assert_eq!(editor.buffer.name, "main.rs");
+    assert_eq!(editor.status_message, "No definition found.");
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn completions_narrow_down_while_typing(){
+    let dir = temp_dir("lsp-completion", &[]);
+    let path = dir.join("main.rs");
+    std::fs::write(&path, "let alphabet = alpine + beta;\n").unwrap();
+    let complete = ||{
+        let editor = wait_until(with_server(&path, headless()), answered);
+        wait_until(then(editor, headless()
+            .key(KeyCode::End, KeyModifiers::CONTROL)
+            .keys("\nal")
+            .key(KeyCode::Char('/'), KeyModifiers::ALT)), answered)
+    };
+    let editor = complete();
+    let screen = editor.backend.screen();
+    assert_eq!(screen[1], "  al");
+    assert_eq!(screen[2], "  alphabet");
+    assert_eq!(screen[3], "  alpine");
+    assert_eq!(screen[4], "");
+
+    let editor = then(complete(), headless().keys("p").key(KeyCode::Down, KeyModifiers::NONE).keys("\n"));
+    assert_eq!(editor.buffer.get(1), Some("alpine"));
+    assert!(editor.popup.is_none());
+
+    let editor = then(complete(), headless().keys("x"));
+    assert!(editor.popup.is_none());
+    assert_eq!(editor.buffer.get(1), Some("alx"));
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn typing_offers_the_nearest_buffer_words(){
+    let text = "counter_far\n\ncount\ncounted + country\n";
+    let typing = || headless()
+        .event(Event::Paste(text.to_string()))
+        .key(KeyCode::Up, KeyModifiers::NONE)
+        .key(KeyCode::Up, KeyModifiers::NONE)
+        .key(KeyCode::End, KeyModifiers::NONE)
+        .keys(" cou");
+    let editor = run(typing());
+    let screen = editor.backend.screen();
+    // Words on the same line come first, then the lines around it.
+    assert_eq!(screen[3], "count count");
+    assert_eq!(screen[4], "      counted");
+    assert_eq!(screen[5], "      country");
+    assert_eq!(screen[6], "      counter_far");
+    assert_eq!(screen[7], "");
+
+    let editor = run(typing().keys("nte").key(KeyCode::Down, KeyModifiers::NONE).key(KeyCode::Tab, KeyModifiers::NONE));
+    assert_eq!(lines(&editor), vec!["counter_far", "", "count counter_far", "counted + country", ""]);
+    assert!(editor.popup.is_none());
+
+    let editor = run(typing().key(KeyCode::Esc, KeyModifiers::NONE).keys("n\n"));
+    assert_eq!(lines(&editor)[2..4], ["count coun", ""]);
+}
+
+#[test]
+fn alt_slash_completes_from_the_buffer_without_a_server(){
+    let text = "Value value_of\n";
+    let complete = || headless()
+        .event(Event::Paste(text.to_string()))
+        .keys("v")
+        .key(KeyCode::Char('/'), KeyModifiers::ALT);
+    let editor = run(complete());
+    let screen = editor.backend.screen();
+    assert_eq!(screen[2], " value_of");
+    assert_eq!(screen[3], " Value");
+    let editor = run(complete().keys("\n"));
+    assert_eq!(lines(&editor), vec!["Value value_of", "value_of"]);
+
+    let editor = run(headless().keys("xyz").key(KeyCode::Char('/'), KeyModifiers::ALT));
+    assert!(editor.popup.is_none());
+    assert_eq!(editor.status_message, "No completions.");
+}
+
+/// A git repository in a fresh temp directory, on branch `trunk`, with `a.txt` committed.
+fn git_repo(name: &str) -> std::path::PathBuf{
+    let dir = temp_dir(name, &[]);
+    std::fs::write(dir.join("a.txt"), "one\ntwo\nthree\nfour\nfive\nsix\n").unwrap();
+    for args in [
+        &["init", "-q", "-b", "trunk"][..],
+        &["add", "a.txt"],
+        &["-c", "user.name=red", "-c", "user.email=red@example.com", "commit", "-q", "-m", "Add a.txt"],
+    ]{
+        let status = std::process::Command::new("git").arg("-C").arg(&dir).args(args).status().unwrap();
+        assert!(status.success(), "git {:?} failed", args);
+    }
+    dir
+}
+
+/// Opens `a.txt`, changes `two`, deletes `four` and adds `seven` at the end.
+fn edit_committed_file(dir: &std::path::Path) -> HeadlessBackend{
+    open(&dir.join("a.txt"))
+        .key(KeyCode::Down, KeyModifiers::NONE)
+        .key(KeyCode::End, KeyModifiers::NONE)
+        .keys("!")
+        .key(KeyCode::Down, KeyModifiers::NONE)
+        .key(KeyCode::Down, KeyModifiers::NONE)
+        .key(KeyCode::Home, KeyModifiers::NONE)
+        .event(Event::Key(ctrl('k')))
+        .event(Event::Key(ctrl('k')))
+        .key(KeyCode::End, KeyModifiers::CONTROL)
+        .keys("\nseven")
+}
+
+#[test]
+fn changes_since_the_last_commit_are_marked_in_the_gutter(){
+    let dir = git_repo("git-gutter");
+    let editor = run(edit_committed_file(&dir));
+    let screen = editor.backend.screen();
+    assert_eq!(&screen[..7], ["  one", "~ two!", "_ three", "  five", "  six", "+ seven", ""]);
+    assert!(screen[10].starts_with("a.txt [trunk]"));
+
+    // Undoing everything leaves nothing to mark.
+    let undo = (0..8).fold(edit_committed_file(&dir), |backend, _| backend.event(Event::Key(ctrl('z'))));
+    let editor = run(undo);
+    assert_eq!(&editor.backend.screen()[..2], ["  one", "  two"]);
+
+    let editor = run(open(&dir.join("a.txt")).event(Event::Key(ctrl('w'))));
+    assert_eq!(editor.backend.screen()[0], "  one");
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn hunks_can_be_stepped_through_and_reverted(){
+    let dir = git_repo("git-hunks");
+    let step = |backend: HeadlessBackend, n| (0..n).fold(backend.key(KeyCode::Home, KeyModifiers::CONTROL), |backend, _| backend.key(KeyCode::Down, KeyModifiers::ALT));
+    let editor = run(step(edit_committed_file(&dir), 2));
+    assert_eq!(editor.cursor_pos, Position{r: 2, c: 0});
+    let editor = run(step(edit_committed_file(&dir), 4));
+    assert_eq!(editor.cursor_pos, Position{r: 5, c: 0});
+    assert_eq!(editor.status_message, "No more changes.");
+    let editor = run(edit_committed_file(&dir).key(KeyCode::Up, KeyModifiers::ALT));
+    assert_eq!(editor.cursor_pos, Position{r: 2, c: 0});
+
+    let editor = run(step(edit_committed_file(&dir), 2).key(KeyCode::Char('r'), KeyModifiers::ALT));
+    assert_eq!(lines(&editor), ["one", "two!", "three", "four", "five", "six", "seven"]);
+    assert_eq!(&editor.backend.screen()[2..5], ["  three", "  four", "  five"]);
+
+    // Reverting every change leaves the committed text; each revert is undone on its own.
+    let revert_all = [1, 2, 5].iter().rev().fold(edit_committed_file(&dir), |backend, &row|{
+        (0..row).fold(backend.key(KeyCode::Home, KeyModifiers::CONTROL), |backend, _| backend.key(KeyCode::Down, KeyModifiers::NONE))
+            .key(KeyCode::Char('r'), KeyModifiers::ALT)
+    });
+    let editor = run(revert_all.event(Event::Key(ctrl('z'))));
+    assert_eq!(lines(&editor), ["one", "two!", "three", "four", "five", "six"]);
+    let editor = run(step(edit_committed_file(&dir), 1).key(KeyCode::Char('r'), KeyModifiers::ALT).key(KeyCode::Char('r'), KeyModifiers::ALT));
+    assert_eq!(editor.status_message, "No change here to revert.");
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn quitting_without_writing_only_counts_as_aborting_a_commit_message(){
+    assert!(!run(headless().keys("text").event(Event::Key(ctrl('q'))).keys("no\n")).aborted());
+    assert!(!run(headless().keys("text").event(Event::Key(ctrl('q'))).keys("cancel\n")).aborted());
+
+    let dir = temp_dir("quit-commit-message", &["COMMIT_EDITMSG"]);
+    let mut message = editor(headless().keys("no\n"));
+    message.open_file(&dir.join("COMMIT_EDITMSG").to_string_lossy()).unwrap();
+    message.buffer.insert_str(Position::default(), "Half a message");
+    message.should_close = true;
+    assert!(finish(message).aborted());
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn commit_messages_are_written_on_quit_or_aborted(){
+    let dir = temp_dir("commit-message", &[]);
+    let path = dir.join("COMMIT_EDITMSG");
+    let template = "\n# Please enter the commit message for your changes.\n";
+    std::fs::write(&path, template).unwrap();
+    let editor = run(open_wide(&path).keys("Fix the frobnicator").event(Event::Key(ctrl('q'))));
+    assert!(!editor.aborted());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), format!("Fix the frobnicator{}", template));
+
+    // Comments are dimmed and column 72 is marked.
+    let frame = editor.backend.frame().unwrap();
+    assert_eq!(frame.cell(0, 1).unwrap().style.fg, Color::DarkGrey);
+    assert_eq!(frame.cell(0, 0).unwrap().style.fg, Color::Reset);
+    assert_eq!(frame.cell(72, 0).unwrap().style.bg, Color::DarkGrey);
+    assert_eq!(frame.cell(71, 0).unwrap().style.bg, Color::Reset);
+
+    std::fs::write(&path, template).unwrap();
+    let editor = run(open_wide(&path).keys("Never mind").event(Event::Key(ctrl('c'))));
+    assert!(editor.aborted());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), template);
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn tab_cycles_the_commands_of_a_rebase_plan(){
+    let dir = temp_dir("rebase-todo", &[]);
+    let path = dir.join("git-rebase-todo");
+    std::fs::write(&path, "pick 1a2b3c First\np 4d5e6f Second\n\n# Commands:\n").unwrap();
+    let editor = run(open(&path)
+        .key(KeyCode::Tab, KeyModifiers::NONE)
+        .key(KeyCode::Tab, KeyModifiers::NONE)
+        .key(KeyCode::Down, KeyModifiers::NONE)
+        .key(KeyCode::BackTab, KeyModifiers::SHIFT));
+    assert_eq!(lines(&editor)[..2], ["edit 1a2b3c First", "drop 4d5e6f Second"]);
+    assert_eq!(editor.status_message, "Tab changes the command, Ctrl + Q writes the plan and rebases, Ctrl + C aborts.");
+
+    let editor = run(open(&path)
+        .key(KeyCode::End, KeyModifiers::CONTROL)
+        .key(KeyCode::Tab, KeyModifiers::NONE));
+    assert_eq!(editor.status_message, "This line has no command to change.");
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+fn filter(backend: HeadlessBackend, command: &str) -> HeadlessBackend{
+    backend.key(KeyCode::Char('|'), KeyModifiers::ALT | KeyModifiers::SHIFT).keys(&format!("{}\n", command))
+}
+
+#[test]
+fn the_buffer_is_filtered_through_a_command_as_one_edit(){
+    let text = || headless().event(Event::Paste("pear\napple\nfig".to_string()));
+    let editor = run(filter(text(), "sort"));
+    assert_eq!(lines(&editor), ["apple", "fig", "pear"]);
+    assert_eq!(editor.status_message, "Replaced with the output of sort.");
+    let editor = run(filter(text(), "sort").event(Event::Key(ctrl('z'))));
+    assert_eq!(lines(&editor), ["pear", "apple", "fig"]);
+
+    // Only the region goes through the command when there is one.
+    let editor = run(filter(text()
+        .key(KeyCode::Up, KeyModifiers::NONE)
+        .key(KeyCode::Home, KeyModifiers::NONE)
+        .event(Event::Key(ctrl(' ')))
+        .key(KeyCode::End, KeyModifiers::NONE), "tr a-z A-Z"));
+    assert_eq!(lines(&editor), ["pear", "APPLE", "fig"]);
+    assert_eq!(editor.cursor_pos, Position{r: 1, c: 5});
+}
+
+#[test]
+fn a_failing_filter_leaves_the_text_alone_and_shows_why(){
+    let failing = || filter(headless().keys("keep me"), "seq 12 >&2; exit 3");
+    let editor = run(failing());
+    assert_eq!(editor.buffer.name, "*messages*");
+    assert!(editor.buffer.read_only);
+    let mut expected = vec!["Filtering through seq 12 >&2; exit 3 failed:".to_string()];
+    expected.extend((1..=12).map(|n| n.to_string()));
+    expected.push("The command failed (exit status: 3).".to_string());
+    assert_eq!(lines(&editor), expected);
+    assert_eq!(editor.status_message, "The command failed, so the text was left as it was.");
+
+    // Moving reads them; Esc goes back to the text, and other keys go on to it.
+    let editor = run(failing().key(KeyCode::PageDown, KeyModifiers::NONE).key(KeyCode::Esc, KeyModifiers::NONE));
+    assert_eq!(lines(&editor), ["keep me"]);
+    assert_eq!(editor.cursor_pos, Position{r: 0, c: 7});
+    let editor = run(failing().keys("!"));
+    assert_eq!(lines(&editor), ["keep me!"]);
+
+    // Complaints on stderr are enough, even when the command says it worked.
+    let editor = run(filter(headless().keys("keep me"), "echo warning >&2; echo replaced").key(KeyCode::Esc, KeyModifiers::NONE));
+    assert_eq!(lines(&editor), ["keep me"]);
+}
+
+/// Runs the script in `backend` with `command` formatting `.txt` files.
+fn with_formatter(command: &str, backend: HeadlessBackend) -> Editor<HeadlessBackend>{
+    let mut editor = editor(backend);
+    editor.set_formatter("txt", command);
+    finish(editor)
+}
+
+#[test]
+fn formatters_run_before_writing_and_keep_the_cursor_on_its_text(){
+    let dir = temp_dir("format", &[]);
+    let path = dir.join("a.txt");
+    std::fs::write(&path, "  alpha\n\n  beta\n  gamma\n").unwrap();
+    let dedent = "sed -e 's/^ *//' -e '/^$/d'";
+    let write = || open(&path)
+        .key(KeyCode::Down, KeyModifiers::NONE)
+        .key(KeyCode::Down, KeyModifiers::NONE)
+        .key(KeyCode::Right, KeyModifiers::NONE)
+        .key(KeyCode::Right, KeyModifiers::NONE)
+        .key(KeyCode::Right, KeyModifiers::NONE)
+        .key(KeyCode::Right, KeyModifiers::NONE)
+        .event(Event::Key(ctrl('w')));
+    let editor = with_formatter(dedent, write());
+    assert_eq!(lines(&editor), ["alpha", "beta", "gamma"]);
+    assert_eq!(editor.cursor_pos, Position{r: 1, c: 2});
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "alpha\nbeta\ngamma\n");
+    assert!(!editor.is_modified());
+
+    // Formatting is one edit, undone with one key.
+    std::fs::write(&path, "  alpha\n\n  beta\n  gamma\n").unwrap();
+    let editor = with_formatter(dedent, write().event(Event::Key(ctrl('z'))));
+    assert_eq!(lines(&editor), ["  alpha", "", "  beta", "  gamma"]);
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn a_failed_write_takes_the_formatting_back(){
+    let dir = temp_dir("format-unwritten", &[]);
+    std::fs::create_dir_all(dir.join("taken.txt")).unwrap();
+    let editor = with_formatter("tr a-z A-Z", headless()
+        .keys("shout")
+        .key(KeyCode::Left, KeyModifiers::NONE)
+        .event(Event::Key(ctrl('w')))
+        .keys(&format!("{}\n", dir.join("taken.txt").display())));
+    assert!(editor.status_message.starts_with("Error: failed to write to disk"));
+    assert_eq!(lines(&editor), ["shout"]);
+    assert_eq!(editor.cursor_pos, Position{r: 0, c: 4});
+    assert!(editor.is_modified());
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn a_failing_formatter_stops_the_write(){
+    let dir = temp_dir("format-failure", &["a.txt", "b.md"]);
+    let path = dir.join("a.txt");
+    let failing = "echo 'a.txt:1: no good' >&2; exit 1";
+    let editor = with_formatter(failing, open(&path).keys("x").event(Event::Key(ctrl('w'))));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "a.txt\n");
+    assert_eq!(editor.status_message, format!("Not written: formatting with {} failed.", failing));
+    assert_eq!(lines(&editor), [
+        format!("Formatting {} with {} failed:", path.display(), failing).as_str(),
+        "a.txt:1: no good",
+        "The command failed (exit status: 1).",
+    ]);
+
+    // Trying again adds to the messages, and the text is still there underneath.
+    let editor = with_formatter(failing, open(&path).keys("x").event(Event::Key(ctrl('w'))).event(Event::Key(ctrl('w'))));
+    assert_eq!(lines(&editor).len(), 7);
+    assert_eq!(lines(&editor)[3], "");
+    let editor = with_formatter(failing, open(&path).keys("x").event(Event::Key(ctrl('w'))).keys("q"));
+    assert_eq!(lines(&editor), ["xa.txt"]);
+    assert!(editor.is_modified());
+
+    // Other files are written as they are.
+    let editor = with_formatter("exit 1", open(&dir.join("b.md")).keys("x").event(Event::Key(ctrl('w'))));
+    assert!(!editor.is_modified());
+    assert_eq!(std::fs::read_to_string(dir.join("b.md")).unwrap(), "xb.md\n");
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn opening_another_file_waits_for_the_formatter_to_succeed(){
+    let dir = temp_dir("format-then-open", &["a.txt", "b.txt"]);
+    let path = dir.join("a.txt");
+    let editor = with_formatter("exit 1", open_wide(&path)
+        .keys("x")
+        .event(Event::Key(ctrl('o')))
+        .keys(&format!("{}\nyes\n", dir.join("b.txt").display())));
+    assert_eq!(editor.buffer.name, "*messages*");
+    assert_eq!(editor.status_message, "Not written: formatting with exit 1 failed.");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "a.txt\n");
+
+    let editor = with_formatter("exit 1", open_wide(&path)
+        .keys("x")
+        .event(Event::Key(ctrl('o')))
+        .keys(&format!("{}\nyes\n", dir.join("b.txt").display()))
+        .key(KeyCode::Esc, KeyModifiers::NONE));
+    assert_eq!(editor.buffer.name, "a.txt");
+    assert_eq!(lines(&editor), ["xa.txt"]);
+    assert!(editor.is_modified());
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn commands_stream_their_output_and_enter_opens_the_location_on_a_line(){
+    let dir = temp_dir("run", &[]);
+    std::fs::create_dir_all(dir.join(".git")).unwrap();
+    std::fs::write(dir.join("a.txt"), "one\ntwo ñx\nthree\n").unwrap();
+    let command = |backend: HeadlessBackend, command: &str| idle(backend.event(Event::Key(alt('!'))).keys(&format!("{}\n", command)), 25);
+
+    let editor = run(command(open(&dir.join("a.txt")), "echo building >&2; sleep 0.1; echo 'a.txt:2:6: here'"));
+    assert_eq!(editor.buffer.name, "*output*");
+    assert!(editor.buffer.read_only);
+    assert_eq!(lines(&editor)[1..], ["building", "a.txt:2:6: here", "echo building >&2; sleep 0.1; echo 'a.txt:2:6: here' finished."]);
+    assert_eq!(editor.cursor_pos, Position{r: 3, c: 0});
+
+    let editor = run(command(open(&dir.join("a.txt")), "echo 'a.txt:2:6: here'").key(KeyCode::Up, KeyModifiers::NONE).key(KeyCode::Enter, KeyModifiers::NONE));
+    assert_eq!(editor.buffer.name, "a.txt");
+    assert_eq!(editor.cursor_pos, Position{r: 1, c: 6});
+    let editor = run(command(open(&dir.join("a.txt")), "exit 3").key(KeyCode::Enter, KeyModifiers::NONE));
+    assert_eq!(editor.buffer.name, "*output*");
+    assert_eq!(editor.status_message, "No file:line on this line.");
+    assert_eq!(lines(&editor).last().unwrap(), "exit 3 failed (exit status: 3).");
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn f5_runs_the_last_command_again(){
+    let dir = temp_dir("rerun", &["a.txt"]);
+    let editor = run(headless().key(KeyCode::F(5), KeyModifiers::NONE));
+    assert_eq!(editor.status_message, "No command yet, Alt + ! runs one.");
+
+    let script = idle(open(&dir.join("a.txt")).event(Event::Key(alt('!'))).keys("echo run >> count; wc -l < count\n"), 25);
+    let editor = run(idle(script.key(KeyCode::F(5), KeyModifiers::NONE), 25));
+    std::fs::remove_dir_all(&dir).ok();
+    assert_eq!(editor.buffer.name, "*output*");
+    assert_eq!(lines(&editor)[1].trim(), "2");
+}
+
+#[test]
+fn errors_are_stepped_through_and_move_with_the_lines_they_point_at(){
+    let dir = temp_dir("errors", &[]);
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    let output = "error[E0425]: cannot find value `x` in this scope\n --> src/a.rs:3:5\n  |\n3 |     x\n  |\n  ::: src/b.rs:1:1\nsrc/a.rs:5:late match\nsrc/b.rs:1:2: error: expected ';'\n12:30:00 done\n";
+    let load = |backend: HeadlessBackend|{
+        std::fs::write(dir.join("src/a.rs"), "one\ntwo\nthree x\nfour\nlate match\nsix\n").unwrap();
+        std::fs::write(dir.join("src/b.rs"), "b()\n").unwrap();
+        let mut editor = editor(backend);
+        editor.load_errors(output, &dir).unwrap();
+        editor
+    };
+    let f8 = |backend: HeadlessBackend| backend.key(KeyCode::F(8), KeyModifiers::NONE);
+    let shift_f8 = |backend: HeadlessBackend| backend.key(KeyCode::F(8), KeyModifiers::SHIFT);
+    let edited = || headless().key(KeyCode::Home, KeyModifiers::CONTROL).key(KeyCode::Enter, KeyModifiers::NONE);
+
+    let editor = finish(load(headless()));
+    assert_eq!(editor.buffer.name, "a.rs");
+    assert_eq!(editor.cursor_pos, Position{r: 2, c: 4});
+    assert_eq!(editor.status_message, "1 of 3: error[E0425]: cannot find value `x` in this scope");
+
+    // A line inserted above moves the next error down with its line.
+    let editor = finish(load(f8(edited())));
+    assert_eq!(editor.cursor_pos, Position{r: 5, c: 0});
+    assert_eq!(editor.status_message, "2 of 3: late match");
+
+    let editor = finish(load(f8(f8(f8(edited())).keys("yes\n"))));
+    assert_eq!(editor.buffer.name, "b.rs");
+    assert_eq!(editor.status_message, "No more errors.");
+    assert_eq!(editor.cursor_pos, Position{r: 0, c: 1});
+
+    // Coming back to the file after writing it finds the errors where they moved to.
+    let editor = finish(load(shift_f8(shift_f8(f8(f8(edited())).keys("yes\n")))));
+    assert_eq!(editor.buffer.name, "a.rs");
+    assert_eq!(editor.cursor_pos, Position{r: 3, c: 4});
+    assert_eq!(std::fs::read_to_string(dir.join("src/a.rs")).unwrap(), "\none\ntwo\nthree x\nfour\nlate match\nsix\n");
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn f8_goes_through_the_places_a_command_named(){
+    let dir = temp_dir("run-errors", &["a.txt", "b.txt"]);
+    std::fs::create_dir_all(dir.join(".git")).unwrap();
+    let editor = run(headless().key(KeyCode::F(8), KeyModifiers::NONE));
+    assert_eq!(editor.status_message, "No errors yet, Alt + ! runs a command.");
+
+    let script = || idle(open(&dir.join("a.txt")).event(Event::Key(alt('!'))).keys("printf 'a.txt:1: first\\nb.txt:1:3: second\\n'\n"), 25);
+    let editor = run(script());
+    assert!(editor.status_message.ends_with(" finished. F8 goes through the 2 places it named."));
+    let editor = run(script().key(KeyCode::F(8), KeyModifiers::NONE).key(KeyCode::F(8), KeyModifiers::NONE));
+    assert_eq!(editor.buffer.name, "b.txt");
+    assert_eq!(editor.cursor_pos, Position{r: 0, c: 2});
+    assert_eq!(editor.status_message, "2 of 2: second");
+
+    // Enter on a line of the output makes it the current one.
+    let editor = run(script().key(KeyCode::Up, KeyModifiers::NONE).key(KeyCode::Up, KeyModifiers::NONE).key(KeyCode::Enter, KeyModifiers::NONE).key(KeyCode::F(8), KeyModifiers::NONE));
+    std::fs::remove_dir_all(&dir).ok();
+    assert_eq!(editor.buffer.name, "b.txt");
+    assert_eq!(editor.status_message, "2 of 2: second");
+}
+
+#[test]
+fn reopening_a_file_puts_the_cursor_back_where_it_was(){
+    let dir = temp_dir("places", &["b.txt"]);
+    std::fs::write(dir.join("a.txt"), "one\ntwo\nthree\n").unwrap();
+    let editor = run(open(&dir.join("a.txt"))
+        .key(KeyCode::Down, KeyModifiers::NONE).key(KeyCode::Down, KeyModifiers::NONE).key(KeyCode::End, KeyModifiers::NONE)
+        .event(Event::Key(ctrl('o'))).keys(&format!("{}\n", dir.join("b.txt").display()))
+        .event(Event::Key(ctrl('o'))).keys(&format!("{}\n", dir.join("a.txt").display())));
+    std::fs::remove_dir_all(&dir).ok();
+    assert_eq!(editor.buffer.name, "a.txt");
+    assert_eq!(editor.cursor_pos, Position{r: 2, c: 5});
+}
+
+#[test]
+fn sessions_bring_back_the_file_history_and_kills(){
+    let dir = temp_dir("session", &[]);
+    let text: String = (0..40).map(|i| format!("line {}\n", i)).collect();
+    std::fs::write(dir.join("a.txt"), &text).unwrap();
+    let session = dir.join("state/red/sessions/default.json");
+    let with_session = |backend: HeadlessBackend|{
+        let mut editor = editor(backend);
+        editor.load_session(&session).unwrap();
+        editor.restore_session().unwrap();
+        finish(editor)
+    };
+
+    let editor = with_session(open(&dir.join("a.txt"))
+        .event(Event::Key(ctrl('j'))).keys("25\n")
+        .event(Event::Key(ctrl(' '))).key(KeyCode::End, KeyModifiers::NONE).event(Event::Key(alt('w')))
+        .event(Event::Key(ctrl('q'))));
+    let scroll = editor.scroll;
+    assert!(scroll > 0);
+    assert!(session.is_file());
+
+    let editor = with_session(headless().event(Event::Key(ctrl('q'))));
+    assert_eq!(editor.buffer.name, "a.txt");
+    assert_eq!(editor.cursor_pos, Position{r: 25, c: 7});
+    assert_eq!(editor.scroll, scroll);
+    assert_eq!(editor.kill_ring.current(), Some("line 25"));
+
+    // Up in a prompt brings back the earlier answers to it.
+    let editor = with_session(headless().key(KeyCode::Home, KeyModifiers::CONTROL).event(Event::Key(ctrl('j'))).key(KeyCode::Up, KeyModifiers::NONE).keys("\n").event(Event::Key(ctrl('q'))));
+    std::fs::remove_dir_all(&dir).ok();
+    assert_eq!(editor.cursor_pos.r, 25);
+}
+
+#[test]
+fn marks_are_shown_in_the_gutter_and_move_with_their_lines(){
+    let dir = temp_dir("marks", &[]);
+    std::fs::write(dir.join("a.txt"), "one\ntwo\nthree\nfour\n").unwrap();
+    let script = || open(&dir.join("a.txt")).key(KeyCode::Down, KeyModifiers::NONE).key(KeyCode::Down, KeyModifiers::NONE)
+        .event(Event::Key(alt('m'))).keys("a")
+        .key(KeyCode::Home, KeyModifiers::CONTROL).key(KeyCode::Enter, KeyModifiers::NONE);
+
+    let editor = run(script());
+    let screen = editor.backend.screen();
+    assert_eq!(screen[1], "  one");
+    assert_eq!(screen[3], "a three");
+    let editor = run(script().event(Event::Key(alt('j'))).keys("a"));
+    assert_eq!(editor.cursor_pos, Position{r: 3, c: 0});
+    let editor = run(script().event(Event::Key(alt('j'))).keys("b"));
+    assert_eq!(editor.cursor_pos, Position{r: 1, c: 0});
+    assert_eq!(editor.status_message, "No mark b.");
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn capital_marks_are_gone_to_from_any_file(){
+    let dir = temp_dir("global-marks", &["b.txt"]);
+    std::fs::write(dir.join("a.txt"), "one\ntwo\nthree\n").unwrap();
+    let script = || open(&dir.join("a.txt")).key(KeyCode::Down, KeyModifiers::NONE).key(KeyCode::End, KeyModifiers::NONE)
+        .event(Event::Key(alt('m'))).keys("A").event(Event::Key(alt('m'))).keys("a")
+        .event(Event::Key(ctrl('o'))).keys(&format!("{}\n", dir.join("b.txt").display()));
+
+    let editor = run(script().event(Event::Key(alt('j'))).keys("A"));
+    assert_eq!(editor.buffer.name, "a.txt");
+    assert_eq!(editor.cursor_pos, Position{r: 1, c: 3});
+    let editor = run(script().event(Event::Key(alt('j'))).keys("a"));
+    assert_eq!(editor.buffer.name, "b.txt");
+    assert_eq!(editor.status_message, "No mark a.");
+    std::fs::remove_dir_all(&dir).ok();
+
+    let editor = run(headless().keys("text").event(Event::Key(alt('m'))).keys("B"));
+    assert_eq!(editor.status_message, "Marks across files need a file; write this one first.");
+}
+
@@ -0,0 +1,55 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Everything that can go wrong in red.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error{
+    /// Reading or writing `path` failed.
+    File{path: PathBuf, source: io::Error},
+    /// Line `line` (counted from zero) of `path` isn't valid UTF-8.
+    Encoding{path: PathBuf, line: usize},
+    /// A buffer that was never saved has to be given a file name before it can be written.
+    NoFileName,
+    /// A directory operation was asked for on a line of the listing that isn't an entry.
+    NoEntry,
+    /// Talking to the terminal failed.
+    Terminal(io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        match self{
+            Error::File{path, source} => write!(f, "{}: {}", path.display(), source),
+            Error::Encoding{path, line} => write!(f, "{}: line {} is not valid UTF-8", path.display(), line + 1),
+            Error::NoFileName => write!(f, "no file name given"),
+            Error::NoEntry => write!(f, "no file on this line"),
+            Error::Terminal(e) => write!(f, "terminal: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)>{
+        match self{
+            Error::File{source, ..} => Some(source),
+            Error::Terminal(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Error{
+    pub(crate) fn file(path: impl Into<PathBuf>) -> impl FnOnce(io::Error) -> Error{
+        let path = path.into();
+        move |source| Error::File{path, source}
+    }
+
+    /// Whether this is a failure to open a file that doesn't exist.
+    pub fn is_not_found(&self) -> bool{
+        matches!(self, Error::File{source, ..} if source.kind() == io::ErrorKind::NotFound)
+    }
+}
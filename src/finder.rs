@@ -0,0 +1,234 @@
+use std::path::{Path,PathBuf};
+use std::sync::mpsc::{self,Receiver,Sender,TryRecvError};
+use std::thread;
+use crate::ignore::walk;
+
+/// Paths are sent from the walk in batches so the UI isn't woken up for every file.
+const BATCH_SIZE: usize = 256;
+
+/// The state of the fuzzy file finder: the files found so far below `root` and the
+/// ones matching the pattern typed, best first. The tree is walked on its own thread;
+/// [`poll`](Finder::poll) picks up what it found since the last call.
+pub(crate) struct Finder{
+    root: PathBuf,
+    files: Vec<String>,
+    receiver: Option<Receiver<Vec<String>>>,
+    pattern: String,
+    matches: Vec<usize>,
+    selected: usize,
+}
+
+impl Finder{
+    /// Starts walking the tree below `root`.
+    pub(crate) fn new(root: PathBuf) -> Self{
+        let (sender, receiver) = mpsc::channel();
+        let walk_root = root.clone();
+        thread::spawn(move || send_files(&walk_root, &sender));
+        Self{
+            root,
+            files: vec![],
+            receiver: Some(receiver),
+            pattern: String::new(),
+            matches: vec![],
+            selected: 0,
+        }
+    }
+
+    pub(crate) fn root(&self) -> &Path{
+        self.root.as_path()
+    }
+
+    /// Whether the walk is still going.
+    pub(crate) fn walking(&self) -> bool{
+        self.receiver.is_some()
+    }
+
+    /// Takes in the files found since the last call. Returns whether anything changed.
+    pub(crate) fn poll(&mut self) -> bool{
+        let receiver = match self.receiver.as_ref(){
+            Some(receiver) => receiver,
+            None => return false,
+        };
+        let mut changed = false;
+        loop{
+            match receiver.try_recv(){
+                Ok(batch) =>{
+                    self.files.extend(batch);
+                    changed = true;
+                },
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) =>{
+                    self.receiver = None;
+                    changed = true;
+                    break;
+                },
+            }
+        }
+        if changed{
+            self.rank();
+        }
+        changed
+    }
+
+    pub(crate) fn pattern(&self) -> &str{
+        &self.pattern
+    }
+
+    pub(crate) fn push(&mut self, text: &str){
+        self.pattern.push_str(text);
+        self.rank();
+    }
+
+    pub(crate) fn pop(&mut self){
+        self.pattern.pop();
+        self.rank();
+    }
+
+    /// Moves the selection `delta` matches down, stopping at either end.
+    pub(crate) fn select(&mut self, delta: isize){
+        let last = self.matches.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(delta).min(last);
+    }
+
+    pub(crate) fn selected(&self) -> usize{
+        self.selected
+    }
+
+    /// The matching files, relative to the root and best first.
+    pub(crate) fn matches(&self) -> impl Iterator<Item = &str>{
+        self.matches.iter().map(|&i| self.files[i].as_str())
+    }
+
+    pub(crate) fn match_count(&self) -> usize{
+        self.matches.len()
+    }
+
+    pub(crate) fn file_count(&self) -> usize{
+        self.files.len()
+    }
+
+    /// The full path of the selected match.
+    pub(crate) fn selection(&self) -> Option<PathBuf>{
+        self.matches.get(self.selected).map(|&i| self.root.join(&self.files[i]))
+    }
+
+    fn rank(&mut self){
+        let mut scored: Vec<(i64,usize)> = self.files.iter().enumerate()
+            .filter_map(|(i,file)| score(&self.pattern, file).map(|score| (score,i)))
+            .collect();
+        // Best score first; ties go to the shorter path, then alphabetical order.
+        scored.sort_by(|a, b| b.0.cmp(&a.0)
+            .then(self.files[a.1].len().cmp(&self.files[b.1].len()))
+            .then(self.files[a.1].cmp(&self.files[b.1])));
+        self.matches = scored.into_iter().map(|(_,i)| i).collect();
+        self.selected = self.selected.min(self.matches.len().saturating_sub(1));
+    }
+}
+
+/// The directory holding the nearest `.git` at or above `start`, or `start` itself.
+pub(crate) fn project_root(start: &Path) -> PathBuf{
+    start.ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .unwrap_or(start)
+        .to_path_buf()
+}
+
+/// Sends the files below `root` that git doesn't ignore, as paths relative to `root`.
+/// Stops early once nobody is listening.
+fn send_files(root: &Path, sender: &Sender<Vec<String>>){
+    let mut batch = vec![];
+    let finished = walk(root, |path|{
+        batch.push(path.to_string_lossy().replace('\\',"/"));
+        batch.len() < BATCH_SIZE || sender.send(std::mem::take(&mut batch)).is_ok()
+    });
+    if finished && !batch.is_empty(){
+        sender.send(batch).ok();
+    }
+}
+
+/// Scores how well `candidate` matches `pattern`, or `None` if the pattern's characters
+/// don't all appear in it in order. Case is ignored unless the pattern has capitals.
+/// Matches in the file name, at the start of words and in runs score higher; gaps and
+/// long paths cost a little.
+pub(crate) fn score(pattern: &str, candidate: &str) -> Option<i64>{
+    if pattern.is_empty(){
+        return Some(0);
+    }
+    let smart_case = pattern.chars().any(char::is_uppercase);
+    let fold = |c: char| if smart_case{ c }else{ c.to_ascii_lowercase() };
+    let pattern: Vec<char> = pattern.chars().map(fold).collect();
+    let text: Vec<char> = candidate.chars().collect();
+
+    // Find where the leftmost match ends, then walk back from there to the latest start,
+    // which gives the tightest window ending at that point.
+    let mut p = 0;
+    let mut end = 0;
+    for (i,&c) in text.iter().enumerate(){
+        if fold(c) == pattern[p]{
+            p += 1;
+            if p == pattern.len(){
+                end = i;
+                break;
+            }
+        }
+    }
+    if p < pattern.len(){
+        return None;
+    }
+    let mut start = end;
+    let mut p = pattern.len();
+    for i in (0..=end).rev(){
+        if fold(text[i]) == pattern[p-1]{
+            p -= 1;
+            if p == 0{
+                start = i;
+                break;
+            }
+        }
+    }
+
+    let name_start = candidate.rfind('/').map(|i| candidate[..=i].chars().count()).unwrap_or(0);
+    let mut score = 0;
+    let mut p = 0;
+    let mut previous: Option<usize> = None;
+    for (i,&c) in text.iter().enumerate().take(end+1).skip(start){
+        if p == pattern.len() || fold(c) != pattern[p]{
+            continue;
+        }
+        score += 16;
+        let boundary = i == 0 || matches!(text[i-1], '/' | '_' | '-' | '.' | ' ')
+            || (text[i-1].is_lowercase() && c.is_uppercase());
+        if boundary{
+            score += 8;
+        }
+        if i >= name_start{
+            score += 4;
+        }
+        match previous{
+            Some(previous) if previous + 1 == i => score += 12,
+            Some(previous) => score -= gap_penalty(i - previous - 1),
+            None =>{},
+        }
+        previous = Some(i);
+        p += 1;
+    }
+    Some(score - text.len() as i64 / 8)
+}
+
+fn gap_penalty(gap: usize) -> i64{
+    3 + (gap as i64).min(12)
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn fuzzy_scores_prefer_file_names_and_word_starts(){
+        assert_eq!(score("xyz", "src/main.rs"), None);
+        assert!(score("main", "src/main.rs") > score("main", "src/my_animation.rs"));
+        assert!(score("ed", "src/editor.rs") > score("ed", "src/used.rs"));
+        assert!(score("Buf", "src/Buffer.rs").is_some());
+        assert_eq!(score("Buf", "src/buffer.rs"), None);
+    }
+}
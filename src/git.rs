@@ -0,0 +1,282 @@
+use std::cmp;
+use std::path::Path;
+use std::process::{Command,Stdio};
+use crate::buffer::{Buffer,Direction};
+
+/// Where a commit message's lines should end, marked with a ruler.
+pub(crate) const RULER: usize = 72;
+/// What a line of a rebase plan starts with, long and short, in the order Tab cycles them.
+const VERBS: &[(&str,&str)] = &[
+    ("pick","p"),
+    ("reword","r"),
+    ("edit","e"),
+    ("squash","s"),
+    ("fixup","f"),
+    ("drop","d"),
+];
+
+/// Most edits between two versions worked out line by line; beyond that the changed
+/// stretch is taken as one hunk, which keeps diffing a rewritten file quick.
+const MAX_EDITS: usize = 1000;
+
+/// How the lines of a hunk differ from the committed file.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Sign{
+    Added,
+    Modified,
+    Deleted,
+}
+
+/// Lines `start..start+added` of the buffer took the place of lines
+/// `old_start..old_start+removed` of the committed file.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) struct Hunk{
+    pub(crate) start: usize,
+    pub(crate) added: usize,
+    pub(crate) old_start: usize,
+    pub(crate) removed: usize,
+}
+
+impl Hunk{
+    pub(crate) fn sign(&self) -> Sign{
+        match (self.added, self.removed){
+            (_, 0) => Sign::Added,
+            (0, _) => Sign::Deleted,
+            _ => Sign::Modified,
+        }
+    }
+
+    /// The rows of the buffer marked in the gutter: the new lines or, where lines were
+    /// only deleted, the line above them.
+    pub(crate) fn rows(&self) -> std::ops::Range<usize>{
+        match self.added{
+            0 => self.start.saturating_sub(1)..cmp::max(self.start, 1),
+            added => self.start..self.start + added,
+        }
+    }
+}
+
+/// The committed version of the current file, compared with the buffer as it changes.
+pub(crate) struct GitFile{
+    head: Vec<String>,
+    hunks: Vec<Hunk>,
+    /// The buffer's generation the hunks were worked out for.
+    generation: Option<u64>,
+}
+
+impl GitFile{
+    /// Reads the file at `path` as HEAD has it. `None` outside a repository, without the
+    /// `git` binary, or for files that were never committed.
+    pub(crate) fn open(path: &Path) -> Option<Self>{
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let name = path.file_name()?.to_str()?;
+        let head = git(dir, &["show", &format!("HEAD:./{}",name)])?;
+        Some(Self{head: head.lines().map(String::from).collect(), hunks: vec![], generation: None})
+    }
+
+    /// Compares `buffer` with the committed file again if it changed since the last time.
+    pub(crate) fn update(&mut self, buffer: &Buffer){
+        if self.generation != Some(buffer.generation()){
+            self.hunks = diff(&self.head, buffer.lines());
+            self.generation = Some(buffer.generation());
+        }
+    }
+
+    /// The changes as of the last [`update`](GitFile::update), top to bottom.
+    pub(crate) fn hunks(&self) -> &[Hunk]{
+        &self.hunks
+    }
+
+    /// The committed lines `hunk` replaced.
+    pub(crate) fn old_lines(&self, hunk: &Hunk) -> Vec<String>{
+        self.head[hunk.old_start..hunk.old_start + hunk.removed].to_vec()
+    }
+}
+
+/// A file git hands to the editor to be filled in.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Message{
+    /// `COMMIT_EDITMSG`, the message of a commit being made.
+    Commit,
+    /// `git-rebase-todo`, the plan of an interactive rebase.
+    RebaseTodo,
+}
+
+impl Message{
+    /// Which kind of file `path` is, going by its name.
+    pub(crate) fn of(path: &Path) -> Option<Self>{
+        match path.file_name()?.to_str()?{
+            "COMMIT_EDITMSG" => Some(Message::Commit),
+            "git-rebase-todo" => Some(Message::RebaseTodo),
+            _ => None,
+        }
+    }
+
+    /// How to finish, shown when the file is opened.
+    pub(crate) fn hint(self) -> &'static str{
+        match self{
+            Message::Commit => "Ctrl + Q writes the message and commits, Ctrl + C aborts the commit.",
+            Message::RebaseTodo => "Tab changes the command, Ctrl + Q writes the plan and rebases, Ctrl + C aborts.",
+        }
+    }
+}
+
+/// Whether git leaves `line` out of the message.
+pub(crate) fn is_comment(line: &str) -> bool{
+    line.starts_with('#')
+}
+
+/// `line` of a rebase plan with its command changed to the next (or previous) one, or
+/// `None` if it doesn't start with one.
+pub(crate) fn cycle_verb(line: &str, direction: Direction) -> Option<String>{
+    let verb = line.split_whitespace().next()?;
+    let i = VERBS.iter().position(|&(long, short)| verb == long || verb == short)?;
+    let next = match direction{
+        Direction::Forward => (i + 1) % VERBS.len(),
+        Direction::Backward => (i + VERBS.len() - 1) % VERBS.len(),
+    };
+    let start = line.len() - line.trim_start().len();
+    Some(format!("{}{}{}",&line[..start],VERBS[next].0,&line[start + verb.len()..]))
+}
+
+/// The branch checked out in the repository holding `dir`, or the abbreviated commit if
+/// none is. `None` outside a repository.
+pub(crate) fn branch(dir: &Path) -> Option<String>{
+    git(dir, &["symbolic-ref", "--short", "-q", "HEAD"])
+        .or_else(|| git(dir, &["rev-parse", "--short", "HEAD"]))
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+/// What `git args` prints when run in `dir`, if it succeeds.
+fn git(dir: &Path, args: &[&str]) -> Option<String>{
+    let output = Command::new("git").arg("-C").arg(dir).args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The hunks that turn `old` into `new`, top to bottom.
+pub(crate) fn diff(old: &[String], new: &[String]) -> Vec<Hunk>{
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    let (a, b) = (&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+    if a.is_empty() && b.is_empty(){
+        return vec![];
+    }
+    let matches = match common_lines(a, b){
+        Some(matches) => matches,
+        None => return vec![Hunk{start: prefix, added: b.len(), old_start: prefix, removed: a.len()}],
+    };
+    let mut hunks = vec![];
+    let (mut i, mut j) = (0, 0);
+    for (x, y) in matches.into_iter().chain([(a.len(), b.len())]){
+        if x > i || y > j{
+            hunks.push(Hunk{start: prefix + j, added: y - j, old_start: prefix + i, removed: x - i});
+        }
+        (i, j) = (x + 1, y + 1);
+    }
+    hunks
+}
+
+/// Where row `row` of the old version ended up, given the `hunks` that made the new one.
+/// Rows inside a hunk keep their place in it as far as it goes.
+pub(crate) fn follow_row(hunks: &[Hunk], row: usize) -> usize{
+    let mut shift = 0isize;
+    for hunk in hunks{
+        if row < hunk.old_start{
+            break;
+        }
+        if row < hunk.old_start + hunk.removed{
+            return hunk.start + cmp::min(row - hunk.old_start, hunk.added.saturating_sub(1));
+        }
+        shift = (hunk.start + hunk.added) as isize - (hunk.old_start + hunk.removed) as isize;
+    }
+    (row as isize + shift) as usize
+}
+
+/// The pairs of lines `a` and `b` have in common, as `(index in a, index in b)` in order,
+/// along a shortest edit script (Myers' algorithm). `None` if that takes more than
+/// `MAX_EDITS` edits.
+fn common_lines(a: &[String], b: &[String]) -> Option<Vec<(usize,usize)>>{
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let limit = cmp::min(a.len() + b.len(), MAX_EDITS) as isize;
+    let offset = limit + 1;
+    // The furthest x reached on each diagonal k = x - y, indexed from `offset`.
+    let mut v = vec![0isize; 2 * offset as usize + 1];
+    // Each round's starting `v` around the diagonals it touched, to retrace the path.
+    let mut trace: Vec<Vec<isize>> = vec![];
+    for d in 0..=limit{
+        trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+        for k in (-d..=d).step_by(2){
+            let i = (offset + k) as usize;
+            let mut x = if k == -d || (k != d && v[i - 1] < v[i + 1]){ v[i + 1] }else{ v[i - 1] + 1 };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize]{
+                x += 1;
+                y += 1;
+            }
+            v[i] = x;
+            if x >= n && y >= m{
+                return Some(retrace(&trace, n, m));
+            }
+        }
+    }
+    None
+}
+
+/// Follows the path that reached `(n, m)` back to the start, collecting its diagonal steps.
+fn retrace(trace: &[Vec<isize>], n: isize, m: isize) -> Vec<(usize,usize)>{
+    let (mut x, mut y) = (n, m);
+    let mut matches = vec![];
+    for (d, v) in trace.iter().enumerate().rev(){
+        let d = d as isize;
+        let at = |k: isize| v[(k + d + 1) as usize];
+        let k = x - y;
+        let previous = if k == -d || (k != d && at(k - 1) < at(k + 1)){ k + 1 }else{ k - 1 };
+        let (previous_x, previous_y) = (at(previous), at(previous) - previous);
+        while x > previous_x && y > previous_y{
+            x -= 1;
+            y -= 1;
+            matches.push((x as usize, y as usize));
+        }
+        if d > 0{
+            (x, y) = (previous_x, previous_y);
+        }
+    }
+    matches.reverse();
+    matches
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::terminal::Position;
+
+    #[test]
+    fn hunks_are_only_worked_out_again_after_an_edit(){
+        let mut git = GitFile{head: vec!["a".to_string()], hunks: vec![], generation: None};
+        let mut buffer = Buffer::from_text("a\nb");
+        git.update(&buffer);
+        assert_eq!(git.hunks(), [Hunk{start: 1, added: 1, old_start: 1, removed: 0}]);
+
+        // A stale comparison stays put until the buffer changes.
+        git.head.push("b".to_string());
+        git.update(&buffer);
+        assert_eq!(git.hunks().len(), 1);
+        buffer.insert_str(Position{r: 1, c: 1}, "!");
+        git.update(&buffer);
+        assert_eq!(git.hunks(), [Hunk{start: 1, added: 1, old_start: 1, removed: 1}]);
+    }
+
+    #[test]
+    fn diff_finds_the_smallest_hunks(){
+        let lines = |text: &str| text.split(' ').map(String::from).collect::<Vec<_>>();
+        let hunk = |start, added, old_start, removed| Hunk{start, added, old_start, removed};
+        assert_eq!(diff(&lines("a b c d e"), &lines("a b c d e")), []);
+        assert_eq!(diff(&lines("a b c d e"), &lines("x a c d y e z")), [hunk(0, 1, 0, 0), hunk(2, 0, 1, 1), hunk(4, 1, 4, 0), hunk(6, 1, 5, 0)]);
+        assert_eq!(diff(&lines("a b a b a"), &lines("b a b")), [hunk(0, 0, 0, 1), hunk(3, 0, 4, 1)]);
+        assert_eq!(diff(&[], &lines("a b")), [hunk(0, 2, 0, 0)]);
+    }
+}
@@ -0,0 +1,187 @@
+use std::fs::File;
+use std::io::{BufRead,BufReader};
+use std::path::{Path,PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool,Ordering};
+use std::sync::mpsc::{self,Receiver,Sender,TryRecvError};
+use std::thread;
+use crate::ignore::walk;
+
+/// Lines of the results buffer before the first hit.
+pub(crate) const HEADER_LINES: usize = 1;
+
+/// A line that contains the pattern.
+pub(crate) struct Hit{
+    /// The file, relative to the search's root.
+    pub(crate) path: PathBuf,
+    /// Counted from zero.
+    pub(crate) line: usize,
+    /// Byte offset of the match in the line.
+    pub(crate) column: usize,
+    pub(crate) text: String,
+}
+
+impl Hit{
+    /// How the hit is shown in the results buffer: `path:line: text`, counting lines from one.
+    pub(crate) fn line(&self) -> String{
+        format!("{}:{}: {}",self.path.display(),self.line + 1,self.text)
+    }
+}
+
+/// A search for a string in every file of a project that git doesn't ignore. Files are
+/// searched on their own thread; [`poll`](Grep::poll) picks up the hits found since the
+/// last call. Dropping the search stops it.
+pub(crate) struct Grep{
+    root: PathBuf,
+    pattern: String,
+    hits: Vec<Hit>,
+    receiver: Option<Receiver<Vec<Hit>>>,
+    cancelled: Arc<AtomicBool>,
+    /// The hit visited last by next/previous.
+    current: Option<usize>,
+}
+
+impl Grep{
+    /// Starts searching the files below `root` for `pattern`. As with the finder, a pattern
+    /// without capitals ignores case.
+    pub(crate) fn new(root: PathBuf, pattern: &str) -> Self{
+        let (sender, receiver) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let (walk_root, walk_pattern, walk_cancelled) = (root.clone(), pattern.to_string(), cancelled.clone());
+        thread::spawn(move || search(&walk_root, &walk_pattern, &walk_cancelled, &sender));
+        Self{
+            root,
+            pattern: pattern.to_string(),
+            hits: vec![],
+            receiver: Some(receiver),
+            cancelled,
+            current: None,
+        }
+    }
+
+    pub(crate) fn root(&self) -> &Path{
+        self.root.as_path()
+    }
+
+    pub(crate) fn pattern(&self) -> &str{
+        &self.pattern
+    }
+
+    /// Whether files are still being searched.
+    pub(crate) fn running(&self) -> bool{
+        self.receiver.is_some()
+    }
+
+    /// Takes in the hits found since the last call and returns how many there were.
+    /// The search having finished also counts as news, with no hits.
+    pub(crate) fn poll(&mut self) -> Option<usize>{
+        let receiver = self.receiver.as_ref()?;
+        let before = self.hits.len();
+        loop{
+            match receiver.try_recv(){
+                Ok(batch) => self.hits.extend(batch),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) =>{
+                    self.receiver = None;
+                    return Some(self.hits.len() - before);
+                },
+            }
+        }
+        match self.hits.len() - before{
+            0 => None,
+            new => Some(new),
+        }
+    }
+
+    pub(crate) fn hits(&self) -> &[Hit]{
+        &self.hits
+    }
+
+    /// The hit shown on line `row` of the results buffer.
+    pub(crate) fn hit_at(&self, row: usize) -> Option<usize>{
+        row.checked_sub(HEADER_LINES).filter(|&i| i < self.hits.len())
+    }
+
+    /// Makes hit `index` the current one and returns it.
+    pub(crate) fn visit(&mut self, index: usize) -> Option<&Hit>{
+        let hit = self.hits.get(index)?;
+        self.current = Some(index);
+        Some(hit)
+    }
+
+    pub(crate) fn current(&self) -> Option<usize>{
+        self.current
+    }
+
+    /// The index of the hit after (or before) the current one, if there is one.
+    pub(crate) fn step(&self, forward: bool) -> Option<usize>{
+        match (self.current, forward){
+            (None, true) if !self.hits.is_empty() => Some(0),
+            (Some(current), true) if current + 1 < self.hits.len() => Some(current + 1),
+            (Some(current), false) if current > 0 => Some(current - 1),
+            _ => None,
+        }
+    }
+
+    /// The first line of the results buffer.
+    pub(crate) fn header(&self) -> String{
+        format!("Searching for \"{}\" in {}",self.pattern,self.root.display())
+    }
+}
+
+impl Drop for Grep{
+    fn drop(&mut self){
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+fn search(root: &Path, pattern: &str, cancelled: &AtomicBool, sender: &Sender<Vec<Hit>>){
+    let ignore_case = !pattern.chars().any(char::is_uppercase);
+    let pattern = if ignore_case{ pattern.to_lowercase() }else{ pattern.to_string() };
+    walk(root, |path|{
+        if cancelled.load(Ordering::Relaxed){
+            return false;
+        }
+        let hits = search_file(&root.join(path), path, &pattern, ignore_case, cancelled);
+        hits.is_empty() || sender.send(hits).is_ok()
+    });
+}
+
+/// The hits in one file, read a line at a time so that big files aren't held in memory.
+/// Files that look binary, because they contain a NUL byte, are skipped, and lines that
+/// aren't valid UTF-8 are searched as far as they can be read.
+fn search_file(file: &Path, path: &Path, pattern: &str, ignore_case: bool, cancelled: &AtomicBool) -> Vec<Hit>{
+    let mut reader = match File::open(file){
+        Ok(file) => BufReader::new(file),
+        Err(_) => return vec![],
+    };
+    let mut hits = vec![];
+    let mut bytes = vec![];
+    for n in 0..{
+        bytes.clear();
+        match reader.read_until(b'\n', &mut bytes){
+            Ok(0) => break,
+            Ok(_) =>{},
+            Err(_) => return vec![],
+        }
+        if bytes.contains(&0) || cancelled.load(Ordering::Relaxed){
+            return vec![];
+        }
+        let line = String::from_utf8_lossy(&bytes);
+        let line = line.strip_suffix('\n').unwrap_or(&line);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let found = if ignore_case{
+            // Lowercasing can change byte lengths outside ASCII, so only trust the
+            // offset when it didn't.
+            let lower = line.to_lowercase();
+            lower.find(pattern).map(|column| if lower.len() == line.len(){ column }else{ 0 })
+        }
+        else{
+            line.find(pattern)
+        };
+        if let Some(column) = found{
+            hits.push(Hit{path: path.to_path_buf(), line: n, column, text: line.to_string()});
+        }
+    }
+    hits
+}
@@ -0,0 +1,251 @@
+use std::fs;
+use std::io::Read;
+use std::path::{Path,PathBuf};
+use crate::buffer::name_of;
+use crate::error::{Error,Result};
+use crate::terminal::Position;
+
+/// Bytes shown on each row of the hex view.
+pub(crate) const BYTES_PER_ROW: usize = 16;
+/// Where the hex digits start on a row, after the offset.
+const HEX_COLUMN: usize = 10;
+/// Where the characters start on a row, after the hex digits, a space and a `|`.
+const TEXT_COLUMN: usize = HEX_COLUMN + BYTES_PER_ROW * 3 + 3;
+/// How much of a file is looked at to decide whether it is binary.
+const SNIFF_SIZE: u64 = 8192;
+
+/// A file held as raw bytes, for content that isn't text. Bytes can be overwritten but not
+/// inserted or removed, so writing it back keeps every other byte exactly as it was.
+///
+/// ```
+/// use red::HexBuffer;
+///
+/// let mut buffer = HexBuffer::from_bytes(b"PNG\0\x01");
+/// assert_eq!(buffer.row(0), "00000000  50 4e 47 00 01                                    |PNG..|");
+/// buffer.set(0, 0x89);
+/// assert_eq!(buffer.get(0), Some(0x89));
+/// assert!(buffer.is_modified());
+/// ```
+pub struct HexBuffer{
+    /// Name shown in the modeline, usually the file name.
+    pub name: String,
+    bytes: Vec<u8>,
+    path: PathBuf,
+    /// Set for files that can't be written; `set` leaves the bytes alone.
+    pub read_only: bool,
+    modified: bool,
+}
+
+impl HexBuffer{
+    /// Builds a buffer holding `bytes`, with no file behind it yet.
+    pub fn from_bytes(bytes: &[u8]) -> Self{
+        Self{
+            name: String::from("scratch"),
+            bytes: bytes.to_vec(),
+            path: PathBuf::new(),
+            read_only: false,
+            modified: false,
+        }
+    }
+
+    /// Reads the file at `file_path`.
+    pub fn open(file_path: impl AsRef<Path>) -> Result<Self>{
+        let path = file_path.as_ref().to_path_buf();
+        let bytes = fs::read(&path).map_err(Error::file(&path))?;
+        let read_only = fs::metadata(&path).map(|stats| stats.permissions().readonly()).unwrap_or(false);
+        Ok(Self{
+            name: name_of(&path),
+            bytes,
+            path,
+            read_only,
+            modified: false,
+        })
+    }
+
+    /// Writes the bytes, unchanged apart from the ones overwritten, to `file_name` or
+    /// else to the file they were read from.
+    pub fn write(&mut self, file_name: Option<String>) -> Result<String>{
+        let path = match file_name{
+            Some(name) if !name.is_empty() => PathBuf::from(name),
+            Some(_) => return Err(Error::NoFileName),
+            None if self.path.as_os_str().is_empty() => return Err(Error::NoFileName),
+            None => self.path.clone(),
+        };
+        fs::write(&path, &self.bytes).map_err(Error::file(&path))?;
+        self.name = name_of(&path);
+        self.path = path;
+        self.modified = false;
+        Ok(format!("Wrote {} bytes to disk.",self.bytes.len()))
+    }
+
+    /// The file the bytes were read from or last written to. Empty if there is none.
+    pub fn path(&self) -> &Path{
+        self.path.as_path()
+    }
+
+    pub fn len(&self) -> usize{
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.bytes.is_empty()
+    }
+
+    /// Whether a byte was changed since the file was read or last written.
+    pub fn is_modified(&self) -> bool{
+        self.modified
+    }
+
+    pub fn bytes(&self) -> &[u8]{
+        &self.bytes
+    }
+
+    pub fn get(&self, offset: usize) -> Option<u8>{
+        self.bytes.get(offset).copied()
+    }
+
+    /// Overwrites the byte at `offset`. Offsets past the end are ignored.
+    pub fn set(&mut self, offset: usize, byte: u8){
+        if self.read_only{
+            return;
+        }
+        if let Some(old) = self.bytes.get_mut(offset){
+            if *old != byte{
+                *old = byte;
+                self.modified = true;
+            }
+        }
+    }
+
+    /// Number of rows in the hex view.
+    pub fn rows(&self) -> usize{
+        self.bytes.len().div_ceil(BYTES_PER_ROW)
+    }
+
+    /// Row `row` of the hex view: the offset, sixteen bytes in hex and the same bytes as
+    /// characters, with a `.` for anything that isn't printable ASCII.
+    pub fn row(&self, row: usize) -> String{
+        let start = (row * BYTES_PER_ROW).min(self.bytes.len());
+        let bytes = &self.bytes[start..(start + BYTES_PER_ROW).min(self.bytes.len())];
+        let mut line = format!("{:08x}  ",start);
+        for i in 0..BYTES_PER_ROW{
+            match bytes.get(i){
+                Some(byte) => line.push_str(&format!("{:02x} ",byte)),
+                None => line.push_str("   "),
+            }
+            if i + 1 == BYTES_PER_ROW / 2{
+                line.push(' ');
+            }
+        }
+        line.push_str(" |");
+        line.extend(bytes.iter().map(|&byte| printable(byte)));
+        line.push('|');
+        line
+    }
+}
+
+/// A [`HexBuffer`] being edited: which byte the cursor is on, which of its two hex digits,
+/// and whether typing goes to the hex digits or to the characters.
+pub(crate) struct HexView{
+    pub(crate) buffer: HexBuffer,
+    offset: usize,
+    nibble: usize,
+    text: bool,
+}
+
+impl HexView{
+    pub(crate) fn new(buffer: HexBuffer) -> Self{
+        Self{buffer, offset: 0, nibble: 0, text: false}
+    }
+
+    pub(crate) fn offset(&self) -> usize{
+        self.offset
+    }
+
+    /// Puts the cursor on the first digit of the byte at `offset`, or of the last byte.
+    pub(crate) fn move_to(&mut self, offset: usize){
+        self.offset = offset.min(self.buffer.len().saturating_sub(1));
+        self.nibble = 0;
+    }
+
+    /// Switches typing between the hex digits and the characters.
+    pub(crate) fn toggle_text(&mut self){
+        self.text = !self.text;
+        self.nibble = 0;
+    }
+
+    /// Overwrites the digit or character under the cursor with `c` and moves on. Returns
+    /// false if `c` can't go there: only hex digits fit the digits and ASCII the characters.
+    pub(crate) fn type_char(&mut self, c: char) -> bool{
+        let old = match self.buffer.get(self.offset){
+            Some(old) => old,
+            None => return true,
+        };
+        if self.text{
+            if !c.is_ascii(){
+                return false;
+            }
+            self.buffer.set(self.offset, c as u8);
+            self.move_to(self.offset + 1);
+            return true;
+        }
+        let digit = match c.to_digit(16){
+            Some(digit) => digit as u8,
+            None => return false,
+        };
+        if self.nibble == 0{
+            self.buffer.set(self.offset, (old & 0x0f) | digit << 4);
+            self.nibble = 1;
+        }
+        else{
+            self.buffer.set(self.offset, (old & 0xf0) | digit);
+            // The last digit of the file stays put rather than wrapping to the first.
+            if self.offset + 1 < self.buffer.len(){
+                self.move_to(self.offset + 1);
+            }
+        }
+        true
+    }
+
+    /// Where the cursor goes on screen: the row and column of the digit or character.
+    pub(crate) fn position(&self) -> Position{
+        Position{r: self.offset / BYTES_PER_ROW, c: self.column(self.text) + if self.text{ 0 }else{ self.nibble }}
+    }
+
+    /// The column of the cursor's byte among the characters or, without `text`, of its
+    /// first hex digit.
+    pub(crate) fn column(&self, text: bool) -> usize{
+        let i = self.offset % BYTES_PER_ROW;
+        if text{
+            TEXT_COLUMN + i
+        }
+        else{
+            HEX_COLUMN + i * 3 + (i >= BYTES_PER_ROW / 2) as usize
+        }
+    }
+
+    /// Whether typing goes to the characters.
+    pub(crate) fn text(&self) -> bool{
+        self.text
+    }
+}
+
+fn printable(byte: u8) -> char{
+    if byte.is_ascii_graphic() || byte == b' '{ byte as char }else{ '.' }
+}
+
+/// How the byte under the cursor is drawn in the column that isn't being typed in, so both
+/// columns show where the cursor is.
+pub(crate) fn counterpart(byte: u8, text: bool) -> String{
+    if text{ format!("{:02x}",byte) }else{ printable(byte).to_string() }
+}
+
+/// Whether the file at `path` looks binary, going by a NUL byte near its start the way
+/// git does.
+pub(crate) fn is_binary(path: &Path) -> bool{
+    let mut start = vec![];
+    match fs::File::open(path){
+        Ok(file) => file.take(SNIFF_SIZE).read_to_end(&mut start).is_ok() && start.contains(&0),
+        Err(_) => false,
+    }
+}
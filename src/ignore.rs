@@ -0,0 +1,188 @@
+use std::fs;
+use std::path::{Path,PathBuf};
+
+/// The patterns from the `.gitignore` files seen so far, following git's rules: the last
+/// matching pattern wins, `!` re-includes, a trailing `/` only matches directories and a
+/// pattern containing a `/` is matched against the whole path below its `.gitignore`.
+#[derive(Default)]
+pub(crate) struct Ignore{
+    rules: Vec<Rule>,
+}
+
+struct Rule{
+    /// The directory holding the file the rule came from, relative to the walk's root.
+    base: PathBuf,
+    pattern: String,
+    negate: bool,
+    dir_only: bool,
+    anchored: bool,
+}
+
+impl Ignore{
+    /// Adds the rules in `file`, which apply to paths below `base`. Missing files are skipped.
+    pub(crate) fn add_file(&mut self, file: &Path, base: &Path){
+        if let Ok(text) = fs::read_to_string(file){
+            self.add_rules(&text, base);
+        }
+    }
+
+    pub(crate) fn add_rules(&mut self, text: &str, base: &Path){
+        for line in text.lines(){
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#'){
+                continue;
+            }
+            let (negate, line) = match line.strip_prefix('!'){
+                Some(rest) => (true, rest),
+                None => (false, line.strip_prefix('\\').unwrap_or(line)),
+            };
+            let (dir_only, line) = match line.strip_suffix('/'){
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let anchored = line.contains('/');
+            self.rules.push(Rule{
+                base: base.to_path_buf(),
+                pattern: line.trim_start_matches('/').to_string(),
+                negate,
+                dir_only,
+                anchored,
+            });
+        }
+    }
+
+    /// Whether `path`, relative to the walk's root, is ignored.
+    pub(crate) fn is_ignored(&self, path: &Path, is_dir: bool) -> bool{
+        for rule in self.rules.iter().rev(){
+            if rule.dir_only && !is_dir{
+                continue;
+            }
+            let relative = match path.strip_prefix(&rule.base){
+                Ok(relative) => relative,
+                Err(_) => continue,
+            };
+            let matched = if rule.anchored{
+                glob(rule.pattern.as_bytes(), relative.to_string_lossy().replace('\\',"/").as_bytes())
+            }
+            else{
+                let name = relative.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+                glob(rule.pattern.as_bytes(), name.as_bytes())
+            };
+            if matched{
+                return !rule.negate;
+            }
+        }
+        false
+    }
+}
+
+/// Matches `text` against a gitignore glob: `*` and `?` stay within one path component,
+/// `**` spans any number of them and `[...]` is a character class.
+fn glob(pattern: &[u8], text: &[u8]) -> bool{
+    match pattern.first(){
+        None => text.is_empty(),
+        Some(b'*') if pattern.starts_with(b"**") =>{
+            let rest = &pattern[2..];
+            let rest = rest.strip_prefix(b"/").unwrap_or(rest);
+            rest.is_empty() || (0..=text.len()).any(|i| (i == 0 || text[i-1] == b'/') && glob(rest, &text[i..]))
+        },
+        Some(b'*') =>{
+            let component = text.iter().position(|&c| c == b'/').unwrap_or(text.len());
+            (0..=component).any(|i| glob(&pattern[1..], &text[i..]))
+        },
+        Some(b'?') => matches!(text.first(), Some(&c) if c != b'/') && glob(&pattern[1..], &text[1..]),
+        Some(b'[') =>{
+            let (class, rest) = match pattern.iter().skip(2).position(|&c| c == b']'){
+                Some(end) => (&pattern[1..end+2], &pattern[end+3..]),
+                None => return text.first() == Some(&b'[') && glob(&pattern[1..], &text[1..]),
+            };
+            match text.first(){
+                Some(&c) if c != b'/' && in_class(class, c) => glob(rest, &text[1..]),
+                _ => false,
+            }
+        },
+        Some(b'\\') if pattern.len() > 1 => text.first() == Some(&pattern[1]) && glob(&pattern[2..], &text[1..]),
+        Some(&c) => text.first() == Some(&c) && glob(&pattern[1..], &text[1..]),
+    }
+}
+
+fn in_class(class: &[u8], c: u8) -> bool{
+    let (negate, class) = match class.first(){
+        Some(b'!') | Some(b'^') => (true, &class[1..]),
+        _ => (false, class),
+    };
+    let mut i = 0;
+    let mut found = false;
+    while i < class.len(){
+        if i + 2 < class.len() && class[i+1] == b'-'{
+            found |= (class[i]..=class[i+2]).contains(&c);
+            i += 3;
+        }
+        else{
+            found |= class[i] == c;
+            i += 1;
+        }
+    }
+    found != negate
+}
+
+/// Calls `visit` with each file below `root` that git doesn't ignore, as a path relative to
+/// `root`, in a stable order. Stops as soon as `visit` returns false; returns whether the
+/// walk got to the end.
+pub(crate) fn walk(root: &Path, mut visit: impl FnMut(&Path) -> bool) -> bool{
+    let mut ignore = Ignore::default();
+    ignore.add_file(&root.join(".git/info/exclude"), Path::new(""));
+    let mut pending = vec![PathBuf::new()];
+    while let Some(dir) = pending.pop(){
+        ignore.add_file(&root.join(&dir).join(".gitignore"), &dir);
+        let entries = match fs::read_dir(root.join(&dir)){
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        let mut entries: Vec<_> = entries.filter_map(|entry| entry.ok()).collect();
+        entries.sort_by_key(|entry| entry.file_name());
+        let mut subdirs = vec![];
+        for entry in entries{
+            let name = entry.file_name();
+            if name == ".git"{
+                continue;
+            }
+            let path = dir.join(&name);
+            // Symlinks aren't followed, so a link to a directory is visited like a file.
+            let is_dir = entry.file_type().map(|kind| kind.is_dir()).unwrap_or(false);
+            if ignore.is_ignored(&path, is_dir){
+                continue;
+            }
+            if is_dir{
+                subdirs.push(path);
+            }
+            else if !visit(&path){
+                return false;
+            }
+        }
+        // Pushed in reverse so subdirectories are visited in name order.
+        pending.extend(subdirs.into_iter().rev());
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn the_last_matching_pattern_wins(){
+        let mut ignore = Ignore::default();
+        ignore.add_rules("# build output\n*.log\n!keep.log\ntarget/\n/docs/*.html\n", Path::new(""));
+        ignore.add_rules("*.tmp\n", Path::new("sub"));
+        assert!(ignore.is_ignored(Path::new("a.log"), false));
+        assert!(ignore.is_ignored(Path::new("sub/deep/b.log"), false));
+        assert!(!ignore.is_ignored(Path::new("keep.log"), false));
+        assert!(ignore.is_ignored(Path::new("target"), true));
+        assert!(!ignore.is_ignored(Path::new("target"), false));
+        assert!(ignore.is_ignored(Path::new("docs/index.html"), false));
+        assert!(!ignore.is_ignored(Path::new("sub/docs/index.html"), false));
+        assert!(ignore.is_ignored(Path::new("sub/x.tmp"), false));
+        assert!(!ignore.is_ignored(Path::new("x.tmp"), false));
+    }
+}
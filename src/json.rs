@@ -0,0 +1,304 @@
+use std::fmt;
+
+/// A JSON value, as exchanged with language servers. Objects keep their keys in order.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Json{
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String,Json)>),
+}
+
+/// What looking up a missing key or index gives, so lookups can be chained.
+static NULL: Json = Json::Null;
+
+impl Json{
+    /// Parses `text`, which must hold exactly one value.
+    pub(crate) fn parse(text: &str) -> Option<Json>{
+        let mut parser = Parser{text: text.as_bytes(), at: 0};
+        let value = parser.value()?;
+        parser.skip_space();
+        (parser.at == parser.text.len()).then_some(value)
+    }
+
+    pub(crate) fn object<'a>(pairs: impl IntoIterator<Item = (&'a str,Json)>) -> Json{
+        Json::Object(pairs.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+    }
+
+    /// The value under `key`, or null if there is none.
+    pub(crate) fn get(&self, key: &str) -> &Json{
+        match self{
+            Json::Object(pairs) => pairs.iter().find(|(k, _)| k == key).map_or(&NULL, |(_, value)| value),
+            _ => &NULL,
+        }
+    }
+
+    /// The element at `index`, or null if there is none.
+    pub(crate) fn at(&self, index: usize) -> &Json{
+        match self{
+            Json::Array(items) => items.get(index).unwrap_or(&NULL),
+            _ => &NULL,
+        }
+    }
+
+    pub(crate) fn is_null(&self) -> bool{
+        matches!(self, Json::Null)
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str>{
+        match self{
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_u64(&self) -> Option<u64>{
+        match self{
+            Json::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as u64),
+            _ => None,
+        }
+    }
+
+    /// The elements of an array; anything else has none.
+    pub(crate) fn items(&self) -> &[Json]{
+        match self{
+            Json::Array(items) => items,
+            _ => &[],
+        }
+    }
+}
+
+impl From<&str> for Json{
+    fn from(s: &str) -> Self{
+        Json::String(s.to_string())
+    }
+}
+
+impl From<String> for Json{
+    fn from(s: String) -> Self{
+        Json::String(s)
+    }
+}
+
+impl From<bool> for Json{
+    fn from(b: bool) -> Self{
+        Json::Bool(b)
+    }
+}
+
+impl From<u64> for Json{
+    fn from(n: u64) -> Self{
+        Json::Number(n as f64)
+    }
+}
+
+impl From<usize> for Json{
+    fn from(n: usize) -> Self{
+        Json::Number(n as f64)
+    }
+}
+
+impl From<Vec<Json>> for Json{
+    fn from(items: Vec<Json>) -> Self{
+        Json::Array(items)
+    }
+}
+
+impl fmt::Display for Json{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        match self{
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            // Whole numbers, like ids and positions, are written without a fraction.
+            Json::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", *n as i64),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(s) => write_string(f, s),
+            Json::Array(items) =>{
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate(){
+                    if i > 0{
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            },
+            Json::Object(pairs) =>{
+                write!(f, "{{")?;
+                for (i, (key, value)) in pairs.iter().enumerate(){
+                    if i > 0{
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            },
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result{
+    write!(f, "\"")?;
+    for c in s.chars(){
+        match c{
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+struct Parser<'a>{
+    text: &'a [u8],
+    at: usize,
+}
+
+impl Parser<'_>{
+    fn skip_space(&mut self){
+        while matches!(self.text.get(self.at), Some(b' ' | b'\t' | b'\n' | b'\r')){
+            self.at += 1;
+        }
+    }
+
+    fn eat(&mut self, literal: &str) -> bool{
+        let found = self.text[self.at..].starts_with(literal.as_bytes());
+        if found{
+            self.at += literal.len();
+        }
+        found
+    }
+
+    fn value(&mut self) -> Option<Json>{
+        self.skip_space();
+        match self.text.get(self.at)?{
+            b'n' if self.eat("null") => Some(Json::Null),
+            b't' if self.eat("true") => Some(Json::Bool(true)),
+            b'f' if self.eat("false") => Some(Json::Bool(false)),
+            b'"' => self.string().map(Json::String),
+            b'[' =>{
+                self.at += 1;
+                let mut items = vec![];
+                self.skip_space();
+                if self.eat("]"){
+                    return Some(Json::Array(items));
+                }
+                loop{
+                    items.push(self.value()?);
+                    self.skip_space();
+                    if self.eat("]"){
+                        return Some(Json::Array(items));
+                    }
+                    if !self.eat(","){
+                        return None;
+                    }
+                }
+            },
+            b'{' =>{
+                self.at += 1;
+                let mut pairs = vec![];
+                self.skip_space();
+                if self.eat("}"){
+                    return Some(Json::Object(pairs));
+                }
+                loop{
+                    self.skip_space();
+                    let key = self.string()?;
+                    self.skip_space();
+                    if !self.eat(":"){
+                        return None;
+                    }
+                    pairs.push((key, self.value()?));
+                    self.skip_space();
+                    if self.eat("}"){
+                        return Some(Json::Object(pairs));
+                    }
+                    if !self.eat(","){
+                        return None;
+                    }
+                }
+            },
+            _ => self.number(),
+        }
+    }
+
+    fn number(&mut self) -> Option<Json>{
+        let start = self.at;
+        while matches!(self.text.get(self.at), Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')){
+            self.at += 1;
+        }
+        std::str::from_utf8(&self.text[start..self.at]).ok()?.parse().ok().map(Json::Number)
+    }
+
+    fn string(&mut self) -> Option<String>{
+        if !self.eat("\""){
+            return None;
+        }
+        let mut bytes = vec![];
+        loop{
+            match *self.text.get(self.at)?{
+                b'"' =>{
+                    self.at += 1;
+                    return String::from_utf8(bytes).ok();
+                },
+                b'\\' =>{
+                    let escape = *self.text.get(self.at + 1)?;
+                    self.at += 2;
+                    let c = match escape{
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'u' =>{
+                            let unit = self.hex4()?;
+                            // Characters outside the BMP come as a surrogate pair.
+                            if (0xd800..0xdc00).contains(&unit) && self.eat("\\u"){
+                                let low = self.hex4()?;
+                                char::from_u32(0x10000 + ((unit - 0xd800) << 10) + (low.checked_sub(0xdc00)?))?
+                            }
+                            else{
+                                char::from_u32(unit).unwrap_or(char::REPLACEMENT_CHARACTER)
+                            }
+                        },
+                        other => other as char,
+                    };
+                    bytes.extend(c.encode_utf8(&mut [0; 4]).as_bytes());
+                },
+                byte =>{
+                    bytes.push(byte);
+                    self.at += 1;
+                },
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Option<u32>{
+        let digits = self.text.get(self.at..self.at + 4)?;
+        self.at += 4;
+        u32::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn json_round_trips(){
+        let text = r#"{"id":1,"result":{"items":[{"label":"é\n\"x\"","n":-2.5}],"ok":true,"none":null}}"#;
+        let json = Json::parse(text).unwrap();
+        assert_eq!(json.get("result").get("items").at(0).get("label").as_str(), Some("é\n\"x\""));
+        assert_eq!(json.get("id").as_u64(), Some(1));
+        assert_eq!(json.to_string(), text);
+        assert_eq!(Json::parse(r#""😀""#).unwrap().as_str(), Some("😀"));
+        assert!(Json::parse("[1,]").is_none());
+    }
+}
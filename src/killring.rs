@@ -0,0 +1,58 @@
+use std::collections::VecDeque;
+
+const KILL_RING_SIZE: usize = 60;
+
+/// Emacs style ring of killed text. The most recent kill sits at the front;
+/// `rotate` walks towards older entries and wraps around.
+#[derive(Default)]
+pub(crate) struct KillRing{
+    entries: VecDeque<String>,
+    index: usize,
+}
+
+impl KillRing{
+    /// A ring holding `entries`, the most recent first.
+    pub(crate) fn from_entries(mut entries: Vec<String>) -> Self{
+        entries.truncate(KILL_RING_SIZE);
+        Self{entries: entries.into(), index: 0}
+    }
+
+    /// The kills, the most recent first.
+    pub(crate) fn entries(&self) -> impl Iterator<Item = &str>{
+        self.entries.iter().map(String::as_str)
+    }
+
+    pub(crate) fn push(&mut self, text: String){
+        self.entries.push_front(text);
+        self.entries.truncate(KILL_RING_SIZE);
+        self.index = 0;
+    }
+
+    /// Extends the most recent kill, used when kills happen back to back.
+    pub(crate) fn append(&mut self, text: &str, direction_forward: bool){
+        match self.entries.front_mut(){
+            Some(entry) =>{
+                if direction_forward{
+                    entry.push_str(text);
+                }
+                else{
+                    entry.insert_str(0, text);
+                }
+                self.index = 0;
+            },
+            None => self.push(text.to_string()),
+        }
+    }
+
+    pub(crate) fn current(&self) -> Option<&str>{
+        self.entries.get(self.index).map(|s| s.as_str())
+    }
+
+    pub(crate) fn rotate(&mut self) -> Option<&str>{
+        if self.entries.is_empty(){
+            return None;
+        }
+        self.index = (self.index + 1) % self.entries.len();
+        self.current()
+    }
+}
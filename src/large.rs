@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read,Seek,SeekFrom};
+use std::path::Path;
+use std::sync::mpsc::{self,Receiver,Sender,TryRecvError};
+use std::thread;
+use crate::error::{Error,Result};
+use crate::terminal::Position;
+
+/// How much of the file is read at a time, both for the index and for searching.
+const CHUNK_SIZE: usize = 1 << 20;
+/// Longer lines are cut off at this many bytes when shown.
+const MAX_LINE: u64 = 1 << 16;
+/// Lines kept in memory before the cache is emptied.
+const CACHE_LINES: usize = 4096;
+
+/// A file too big to load, viewed through a line index. The index is built on its own
+/// thread; only lines that are looked at are read from disk, and only lines indexed so far
+/// can be looked at.
+pub(crate) struct LargeFile{
+    file: File,
+    len: u64,
+    /// Where each line starts. The last entry is where the next line would start.
+    starts: Vec<u64>,
+    scanned: u64,
+    receiver: Option<Receiver<(Vec<u64>,u64)>>,
+    cache: HashMap<usize,String>,
+}
+
+impl LargeFile{
+    pub(crate) fn open(path: &Path) -> Result<Self>{
+        let file = File::open(path).map_err(Error::file(path))?;
+        let len = file.metadata().map_err(Error::file(path))?.len();
+        let indexed = File::open(path).map_err(Error::file(path))?;
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || index(indexed, &sender));
+        Ok(Self{
+            file,
+            len,
+            starts: vec![0],
+            scanned: 0,
+            receiver: Some(receiver),
+            cache: HashMap::new(),
+        })
+    }
+
+    /// Takes in the lines indexed since the last call. Returns whether there were any.
+    pub(crate) fn poll(&mut self) -> bool{
+        let receiver = match self.receiver.as_ref(){
+            Some(receiver) => receiver,
+            None => return false,
+        };
+        let mut changed = false;
+        loop{
+            match receiver.try_recv(){
+                Ok((starts, scanned)) =>{
+                    self.starts.extend(starts);
+                    self.scanned = scanned;
+                    changed = true;
+                },
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) =>{
+                    self.receiver = None;
+                    // The index stops where the file did, even if it shrank meanwhile.
+                    self.len = self.scanned;
+                    changed = true;
+                    break;
+                },
+            }
+        }
+        changed
+    }
+
+    pub(crate) fn indexing(&self) -> bool{
+        self.receiver.is_some()
+    }
+
+    /// How much of the file has been indexed, in percent.
+    pub(crate) fn progress(&self) -> u64{
+        match self.len{
+            0 => 100,
+            len => self.scanned * 100 / len,
+        }
+    }
+
+    /// Number of lines that can be shown so far.
+    pub(crate) fn line_count(&self) -> usize{
+        let last = *self.starts.last().unwrap_or(&0);
+        // Until the index is done the last line's end isn't known; once it is, the text
+        // after the final newline is a line only if there is some.
+        if self.indexing() || last == self.len{
+            self.starts.len() - 1
+        }
+        else{
+            self.starts.len()
+        }
+    }
+
+    /// Reads line `n`, without its line ending.
+    pub(crate) fn line(&mut self, n: usize) -> Option<String>{
+        if n >= self.line_count(){
+            return None;
+        }
+        if let Some(line) = self.cache.get(&n){
+            return Some(line.clone());
+        }
+        let start = self.starts[n];
+        let end = match self.starts.get(n+1){
+            Some(&next) => next - 1,
+            None => self.len,
+        };
+        let mut bytes = vec![0; (end - start).min(MAX_LINE) as usize];
+        self.file.seek(SeekFrom::Start(start)).ok()?;
+        self.file.read_exact(&mut bytes).ok()?;
+        if bytes.last() == Some(&b'\r'){
+            bytes.pop();
+        }
+        let line = String::from_utf8_lossy(&bytes).into_owned();
+        if self.cache.len() >= CACHE_LINES{
+            self.cache.clear();
+        }
+        self.cache.insert(n, line.clone());
+        Some(line)
+    }
+
+    /// Finds the first occurrence of `pattern` at or after `from`, in the part of the file
+    /// that has been indexed.
+    pub(crate) fn find(&mut self, pattern: &str, from: Position) -> Option<Position>{
+        let pattern = pattern.as_bytes();
+        if pattern.is_empty() || from.r >= self.line_count(){
+            return None;
+        }
+        let end = match self.line_count(){
+            count if count < self.starts.len() => self.starts[count],
+            _ => self.len,
+        };
+        let mut offset = self.starts[from.r] + from.c as u64;
+        let mut chunk = vec![0; CHUNK_SIZE + pattern.len()];
+        while offset < end{
+            let want = ((end - offset) as usize).min(chunk.len());
+            self.file.seek(SeekFrom::Start(offset)).ok()?;
+            self.file.read_exact(&mut chunk[..want]).ok()?;
+            if let Some(i) = chunk[..want].windows(pattern.len()).position(|window| window == pattern){
+                let found = offset + i as u64;
+                let r = self.starts.partition_point(|&start| start <= found) - 1;
+                return Some(Position{r, c: (found - self.starts[r]) as usize});
+            }
+            // Overlap the chunks so matches across a boundary aren't missed.
+            offset += (want as u64).saturating_sub(pattern.len() as u64 - 1).max(1);
+        }
+        None
+    }
+}
+
+/// Sends where each line of `file` starts, along with how far the scan got.
+fn index(mut file: File, sender: &Sender<(Vec<u64>,u64)>){
+    let mut chunk = vec![0; CHUNK_SIZE];
+    let mut offset = 0;
+    loop{
+        let read = match file.read(&mut chunk){
+            Ok(0) | Err(_) => return,
+            Ok(read) => read,
+        };
+        let starts = chunk[..read].iter().enumerate()
+            .filter(|(_, &byte)| byte == b'\n')
+            .map(|(i, _)| offset + i as u64 + 1)
+            .collect();
+        offset += read as u64;
+        if sender.send((starts, offset)).is_err(){
+            return;
+        }
+    }
+}
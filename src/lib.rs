@@ -0,0 +1,58 @@
+//! Red is a barebones text editor. Its editing core is usable on its own: a
+//! [`Buffer`] holds the text and knows how to edit, search, undo and save it, while
+//! [`Editor`] is the terminal front end built on top. A [`Directory`] lists the files
+//! in a directory so they can be browsed the same way, and a [`HexBuffer`] holds files
+//! that aren't text as bytes.
+//!
+//! ```
+//! use red::{Buffer, Direction, Position};
+//!
+//! let mut buffer = Buffer::from_text("Fix the thing\n\nLonger description.");
+//! let start = buffer.find("thing").unwrap();
+//! let end = buffer.word_boundary(start, Direction::Forward);
+//! buffer.remove_range(start, end);
+//! buffer.insert_str(start, "parser");
+//! assert_eq!(buffer.get(0), Some("Fix the parser"));
+//! ```
+//!
+//! Running the editor takes over the terminal until the user quits:
+//!
+//! ```no_run
+//! let mut editor = red::Editor::default();
+//! editor.open_file("notes.txt").unwrap();
+//! editor.run().unwrap();
+//! ```
+//!
+//! Items exported from the crate root follow semantic versioning; anything else is
+//! an implementation detail.
+
+mod buffer;
+mod clipboard;
+mod directory;
+mod editor;
+mod error;
+mod finder;
+mod git;
+mod grep;
+mod hex;
+mod ignore;
+mod json;
+mod killring;
+mod large;
+mod lsp;
+mod quickfix;
+mod run;
+mod screen;
+mod session;
+mod shell;
+mod terminal;
+mod undo;
+
+pub use buffer::{Buffer, Direction};
+pub use directory::{Directory, Entry, EntryKind};
+pub use editor::Editor;
+pub use error::{Error, Result};
+pub use hex::HexBuffer;
+pub use screen::{Cell, Frame, Style};
+pub use session::session_path;
+pub use terminal::{Backend, CrosstermBackend, Position, WindowSize};
@@ -0,0 +1,573 @@
+use std::collections::HashMap;
+use std::io::{self,BufRead,BufReader,Read,Write};
+use std::path::{Path,PathBuf};
+use std::process::{Child,ChildStdin,ChildStdout,Command,Stdio};
+use std::sync::mpsc::{self,Receiver,Sender,TryRecvError};
+use std::thread;
+use std::time::{Duration,Instant};
+use crate::buffer::Buffer;
+use crate::json::Json;
+use crate::terminal::Position;
+
+/// How long a server gets to exit once asked to before it is killed.
+const EXIT_TIME: Duration = Duration::from_millis(500);
+
+/// How serious a diagnostic is, most serious first.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub(crate) enum Severity{
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+impl Severity{
+    pub(crate) fn name(self) -> &'static str{
+        match self{
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Information => "info",
+            Severity::Hint => "hint",
+        }
+    }
+}
+
+/// A problem the server found in a file. Positions are in the server's units, see
+/// [`LanguageServer::column`].
+#[derive(Clone, Debug)]
+pub(crate) struct Diagnostic{
+    pub(crate) start: Position,
+    pub(crate) end: Position,
+    pub(crate) severity: Severity,
+    pub(crate) message: String,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Completion{
+    pub(crate) label: String,
+    /// What accepting the completion inserts in place of the word before the cursor.
+    pub(crate) text: String,
+    pub(crate) detail: String,
+}
+
+/// Something from the server that the editor acts on.
+pub(crate) enum Reply{
+    /// The diagnostics of the file changed.
+    Diagnostics(PathBuf),
+    /// Hover text for the position asked about; empty if there is nothing to say.
+    Hover(String),
+    /// Where the symbol asked about is defined, in the server's units.
+    Definition(Option<(PathBuf,Position)>),
+    Completions(Vec<Completion>),
+    /// Something to show on the status line.
+    Message(String),
+    /// The server went away.
+    Exited,
+}
+
+/// What an outstanding request was for, and which file it was about.
+enum Request{
+    Initialize,
+    Hover(PathBuf),
+    Definition(PathBuf),
+    Completion(PathBuf),
+    Shutdown,
+}
+
+/// A language server running as a child process, spoken to over its stdin and stdout.
+/// Messages to it are written, and its messages read, on threads of their own so that a
+/// server that is slow to take them never holds up the editor; [`poll`](LanguageServer::poll)
+/// handles the ones that came in since the last call.
+pub(crate) struct LanguageServer{
+    child: Child,
+    /// Messages for the writer thread, already framed.
+    writer: Sender<String>,
+    receiver: Option<Receiver<Json>>,
+    next_id: u64,
+    pending: HashMap<u64,Request>,
+    /// Messages held back until the server has answered `initialize`.
+    queued: Option<Vec<Json>>,
+    /// Whether columns count UTF-8 bytes rather than UTF-16 code units.
+    utf8: bool,
+    /// Whether changes can be sent as edits rather than the whole text.
+    incremental: bool,
+    versions: HashMap<PathBuf,u64>,
+    diagnostics: HashMap<PathBuf,Vec<Diagnostic>>,
+}
+
+impl LanguageServer{
+    /// Runs `command` and starts initializing it for the project at `root`.
+    pub(crate) fn start(command: &[String], root: &Path) -> io::Result<Self>{
+        let program = command.first().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty command"))?;
+        let mut child = Command::new(program)
+            .args(&command[1..])
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            // Anything it prints there would land on top of the editor.
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || read_messages(stdout, &sender));
+        let (writer, messages) = mpsc::channel();
+        thread::spawn(move || write_messages(stdin, messages));
+        let mut server = Self{
+            child,
+            writer,
+            receiver: Some(receiver),
+            next_id: 0,
+            pending: HashMap::new(),
+            queued: Some(vec![]),
+            utf8: false,
+            incremental: false,
+            versions: HashMap::new(),
+            diagnostics: HashMap::new(),
+        };
+        let root_uri = uri(root);
+        let name = root.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let capabilities = Json::object([
+            ("general", Json::object([("positionEncodings", vec!["utf-8".into(), "utf-16".into()].into())])),
+            ("textDocument", Json::object([
+                ("synchronization", Json::object([("didSave", true.into())])),
+                ("hover", Json::object([("contentFormat", vec!["plaintext".into(), "markdown".into()].into())])),
+                ("completion", Json::object([("completionItem", Json::object([("snippetSupport", false.into())]))])),
+                ("definition", Json::object([])),
+                ("publishDiagnostics", Json::object([])),
+            ])),
+        ]);
+        server.request(Request::Initialize, "initialize", Json::object([
+            ("processId", (std::process::id() as u64).into()),
+            ("clientInfo", Json::object([("name", "red".into()), ("version", env!("CARGO_PKG_VERSION").into())])),
+            ("rootUri", root_uri.clone().into()),
+            ("workspaceFolders", vec![Json::object([("uri", root_uri.into()), ("name", name.into())])].into()),
+            ("capabilities", capabilities),
+        ]));
+        Ok(server)
+    }
+
+    /// Whether answers to requests are still to come.
+    pub(crate) fn busy(&self) -> bool{
+        !self.pending.is_empty()
+    }
+
+    /// Whether the server has answered `initialize`, so it can take edits and requests.
+    pub(crate) fn ready(&self) -> bool{
+        self.queued.is_none()
+    }
+
+    /// Tells the server `path` is open with the text of `buffer`.
+    pub(crate) fn open(&mut self, path: &Path, language: &str, buffer: &Buffer){
+        self.versions.insert(path.to_path_buf(), 0);
+        self.notify("textDocument/didOpen", Json::object([("textDocument", Json::object([
+            ("uri", uri(path).into()),
+            ("languageId", language.into()),
+            ("version", 0u64.into()),
+            ("text", document_text(buffer).into()),
+        ]))]));
+    }
+
+    /// Sends the edits made to `buffer` since the last call, if there were any.
+    pub(crate) fn change(&mut self, path: &Path, buffer: &mut Buffer){
+        // How to send them depends on what the server supports, so until it has said,
+        // the edits stay with the buffer.
+        if !self.ready(){
+            return;
+        }
+        let edits = buffer.take_edits();
+        let version = match self.versions.get_mut(path){
+            Some(version) if !edits.is_empty() =>{
+                *version += 1;
+                *version
+            },
+            _ => return,
+        };
+        // The buffer's lines each end in a newline, so every edit replaces whole lines and
+        // its range never needs converting between units.
+        let changes = match self.incremental{
+            true => edits.into_iter().map(|edit|{
+                let text: String = edit.lines.iter().map(|line| format!("{}\n",line)).collect();
+                Json::object([
+                    ("range", range(Position{r: edit.row, c: 0}, Position{r: edit.row + edit.removed, c: 0})),
+                    ("text", text.into()),
+                ])
+            }).collect(),
+            false => vec![Json::object([("text", document_text(buffer).into())])],
+        };
+        self.notify("textDocument/didChange", Json::object([
+            ("textDocument", Json::object([("uri", uri(path).into()), ("version", version.into())])),
+            ("contentChanges", changes.into()),
+        ]));
+    }
+
+    pub(crate) fn saved(&mut self, path: &Path){
+        self.notify("textDocument/didSave", Json::object([("textDocument", text_document(path))]));
+    }
+
+    /// Tells the server `path` was closed, dropping its diagnostics and any answers still
+    /// to come about it.
+    pub(crate) fn close(&mut self, path: &Path){
+        if self.versions.remove(path).is_none(){
+            return;
+        }
+        self.diagnostics.remove(path);
+        self.pending.retain(|_, request| !matches!(request,
+            Request::Hover(file) | Request::Definition(file) | Request::Completion(file) if file == path));
+        self.notify("textDocument/didClose", Json::object([("textDocument", text_document(path))]));
+    }
+
+    /// Asks about the symbol at `pos` of `path`, whose line is `line`.
+    pub(crate) fn hover(&mut self, path: &Path, pos: Position, line: &str){
+        let params = self.position_params(path, pos, line);
+        self.request(Request::Hover(path.to_path_buf()), "textDocument/hover", params);
+    }
+
+    pub(crate) fn definition(&mut self, path: &Path, pos: Position, line: &str){
+        let params = self.position_params(path, pos, line);
+        self.request(Request::Definition(path.to_path_buf()), "textDocument/definition", params);
+    }
+
+    pub(crate) fn completion(&mut self, path: &Path, pos: Position, line: &str){
+        let params = self.position_params(path, pos, line);
+        self.request(Request::Completion(path.to_path_buf()), "textDocument/completion", params);
+    }
+
+    /// The latest diagnostics for `path`.
+    pub(crate) fn diagnostics(&self, path: &Path) -> &[Diagnostic]{
+        self.diagnostics.get(path).map_or(&[], |diagnostics| diagnostics.as_slice())
+    }
+
+    /// The byte offset into `line` of a column the server counted in its units.
+    pub(crate) fn column(&self, line: &str, units: usize) -> usize{
+        if self.utf8{
+            let mut column = units.min(line.len());
+            while !line.is_char_boundary(column){
+                column -= 1;
+            }
+            return column;
+        }
+        let mut count = 0;
+        for (i, c) in line.char_indices(){
+            if count >= units{
+                return i;
+            }
+            count += c.len_utf16();
+        }
+        line.len()
+    }
+
+    /// A byte offset into `line` in the server's units.
+    fn units(&self, line: &str, column: usize) -> usize{
+        let head = &line[..column.min(line.len())];
+        if self.utf8{ head.len() }else{ head.encode_utf16().count() }
+    }
+
+    fn position_params(&self, path: &Path, pos: Position, line: &str) -> Json{
+        Json::object([
+            ("textDocument", text_document(path)),
+            ("position", position(Position{r: pos.r, c: self.units(line, pos.c)})),
+        ])
+    }
+
+    /// Handles the messages that came in since the last call and returns what the editor
+    /// needs to know about.
+    pub(crate) fn poll(&mut self) -> Vec<Reply>{
+        let mut replies = vec![];
+        loop{
+            let message = match self.receiver.as_ref().map(Receiver::try_recv){
+                Some(Ok(message)) => message,
+                Some(Err(TryRecvError::Empty)) | None => break,
+                Some(Err(TryRecvError::Disconnected)) =>{
+                    self.receiver = None;
+                    self.pending.clear();
+                    replies.push(Reply::Exited);
+                    break;
+                },
+            };
+            let id = message.get("id");
+            match message.get("method").as_str(){
+                Some(method) if !id.is_null() =>{
+                    let result = self.answer(method, message.get("params"));
+                    self.send(Json::object([("jsonrpc", "2.0".into()), ("id", id.clone()), ("result", result)]));
+                },
+                Some(method) => replies.extend(self.notification(method, message.get("params"))),
+                None =>{
+                    let request = match id.as_u64().and_then(|id| self.pending.remove(&id)){
+                        Some(request) => request,
+                        None => continue,
+                    };
+                    match message.get("error").get("message").as_str(){
+                        Some(error) => replies.push(Reply::Message(format!("Language server: {}",error))),
+                        None => replies.extend(self.response(request, message.get("result"))),
+                    }
+                },
+            }
+        }
+        replies
+    }
+
+    /// The result for a request the server made. Settings are left at their defaults and
+    /// everything else is simply acknowledged.
+    fn answer(&self, method: &str, params: &Json) -> Json{
+        match method{
+            "workspace/configuration" => params.get("items").items().iter().map(|_| Json::Null).collect::<Vec<_>>().into(),
+            _ => Json::Null,
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Json) -> Option<Reply>{
+        match method{
+            "textDocument/publishDiagnostics" =>{
+                let path = path_of(params.get("uri").as_str()?)?;
+                let diagnostics = params.get("diagnostics").items().iter().map(|diagnostic|{
+                    let range = diagnostic.get("range");
+                    Diagnostic{
+                        start: position_of(range.get("start")),
+                        end: position_of(range.get("end")),
+                        severity: match diagnostic.get("severity").as_u64(){
+                            Some(2) => Severity::Warning,
+                            Some(3) => Severity::Information,
+                            Some(4) => Severity::Hint,
+                            _ => Severity::Error,
+                        },
+                        message: diagnostic.get("message").as_str().unwrap_or_default().to_string(),
+                    }
+                }).collect();
+                // Files that were closed meanwhile are of no interest any more.
+                if !self.versions.contains_key(&path){
+                    return None;
+                }
+                self.diagnostics.insert(path.clone(), diagnostics);
+                Some(Reply::Diagnostics(path))
+            },
+            // Errors and warnings only; the rest is chatter.
+            "window/showMessage" if params.get("type").as_u64() <= Some(2) =>{
+                Some(Reply::Message(params.get("message").as_str()?.to_string()))
+            },
+            _ => None,
+        }
+    }
+
+    fn response(&mut self, request: Request, result: &Json) -> Option<Reply>{
+        match request{
+            Request::Initialize =>{
+                let capabilities = result.get("capabilities");
+                self.utf8 = capabilities.get("positionEncoding").as_str() == Some("utf-8");
+                let sync = match capabilities.get("textDocumentSync"){
+                    Json::Object(_) => capabilities.get("textDocumentSync").get("change"),
+                    sync => sync,
+                };
+                self.incremental = sync.as_u64() == Some(2);
+                self.notify_now("initialized", Json::object([]));
+                for message in self.queued.take().unwrap_or_default(){
+                    self.send(message);
+                }
+                None
+            },
+            Request::Hover(_) => Some(Reply::Hover(hover_text(result.get("contents")))),
+            Request::Definition(_) =>{
+                // A single location, a list of them or a list of links; the first one wins.
+                let location = if result.get("uri").is_null(){ result.at(0) }else{ result };
+                let target = match location.get("targetUri").as_str(){
+                    Some(uri) => path_of(uri).map(|path| (path, position_of(location.get("targetSelectionRange").get("start")))),
+                    None => location.get("uri").as_str().and_then(path_of).map(|path| (path, position_of(location.get("range").get("start")))),
+                };
+                Some(Reply::Definition(target))
+            },
+            Request::Completion(_) =>{
+                let items = if result.get("items").is_null(){ result }else{ result.get("items") };
+                Some(Reply::Completions(items.items().iter().map(|item|{
+                    let label = item.get("label").as_str().unwrap_or_default().to_string();
+                    let text = item.get("textEdit").get("newText").as_str()
+                        .or(item.get("insertText").as_str())
+                        .unwrap_or(&label)
+                        .to_string();
+                    Completion{label, text, detail: item.get("detail").as_str().unwrap_or_default().to_string()}
+                }).collect()))
+            },
+            Request::Shutdown => None,
+        }
+    }
+
+    fn request(&mut self, request: Request, method: &str, params: Json){
+        self.next_id += 1;
+        self.pending.insert(self.next_id, request);
+        self.send(Json::object([
+            ("jsonrpc", "2.0".into()),
+            ("id", self.next_id.into()),
+            ("method", method.into()),
+            ("params", params),
+        ]));
+    }
+
+    fn notify(&mut self, method: &str, params: Json){
+        let message = Json::object([("jsonrpc", "2.0".into()), ("method", method.into()), ("params", params)]);
+        // Until the server is initialized only `initialize` itself may be sent.
+        match &mut self.queued{
+            Some(queued) => queued.push(message),
+            None => self.send(message),
+        }
+    }
+
+    fn notify_now(&mut self, method: &str, params: Json){
+        self.send(Json::object([("jsonrpc", "2.0".into()), ("method", method.into()), ("params", params)]));
+    }
+
+    fn send(&mut self, message: Json){
+        let body = message.to_string();
+        // A server that died is noticed by the reader, so a writer that gave up can be ignored.
+        let _ = self.writer.send(format!("Content-Length: {}\r\n\r\n{}", body.len(), body));
+    }
+}
+
+/// Asks the server to shut down and exit, and kills it if it takes too long.
+impl Drop for LanguageServer{
+    fn drop(&mut self){
+        // A server that never finished initializing can't be asked nicely.
+        if self.queued.is_none(){
+            self.request(Request::Shutdown, "shutdown", Json::Null);
+            self.notify_now("exit", Json::Null);
+            let asked = Instant::now();
+            while asked.elapsed() < EXIT_TIME{
+                if !matches!(self.child.try_wait(), Ok(None)){
+                    return;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Writes the messages sent on `messages` until the server stops reading or the
+/// [`LanguageServer`] goes away.
+fn write_messages(mut stdin: ChildStdin, messages: Receiver<String>){
+    for message in messages{
+        if stdin.write_all(message.as_bytes()).and_then(|_| stdin.flush()).is_err(){
+            return;
+        }
+    }
+}
+
+/// Reads messages until the server closes its stdout or nobody is listening.
+fn read_messages(stdout: ChildStdout, sender: &Sender<Json>){
+    let mut reader = BufReader::new(stdout);
+    loop{
+        let mut length = None;
+        loop{
+            let mut header = String::new();
+            match reader.read_line(&mut header){
+                Ok(0) | Err(_) => return,
+                Ok(_) =>{},
+            }
+            let header = header.trim_end();
+            if header.is_empty(){
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length:"){
+                length = value.trim().parse().ok();
+            }
+        }
+        let mut body = vec![0; length.unwrap_or(0)];
+        if reader.read_exact(&mut body).is_err(){
+            return;
+        }
+        let message = match std::str::from_utf8(&body).ok().and_then(Json::parse){
+            Some(message) => message,
+            None => continue,
+        };
+        if sender.send(message).is_err(){
+            return;
+        }
+    }
+}
+
+/// The text of `buffer` as the server sees it: every line ends in a newline, as when the
+/// buffer is written.
+fn document_text(buffer: &Buffer) -> String{
+    (0..buffer.len()).filter_map(|r| buffer.get(r)).map(|line| format!("{}\n",line)).collect()
+}
+
+/// Hover contents come as markup, a plain string, a string tagged with its language, or a
+/// list of those.
+fn hover_text(contents: &Json) -> String{
+    match contents{
+        Json::String(text) => text.clone(),
+        Json::Array(items) => items.iter().map(hover_text).collect::<Vec<_>>().join("\n"),
+        _ => contents.get("value").as_str().unwrap_or_default().to_string(),
+    }
+}
+
+fn position(pos: Position) -> Json{
+    Json::object([("line", pos.r.into()), ("character", pos.c.into())])
+}
+
+fn range(start: Position, end: Position) -> Json{
+    Json::object([("start", position(start)), ("end", position(end))])
+}
+
+fn position_of(json: &Json) -> Position{
+    Position{
+        r: json.get("line").as_u64().unwrap_or(0) as usize,
+        c: json.get("character").as_u64().unwrap_or(0) as usize,
+    }
+}
+
+fn text_document(path: &Path) -> Json{
+    Json::object([("uri", uri(path).into())])
+}
+
+/// The `file://` URI of an absolute path, escaping everything but unreserved characters.
+pub(crate) fn uri(path: &Path) -> String{
+    let mut uri = String::from("file://");
+    for &byte in path.to_string_lossy().as_bytes(){
+        match byte{
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => uri.push(byte as char),
+            _ => uri.push_str(&format!("%{:02X}",byte)),
+        }
+    }
+    uri
+}
+
+/// The path of a `file://` URI.
+pub(crate) fn path_of(uri: &str) -> Option<PathBuf>{
+    let escaped = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::with_capacity(escaped.len());
+    let mut i = 0;
+    while i < escaped.len(){
+        let decoded = match escaped[i]{
+            b'%' => escaped.get(i+1..i+3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match decoded{
+            Some(byte) =>{
+                bytes.push(byte);
+                i += 3;
+            },
+            None =>{
+                bytes.push(escaped[i]);
+                i += 1;
+            },
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+/// The `languageId` servers expect for files with `extension`.
+pub(crate) fn language_id(extension: &str) -> &str{
+    match extension{
+        "rs" => "rust",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hpp" | "hh" => "cpp",
+        "py" => "python",
+        "js" => "javascript",
+        "ts" => "typescript",
+        "sh" => "shellscript",
+        "md" => "markdown",
+        extension => extension,
+    }
+}
@@ -1,15 +1,139 @@
-mod terminal;
-mod buffer;
-mod editor;
-use editor::Editor;
+use clap::Parser;
+use red::{Backend, Buffer, CrosstermBackend, Editor, HexBuffer};
+use std::io::{IsTerminal, Read};
+use std::path::{Path, PathBuf};
+
+/// A barebones text editor.
+#[derive(Parser)]
+#[command(version)]
+struct Args{
+    /// View files read-only with less-style keys; the default when run as `redview`
+    #[arg(short = 'R', long = "view")]
+    view: bool,
+    /// Show line numbers
+    #[arg(short = 'n', long)]
+    line_numbers: bool,
+    /// Offer completions after this many characters of a word; 0 only offers them on Alt + /
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    complete_after: usize,
+    /// Format files ending in .EXT with COMMAND before writing them, e.g. rs='rustfmt --emit stdout'
+    #[arg(long = "format", value_name = "EXT=COMMAND")]
+    formatters: Vec<String>,
+    /// Use COMMAND as the language server for files ending in .EXT, e.g. go='gopls serve'; an empty COMMAND turns servers off for them
+    #[arg(long = "lsp", value_name = "EXT=COMMAND")]
+    language_servers: Vec<String>,
+    /// Don't start language servers, except for the extensions given with --lsp
+    #[arg(long)]
+    no_lsp: bool,
+    /// Go through the places named in FILE, as written by a compiler or grep; - reads them from standard input
+    #[arg(short = 'q', long = "errors", value_name = "FILE")]
+    errors: Option<PathBuf>,
+    /// Restore and save the session called NAME rather than the default one
+    #[arg(long, value_name = "NAME", default_value = "default")]
+    session: String,
+    /// File to open; without one, text piped to standard input is shown
+    file: Option<PathBuf>,
+}
 
 fn main() {
-    // let args = Args::parse();
+    // A panic message printed inside the alternate screen is lost, so leave it first.
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info|{
+        let _ = CrosstermBackend::default().restore();
+        default_hook(info);
+    }));
+    let args = Args::parse();
+    let viewer = args.view || invoked_as("redview");
     let mut e = Editor::default();
-    // println!("{}",args);
-    // e.line_numbers = args.line_numbers;
-    e.run();
-    editor::cleanup();
-    crossterm::terminal::disable_raw_mode().expect("red: error: failed to disable raw mode!");
+    e.line_numbers = args.line_numbers;
+    e.complete_after = args.complete_after;
+    for formatter in &args.formatters{
+        match formatter.split_once('='){
+            Some((extension, command)) => e.set_formatter(extension.trim_start_matches('.'), command),
+            None =>{
+                eprintln!("red: --format takes EXT=COMMAND, not {}", formatter);
+                std::process::exit(2);
+            },
+        }
+    }
+    if args.no_lsp{
+        e.clear_language_servers();
+    }
+    for server in &args.language_servers{
+        match server.split_once('='){
+            Some((extension, command)) => e.set_language_server(extension.trim_start_matches('.'), command.split_whitespace().map(String::from).collect()),
+            None =>{
+                eprintln!("red: --lsp takes EXT=COMMAND, not {}", server);
+                std::process::exit(2);
+            },
+        }
+    }
+    e.set_viewer(viewer);
+    // A session that can't be read is left alone rather than saved over.
+    if let Some(path) = red::session_path(&args.session){
+        if let Err(err) = e.load_session(path){
+            eprintln!("red: not restoring the session: {}", err);
+        }
+    }
+    let opened = match &args.file{
+        Some(file) if file.exists() => e.open_file(&file.to_string_lossy()),
+        Some(file) if viewer =>{
+            eprintln!("red: {}: No such file or directory", file.display());
+            std::process::exit(1);
+        },
+        // A new file is created when it is first written.
+        Some(file) =>{
+            e.open_buffer(Buffer::with_path(file));
+            Ok(())
+        },
+        None if args.errors.is_none() && !std::io::stdin().is_terminal() => read_stdin().map(|bytes|{
+            // Binary input is shown in hex, like binary files.
+            if bytes.contains(&0){
+                let mut buffer = HexBuffer::from_bytes(&bytes);
+                buffer.name = String::from("stdin");
+                e.open_hex(buffer);
+            }
+            else{
+                let mut buffer = Buffer::from_text(&String::from_utf8_lossy(&bytes));
+                buffer.name = String::from("stdin");
+                e.open_buffer(buffer);
+            }
+        }),
+        None if args.errors.is_none() => e.restore_session(),
+        None => Ok(()),
+    };
+    let opened = opened.and_then(|_| match &args.errors{
+        Some(errors) => read_errors(errors).and_then(|output| e.load_errors(&output, std::env::current_dir().unwrap_or_default())),
+        None => Ok(()),
+    });
+    if let Err(e) = opened.and_then(|_| e.run()){
+        eprintln!("red: error: {}",e);
+        std::process::exit(1);
+    }
+    // Whoever started red as their editor learns that the edit was given up.
+    if e.aborted(){
+        std::process::exit(1);
+    }
+}
+
+/// Whether the program was started under `name`, e.g. through a symlink.
+fn invoked_as(name: &str) -> bool{
+    std::env::args_os().next()
+        .and_then(|arg0| Path::new(&arg0).file_stem().map(|stem| stem == name))
+        .unwrap_or(false)
+}
+
+/// The text of `path`, or of standard input for `-`.
+fn read_errors(path: &Path) -> red::Result<String>{
+    let bytes = match path.to_str(){
+        Some("-") => read_stdin()?,
+        _ => std::fs::read(path).map_err(|source| red::Error::File{path: path.to_path_buf(), source})?,
+    };
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
 
+fn read_stdin() -> red::Result<Vec<u8>>{
+    let mut bytes = vec![];
+    std::io::stdin().read_to_end(&mut bytes).map_err(|source| red::Error::File{path: PathBuf::from("stdin"), source})?;
+    Ok(bytes)
 }
@@ -0,0 +1,129 @@
+use std::path::{Path,PathBuf};
+use crate::buffer::{Buffer,Direction};
+use crate::run::Location;
+use crate::terminal::Position;
+
+/// A place in a file named by a compiler or grep, with what it said about it.
+pub(crate) struct Entry{
+    /// Canonical, so it can be compared with the file of a buffer.
+    pub(crate) path: PathBuf,
+    pub(crate) location: Location,
+    pub(crate) message: String,
+    /// The line of the output that named it.
+    row: usize,
+}
+
+/// The locations in the output of a compiler, grep or anything else that writes
+/// `path:line[:column]`, stepped through one at a time. The entries of the file being
+/// edited are marks in its buffer, so they move along with the lines they point at.
+pub(crate) struct Quickfix{
+    dir: PathBuf,
+    entries: Vec<Entry>,
+    /// The entry visited last by next/previous.
+    current: Option<usize>,
+    /// The last line that started a message, which rustc names the place of on a
+    /// `--> path:line:column` line of its own.
+    headline: Option<String>,
+    /// Lines of output read so far.
+    rows: usize,
+    /// The file whose entries are marked in the buffer, by their index.
+    marked: Option<PathBuf>,
+}
+
+impl Quickfix{
+    /// An empty list for output that names files relative to `dir`.
+    pub(crate) fn new(dir: PathBuf) -> Self{
+        Self{dir, entries: vec![], current: None, headline: None, rows: 0, marked: None}
+    }
+
+    /// Reads more lines of output. Places in files that don't exist are left out, which
+    /// also keeps out things like times that only look like locations.
+    pub(crate) fn extend<'a>(&mut self, lines: impl IntoIterator<Item = &'a str>){
+        for line in lines{
+            let row = self.rows;
+            self.rows += 1;
+            // rustc's `::: path` lines point at related code elsewhere, not at the problem.
+            if line.trim_start().starts_with(":::"){
+                continue;
+            }
+            match Location::split(line){
+                Some((location, rest)) =>{
+                    let path = match self.dir.join(&location.path).canonicalize(){
+                        Ok(path) if path.is_file() => path,
+                        _ => continue,
+                    };
+                    let message = rest.trim().trim_start_matches(':').trim();
+                    let message = match (message.is_empty(), &self.headline){
+                        (true, Some(headline)) => headline.clone(),
+                        _ => message.to_string(),
+                    };
+                    self.entries.push(Entry{path, location, message, row});
+                },
+                None if !line.is_empty() && !line.starts_with(char::is_whitespace) =>{
+                    self.headline = Some(line.to_string());
+                },
+                None =>{},
+            }
+        }
+    }
+
+    pub(crate) fn entries(&self) -> &[Entry]{
+        &self.entries
+    }
+
+    /// The entry named on line `row` of the output.
+    pub(crate) fn entry_at(&self, row: usize) -> Option<usize>{
+        self.entries.iter().position(|entry| entry.row == row)
+    }
+
+    /// Makes entry `index` the current one and returns it.
+    pub(crate) fn visit(&mut self, index: usize) -> Option<&Entry>{
+        let entry = self.entries.get(index)?;
+        self.current = Some(index);
+        Some(entry)
+    }
+
+    /// The index of the entry after (or before) the current one, if there is one.
+    pub(crate) fn step(&self, direction: Direction) -> Option<usize>{
+        match (self.current, direction){
+            (None, Direction::Forward) if !self.entries.is_empty() => Some(0),
+            (Some(current), Direction::Forward) if current + 1 < self.entries.len() => Some(current + 1),
+            (Some(current), Direction::Backward) if current > 0 => Some(current - 1),
+            _ => None,
+        }
+    }
+
+    /// Marks the entries of the file in `buffer`, if it has any, so they move along with
+    /// its edits. Call it whenever a different file, or the same one afresh, is loaded into
+    /// the buffer, and when more entries come in.
+    pub(crate) fn watch(&mut self, buffer: &mut Buffer){
+        self.follow(buffer);
+        buffer.clear_places();
+        self.marked = None;
+        let path = match buffer.path().canonicalize(){
+            Ok(path) if self.entries.iter().any(|entry| entry.path == path) => path,
+            _ => return,
+        };
+        for (i, entry) in self.entries.iter().enumerate().filter(|(_, entry)| entry.path == path){
+            buffer.set_place(i, Position{r: entry.location.line, c: 0});
+        }
+        self.marked = Some(path);
+    }
+
+    /// Moves the entries of the file in `buffer` to where their marks are now.
+    pub(crate) fn follow(&mut self, buffer: &Buffer){
+        match &self.marked{
+            Some(marked) if same_path(marked, buffer.path()) =>{},
+            _ => return,
+        }
+        for (i, pos) in buffer.places(){
+            if let Some(entry) = self.entries.get_mut(i){
+                entry.location.line = pos.r;
+            }
+        }
+    }
+}
+
+fn same_path(canonical: &Path, path: &Path) -> bool{
+    path.canonicalize().is_ok_and(|path| path == canonical)
+}
@@ -0,0 +1,227 @@
+use std::io::{BufRead,BufReader,Read};
+use std::path::{Path,PathBuf};
+use std::process::{Child,Command,ExitStatus,Stdio};
+use std::sync::mpsc::{self,Receiver,Sender,TryRecvError};
+use std::thread;
+
+/// A place in a file named in a line of output, as `path:line` or `path:line:column`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) struct Location{
+    /// As written, so relative to the directory the command ran in unless it is absolute.
+    pub(crate) path: PathBuf,
+    /// Counted from zero.
+    pub(crate) line: usize,
+    /// Characters into the line, counted from zero; zero when the output gave no column.
+    pub(crate) column: usize,
+}
+
+impl Location{
+    /// The first `path:line[:column]` in `text`, such as `src/main.rs:3:5: error` or
+    /// `--> src/main.rs:3:5`. Line and column are counted from one in the text.
+    pub(crate) fn find(text: &str) -> Option<Self>{
+        Self::split(text).map(|(location, _)| location)
+    }
+
+    /// The first location in `text` and what follows it, which for compilers and grep is
+    /// the message or the matching line.
+    pub(crate) fn split(text: &str) -> Option<(Self,&str)>{
+        let mut rest = text;
+        loop{
+            rest = rest.trim_start();
+            if rest.is_empty(){
+                return None;
+            }
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            if let Some((location, after)) = Self::parse(&rest[..end]){
+                // What follows the numbers in the same word, as in grep's `path:3:text`,
+                // belongs to the message.
+                let message = if after.is_empty(){ &rest[end..] }else{ &rest[end - after.len()..] };
+                return Some((location, message));
+            }
+            rest = &rest[end..];
+        }
+    }
+
+    fn parse(word: &str) -> Option<(Self,&str)>{
+        let (path, rest) = word.split_once(':')?;
+        if path.is_empty(){
+            return None;
+        }
+        let (line, rest) = number(rest)?;
+        let (column, rest) = number(rest).unwrap_or((1, rest));
+        Some((Self{path: PathBuf::from(path), line: line.checked_sub(1)?, column: column.saturating_sub(1)}, rest))
+    }
+
+    /// The byte offset of the column in `line`, at its end if the line is shorter.
+    pub(crate) fn offset_in(&self, line: &str) -> usize{
+        line.char_indices().nth(self.column).map_or(line.len(), |(i, _)| i)
+    }
+}
+
+/// The number `part` starts with, if it ends there or at a colon, and what comes after
+/// that colon.
+fn number(part: &str) -> Option<(usize,&str)>{
+    let digits = part.bytes().take_while(u8::is_ascii_digit).count();
+    let rest = &part[digits..];
+    if digits == 0 || !(rest.is_empty() || rest.starts_with(':')){
+        return None;
+    }
+    Some((part[..digits].parse().ok()?, rest.strip_prefix(':').unwrap_or(rest)))
+}
+
+/// A shell command running in the background. Its standard output and standard error are
+/// read on their own threads, a line at a time; [`poll`](Run::poll) picks up the lines
+/// written since the last call. Dropping it kills the command.
+pub(crate) struct Run{
+    command: String,
+    dir: PathBuf,
+    lines: Vec<String>,
+    child: Option<Child>,
+    receiver: Option<Receiver<String>>,
+    status: Option<Result<ExitStatus,String>>,
+}
+
+impl Run{
+    /// Starts `command` through `sh -c` in `dir`.
+    pub(crate) fn new(dir: PathBuf, command: &str) -> Self{
+        let (sender, receiver) = mpsc::channel();
+        let spawned = Command::new("sh").arg("-c").arg(command)
+            .current_dir(&dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn();
+        let (child, status) = match spawned{
+            Ok(mut child) =>{
+                forward(child.stdout.take().expect("stdout is piped"), sender.clone());
+                forward(child.stderr.take().expect("stderr is piped"), sender);
+                (Some(child), None)
+            },
+            Err(e) => (None, Some(Err(format!("Couldn't run {}: {}",command,e)))),
+        };
+        Self{
+            command: command.to_string(),
+            dir,
+            lines: vec![],
+            child,
+            receiver: Some(receiver),
+            status,
+        }
+    }
+
+    pub(crate) fn command(&self) -> &str{
+        &self.command
+    }
+
+    pub(crate) fn dir(&self) -> &Path{
+        self.dir.as_path()
+    }
+
+    /// Whether the command hasn't finished yet.
+    pub(crate) fn running(&self) -> bool{
+        self.status.is_none()
+    }
+
+    /// Takes in the lines written since the last call and returns how many there were.
+    /// The command having finished also counts as news, with no lines.
+    pub(crate) fn poll(&mut self) -> Option<usize>{
+        if !self.running(){
+            return None;
+        }
+        let before = self.lines.len();
+        if let Some(receiver) = &self.receiver{
+            loop{
+                match receiver.try_recv(){
+                    Ok(line) => self.lines.push(line),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) =>{
+                        self.receiver = None;
+                        break;
+                    },
+                }
+            }
+        }
+        // Both pipes being closed doesn't mean the command is done, so only ask then.
+        if self.receiver.is_none(){
+            if let Some(child) = &mut self.child{
+                match child.try_wait(){
+                    Ok(Some(status)) => self.status = Some(Ok(status)),
+                    Ok(None) =>{},
+                    Err(e) => self.status = Some(Err(e.to_string())),
+                }
+            }
+            if !self.running(){
+                return Some(self.lines.len() - before);
+            }
+        }
+        match self.lines.len() - before{
+            0 => None,
+            new => Some(new),
+        }
+    }
+
+    pub(crate) fn lines(&self) -> &[String]{
+        &self.lines
+    }
+
+    /// The first line of the output buffer.
+    pub(crate) fn header(&self) -> String{
+        format!("$ {}    (in {})",self.command,self.dir.display())
+    }
+
+    /// How the command ended, once it has.
+    pub(crate) fn outcome(&self) -> Option<String>{
+        match self.status.as_ref()?{
+            Ok(status) if status.success() => Some(format!("{} finished.",self.command)),
+            Ok(status) => Some(format!("{} failed ({}).",self.command,status)),
+            Err(e) => Some(e.clone()),
+        }
+    }
+}
+
+impl Drop for Run{
+    fn drop(&mut self){
+        if let Some(child) = &mut self.child{
+            if self.status.is_none(){
+                let _ = child.kill();
+                let _ = child.wait();
+            }
+        }
+    }
+}
+
+/// Sends the lines of `pipe` on `sender` from a thread of its own, until either end closes.
+fn forward(pipe: impl Read + Send + 'static, sender: Sender<String>){
+    thread::spawn(move ||{
+        let mut reader = BufReader::new(pipe);
+        let mut bytes = vec![];
+        loop{
+            bytes.clear();
+            match reader.read_until(b'\n', &mut bytes){
+                Ok(0) | Err(_) => break,
+                Ok(_) =>{
+                    let line = String::from_utf8_lossy(&bytes);
+                    let line = line.trim_end_matches(['\n', '\r']);
+                    if sender.send(line.to_string()).is_err(){
+                        break;
+                    }
+                },
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn locations_are_found_in_compiler_and_grep_output(){
+        let location = |path: &str, line, column| Some(Location{path: path.into(), line, column});
+        assert_eq!(Location::find("  --> src/main.rs:3:5"), location("src/main.rs", 2, 4));
+        assert_eq!(Location::find("main.c:10:2: error: expected ';'"), location("main.c", 9, 1));
+        assert_eq!(Location::find("src/lib.rs:7: let x = 1;"), location("src/lib.rs", 6, 0));
+        assert_eq!(Location::find("error[E0425]: cannot find value `x` in this scope"), None);
+        assert_eq!(Location::find("took 12 s"), None);
+    }
+}
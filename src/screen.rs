@@ -0,0 +1,257 @@
+use std::cmp;
+use std::io::{self, Write};
+use crossterm::{cursor, QueueableCommand};
+use crossterm::style::{Attribute, Color, Colors, Print, SetAttribute, SetColors};
+
+/// How a cell is painted.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Style{
+    pub fg: Color,
+    pub bg: Color,
+    pub reverse: bool,
+    pub underline: bool,
+}
+
+impl Default for Style{
+    fn default() -> Self{
+        Self{fg: Color::Reset, bg: Color::Reset, reverse: false, underline: false}
+    }
+}
+
+impl Style{
+    pub fn colors(fg: Color, bg: Color) -> Self{
+        Self{fg, bg, ..Default::default()}
+    }
+
+    pub fn reversed() -> Self{
+        Self{reverse: true, ..Default::default()}
+    }
+}
+
+/// A single character on screen. Every character takes one cell, so those a terminal
+/// draws two columns wide, like CJK ideographs and most emoji, push the rest of their row
+/// one column to the right of where the frame has it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Cell{
+    pub ch: char,
+    pub style: Style,
+}
+
+impl Default for Cell{
+    fn default() -> Self{
+        Self{ch: ' ', style: Default::default()}
+    }
+}
+
+/// One screen's worth of cells, built in memory each time the editor draws.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Frame{
+    cols: u16,
+    rows: u16,
+    cells: Vec<Cell>,
+    /// Where the cursor should be shown as `(column, row)`, or `None` to hide it.
+    pub cursor: Option<(u16,u16)>,
+}
+
+impl Frame{
+    /// A blank frame of the given size.
+    pub fn new(cols: u16, rows: u16) -> Self{
+        Self{cols, rows, cells: vec![Default::default(); cols as usize * rows as usize], cursor: None}
+    }
+
+    pub fn cols(&self) -> u16{
+        self.cols
+    }
+
+    pub fn rows(&self) -> u16{
+        self.rows
+    }
+
+    /// The cell at column `x` of row `y`, or `None` outside the frame.
+    pub fn cell(&self, x: u16, y: u16) -> Option<&Cell>{
+        if x >= self.cols || y >= self.rows{
+            return None;
+        }
+        self.cells.get(y as usize * self.cols as usize + x as usize)
+    }
+
+    /// Writes `text` starting at `(x, y)`, clipping at the right edge, and returns the
+    /// column after the last character written.
+    pub fn put_str(&mut self, x: u16, y: u16, text: &str, style: Style) -> u16{
+        if y >= self.rows{
+            return x;
+        }
+        let mut x = x;
+        for ch in text.chars(){
+            if x >= self.cols{
+                break;
+            }
+            let ch = if ch.is_control() {' '} else {ch};
+            self.cells[y as usize * self.cols as usize + x as usize] = Cell{ch, style};
+            x += 1;
+        }
+        x
+    }
+
+    /// The text of row `y` with trailing blanks removed.
+    pub fn row_text(&self, y: u16) -> String{
+        (0..self.cols)
+            .filter_map(|x| self.cell(x, y).map(|c| c.ch))
+            .collect::<String>()
+            .trim_end()
+            .to_string()
+    }
+
+    /// Changes the style of `width` cells of row `y` from column `x`, keeping their text.
+    pub fn restyle(&mut self, x: u16, y: u16, width: u16, change: impl Fn(Style) -> Style){
+        if y >= self.rows{
+            return;
+        }
+        for x in x..cmp::min(x.saturating_add(width), self.cols){
+            let cell = &mut self.cells[y as usize * self.cols as usize + x as usize];
+            cell.style = change(cell.style);
+        }
+    }
+
+    /// Paints the rest of row `y` from column `x` with `style`.
+    pub fn fill(&mut self, x: u16, y: u16, style: Style){
+        for x in x..self.cols{
+            self.put_str(x, y, " ", style);
+        }
+    }
+}
+
+/// A box of lines drawn over the text next to a point, like a list of completions.
+pub(crate) struct Overlay{
+    pub(crate) lines: Vec<String>,
+    /// The line drawn reversed; the box scrolls to keep it in view.
+    pub(crate) selected: Option<usize>,
+    /// Most lines shown at once.
+    pub(crate) max_rows: usize,
+}
+
+impl Frame{
+    /// Draws `overlay` next to `(x, y)`: from the row below if it fits there, else ending
+    /// on the row above. Rows from `bottom` on are left alone, and so is row `y`.
+    pub(crate) fn overlay(&mut self, overlay: &Overlay, x: u16, y: u16, bottom: u16){
+        let (x, y, bottom) = (x as usize, y as usize, cmp::min(bottom, self.rows) as usize);
+        if y >= bottom{
+            return;
+        }
+        let below = bottom - y - 1;
+        let height = cmp::min(cmp::min(overlay.lines.len(), overlay.max_rows), cmp::max(below, y));
+        let top = if below >= height{ y + 1 }else{ y - height };
+        let width = cmp::min(overlay.lines.iter().map(|line| line.chars().count()).max().unwrap_or(0) + 2, self.cols as usize);
+        let x = cmp::min(x, self.cols as usize - width) as u16;
+        let skip = overlay.selected.map_or(0, |selected| (selected + 1).saturating_sub(height));
+        for (i, line) in overlay.lines.iter().enumerate().skip(skip).take(height){
+            let style = if overlay.selected == Some(i){ Style::reversed() }else{ Style::colors(Color::White, Color::DarkGrey) };
+            self.put_str(x, (top + i - skip) as u16, &format!(" {:<w$}", line, w = width - 1), style);
+        }
+    }
+}
+
+/// Remembers the last frame shown on the terminal so that only changed cells are
+/// sent the next time round.
+#[derive(Default)]
+pub(crate) struct Screen{
+    previous: Option<Frame>,
+}
+
+impl Screen{
+    /// Forgets what is on the terminal, forcing the next render to redraw everything.
+    pub(crate) fn invalidate(&mut self){
+        self.previous = None;
+    }
+
+    /// Sends `frame` to `out`, skipping the cells that are the same as in the frame
+    /// rendered before. Nothing at all is written when the whole frame is unchanged.
+    pub(crate) fn render(&mut self, frame: Frame, out: &mut impl Write) -> io::Result<()>{
+        if self.previous.as_ref() == Some(&frame){
+            return Ok(());
+        }
+        let previous = self.previous.take().filter(|p| p.cols == frame.cols && p.rows == frame.rows);
+        if previous.is_none(){
+            out.queue(crossterm::terminal::Clear(crossterm::terminal::ClearType::All))?;
+        }
+        out.queue(cursor::Hide)?;
+        let mut style: Option<Style> = None;
+        for y in 0..frame.rows{
+            let mut next_x: Option<u16> = None;
+            for x in 0..frame.cols{
+                let cell = frame.cell(x, y).copied().unwrap_or_default();
+                if previous.as_ref().and_then(|p| p.cell(x, y)) == Some(&cell){
+                    continue;
+                }
+                if next_x != Some(x){
+                    out.queue(cursor::MoveTo(x, y))?;
+                }
+                if style != Some(cell.style){
+                    out.queue(SetAttribute(Attribute::Reset))?;
+                    out.queue(SetColors(Colors::new(cell.style.fg, cell.style.bg)))?;
+                    if cell.style.reverse{
+                        out.queue(SetAttribute(Attribute::Reverse))?;
+                    }
+                    if cell.style.underline{
+                        out.queue(SetAttribute(Attribute::Underlined))?;
+                    }
+                    style = Some(cell.style);
+                }
+                out.queue(Print(cell.ch))?;
+                next_x = Some(x + 1);
+            }
+        }
+        out.queue(SetAttribute(Attribute::Reset))?;
+        if let Some((x, y)) = frame.cursor{
+            out.queue(cursor::MoveTo(x, y))?;
+            out.queue(cursor::Show)?;
+        }
+        out.flush()?;
+        self.previous = Some(frame);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn rendering_the_same_frame_again_writes_nothing(){
+        let mut screen = Screen::default();
+        let mut frame = Frame::new(10, 3);
+        frame.put_str(0, 0, "hello", Style::default());
+        frame.cursor = Some((5, 0));
+        let mut out = vec![];
+        screen.render(frame.clone(), &mut out).unwrap();
+        assert!(!out.is_empty());
+
+        let mut out = vec![];
+        screen.render(frame, &mut out).unwrap();
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn only_the_cells_that_changed_are_redrawn(){
+        let mut screen = Screen::default();
+        let mut frame = Frame::new(10, 3);
+        frame.put_str(0, 1, "hello", Style::default());
+        screen.render(frame.clone(), &mut vec![]).unwrap();
+
+        frame.put_str(1, 1, "a", Style::default());
+        frame.cursor = Some((2, 1));
+        let mut out = vec![];
+        screen.render(frame, &mut out).unwrap();
+
+        let mut expected = vec![];
+        expected.queue(cursor::Hide).unwrap()
+            .queue(cursor::MoveTo(1, 1)).unwrap()
+            .queue(SetAttribute(Attribute::Reset)).unwrap()
+            .queue(SetColors(Colors::new(Color::Reset, Color::Reset))).unwrap()
+            .queue(Print('a')).unwrap()
+            .queue(SetAttribute(Attribute::Reset)).unwrap()
+            .queue(cursor::MoveTo(2, 1)).unwrap()
+            .queue(cursor::Show).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), String::from_utf8(expected).unwrap());
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path,PathBuf};
+use crate::error::{Error,Result};
+use crate::json::Json;
+use crate::terminal::Position;
+
+/// Answers kept for each kind of prompt.
+const HISTORY_SIZE: usize = 100;
+/// Files whose place is remembered, the most recently left first.
+const PLACES_KEPT: usize = 100;
+
+/// The kinds of prompt whose answers are kept, each with a history of its own.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub(crate) enum Topic{
+    Files,
+    Search,
+    ProjectSearch,
+    Commands,
+    Filters,
+    Lines,
+}
+
+impl Topic{
+    const ALL: [Topic; 6] = [Topic::Files, Topic::Search, Topic::ProjectSearch, Topic::Commands, Topic::Filters, Topic::Lines];
+
+    /// What the history is saved under.
+    fn key(self) -> &'static str{
+        match self{
+            Topic::Files => "files",
+            Topic::Search => "search",
+            Topic::ProjectSearch => "project search",
+            Topic::Commands => "commands",
+            Topic::Filters => "filters",
+            Topic::Lines => "lines",
+        }
+    }
+}
+
+/// Where the cursor and the view were when a file was left.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) struct Place{
+    /// Canonical, so the file is recognized however it is opened again.
+    pub(crate) path: PathBuf,
+    pub(crate) cursor: Position,
+    pub(crate) scroll: usize,
+    /// The file's named marks.
+    pub(crate) marks: Vec<(char,Position)>,
+}
+
+/// What carries over from one run of the editor to the next: where each file was left,
+/// the answers given to prompts and the kill ring.
+#[derive(Default, Debug, PartialEq)]
+pub(crate) struct Session{
+    /// The most recently left first; the first is the file that was open on quitting.
+    places: Vec<Place>,
+    /// Oldest first.
+    history: HashMap<Topic,Vec<String>>,
+    /// The most recent first.
+    pub(crate) kills: Vec<String>,
+}
+
+impl Session{
+    /// Reads the session saved at `path`; a session that was never saved is empty.
+    pub(crate) fn load(path: &Path) -> Result<Self>{
+        let text = match fs::read_to_string(path){
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(Error::file(path)(e)),
+        };
+        match Json::parse(&text){
+            Some(json) => Ok(Self::from_json(&json)),
+            None => Err(Error::file(path)(io::Error::new(io::ErrorKind::InvalidData, "not a saved session"))),
+        }
+    }
+
+    /// Writes the session to `path`, creating the directories it goes in.
+    pub(crate) fn save(&self, path: &Path) -> Result<()>{
+        if let Some(dir) = path.parent(){
+            fs::create_dir_all(dir).map_err(Error::file(dir))?;
+        }
+        fs::write(path, format!("{}\n",self.to_json())).map_err(Error::file(path))
+    }
+
+    /// Notes where `place`'s file was left, in front of the others.
+    pub(crate) fn remember(&mut self, place: Place){
+        self.places.retain(|other| other.path != place.path);
+        self.places.insert(0, place);
+        self.places.truncate(PLACES_KEPT);
+    }
+
+    /// Where the file at `path` was left last.
+    pub(crate) fn place(&self, path: &Path) -> Option<&Place>{
+        let path = path.canonicalize().ok()?;
+        self.places.iter().find(|place| place.path == path)
+    }
+
+    /// Where the file with the mark called `name` was left.
+    pub(crate) fn place_with_mark(&self, name: char) -> Option<&Place>{
+        self.places.iter().find(|place| place.marks.iter().any(|&(mark, _)| mark == name))
+    }
+
+    /// Drops the mark called `name` from every file left.
+    pub(crate) fn forget_mark(&mut self, name: char){
+        for place in &mut self.places{
+            place.marks.retain(|&(mark, _)| mark != name);
+        }
+    }
+
+    /// The file that was open on quitting.
+    pub(crate) fn last_place(&self) -> Option<&Place>{
+        self.places.first()
+    }
+
+    /// The answers given to prompts of `topic`, oldest first.
+    pub(crate) fn history(&self, topic: Topic) -> &[String]{
+        self.history.get(&topic).map_or(&[], Vec::as_slice)
+    }
+
+    /// Adds `answer` as the newest answer to prompts of `topic`, moving it there if it
+    /// was given before.
+    pub(crate) fn add_history(&mut self, topic: Topic, answer: &str){
+        let answers = self.history.entry(topic).or_default();
+        answers.retain(|other| other != answer);
+        answers.push(answer.to_string());
+        if answers.len() > HISTORY_SIZE{
+            answers.remove(0);
+        }
+    }
+
+    fn to_json(&self) -> Json{
+        let places = self.places.iter().map(|place| Json::object([
+            ("path", Json::from(place.path.to_string_lossy().into_owned())),
+            ("line", Json::from(place.cursor.r)),
+            ("column", Json::from(place.cursor.c)),
+            ("scroll", Json::from(place.scroll)),
+            ("marks", Json::Object(place.marks.iter().map(|&(name, pos)| (name.to_string(), Json::from(vec![Json::from(pos.r), Json::from(pos.c)]))).collect())),
+        ])).collect::<Vec<_>>();
+        let history = Topic::ALL.iter()
+            .map(|&topic| (topic.key(), strings(self.history(topic))))
+            .filter(|(_, answers)| !answers.items().is_empty());
+        Json::object([
+            ("places", Json::from(places)),
+            ("history", Json::object(history)),
+            ("kills", strings(&self.kills)),
+        ])
+    }
+
+    /// Reads what [`to_json`](Session::to_json) wrote, skipping whatever doesn't fit.
+    fn from_json(json: &Json) -> Self{
+        let mut session = Self::default();
+        for place in json.get("places").items(){
+            let number = |key| place.get(key).as_u64().map_or(0, |n| n as usize);
+            let marks = match place.get("marks"){
+                Json::Object(marks) => marks.iter().filter_map(|(name, pos)|{
+                    let mut chars = name.chars();
+                    let name = chars.next().filter(|_| chars.next().is_none())?;
+                    Some((name, Position{r: pos.at(0).as_u64()? as usize, c: pos.at(1).as_u64()? as usize}))
+                }).collect(),
+                _ => vec![],
+            };
+            if let Some(path) = place.get("path").as_str(){
+                session.places.push(Place{
+                    path: PathBuf::from(path),
+                    cursor: Position{r: number("line"), c: number("column")},
+                    scroll: number("scroll"),
+                    marks,
+                });
+            }
+        }
+        for topic in Topic::ALL{
+            let answers = json.get("history").get(topic.key()).items().iter().filter_map(Json::as_str);
+            session.history.insert(topic, answers.map(String::from).collect());
+        }
+        session.kills = json.get("kills").items().iter().filter_map(Json::as_str).map(String::from).collect();
+        session
+    }
+}
+
+fn strings(items: &[String]) -> Json{
+    Json::from(items.iter().map(|item| Json::from(item.as_str())).collect::<Vec<_>>())
+}
+
+/// Where the session called `name` is saved: under `$XDG_STATE_HOME/red/sessions`, or
+/// `~/.local/state/red/sessions` without it. `None` if there is no home directory either.
+pub fn session_path(name: &str) -> Option<PathBuf>{
+    let state = std::env::var_os("XDG_STATE_HOME").filter(|dir| !dir.is_empty()).map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").filter(|dir| !dir.is_empty()).map(|home| Path::new(&home).join(".local/state")))?;
+    Some(state.join("red").join("sessions").join(format!("{}.json",name)))
+}
@@ -0,0 +1,45 @@
+use std::io::{Read,Write};
+use std::path::Path;
+use std::process::{Command,Stdio};
+use std::thread;
+
+/// Runs `command` through `sh -c` in `dir` with `input` on its standard input, and returns
+/// its standard output. If it exits with a failure or writes anything to standard error,
+/// what went wrong comes back instead, one line per element, so nothing is replaced with a
+/// half-done result.
+pub(crate) fn filter(command: &str, input: &str, dir: &Path) -> Result<String,Vec<String>>{
+    let mut child = Command::new("sh").arg("-c").arg(command)
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| vec![format!("Couldn't run {}: {}",command,e)])?;
+    // Writing from another thread keeps a command that answers before it has read
+    // everything from blocking on a full pipe.
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = input.to_string();
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let reader = thread::spawn(move ||{
+        let mut errors = vec![];
+        stderr.read_to_end(&mut errors).map(|_| errors)
+    });
+    let mut output = vec![];
+    let read = child.stdout.take().expect("stdout is piped").read_to_end(&mut output);
+    let status = child.wait().map_err(|e| vec![format!("Couldn't run {}: {}",command,e)])?;
+    // A command that stops reading early is fine; its exit status says whether it worked.
+    let _ = writer.join();
+    let errors = reader.join().ok().and_then(Result::ok).unwrap_or_default();
+    let mut problems: Vec<String> = String::from_utf8_lossy(&errors).lines().map(String::from).collect();
+    if !status.success(){
+        problems.push(format!("The command failed ({}).",status));
+    }
+    if !problems.is_empty(){
+        return Err(problems);
+    }
+    if let Err(e) = read{
+        return Err(vec![format!("Couldn't read the output of {}: {}",command,e)]);
+    }
+    String::from_utf8(output).map_err(|_| vec![format!("{} didn't write UTF-8 text.",command)])
+}
@@ -1,6 +1,14 @@
-pub(crate) struct WindowSize{
-    pub(crate) cols: u16,
-    pub(crate) rows: u16,
+use std::io::{self, Write, stdout};
+use std::time::Duration;
+use crossterm::event::Event;
+use crossterm::execute;
+use crate::screen::{Frame, Screen};
+
+/// Size of the terminal in character cells.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WindowSize{
+    pub cols: u16,
+    pub rows: u16,
 }
 
 impl From<(u16,u16)> for WindowSize{
@@ -9,33 +17,228 @@ impl From<(u16,u16)> for WindowSize{
     }
 }
 impl WindowSize{
-    fn resize(&mut self, dim: (u16,u16)) {
+    pub fn resize(&mut self, dim: (u16,u16)) {
         self.cols = dim.0;
         self.rows = dim.1;
     }
 }
 
-#[derive(Clone, Copy)]
-pub(crate) struct Position{
-    pub(crate) r: u16,
-    pub(crate) c: u16,
+/// A place in a [`Buffer`](crate::Buffer): `r` is the line and `c` the byte offset
+/// into it, both counted from zero.
+///
+/// Converting from a tuple takes `(column, row)`, matching terminal coordinates.
+///
+/// ```
+/// use red::Position;
+///
+/// let pos: Position = (4,2).into();
+/// assert_eq!(pos, Position{r: 2, c: 4});
+/// ```
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position{
+    pub r: usize,
+    pub c: usize,
 }
 
-impl From<(u16,u16)> for Position{
-    fn from(pos: (u16,u16))->Self{
+impl From<(usize,usize)> for Position{
+    fn from(pos: (usize,usize))->Self{
         Self{r:pos.1,c:pos.0}
     }
 }
 
-impl From<Position> for (u16,u16){
-    fn from(pos:Position)->(u16,u16){
-        (pos.c as u16, pos.r as u16)
+impl From<Position> for (usize,usize){
+    fn from(pos:Position)->(usize,usize){
+        (pos.c, pos.r)
+    }
+}
+
+/// Everything the editor needs from a terminal: its size, input events and a place
+/// to show frames.
+pub trait Backend{
+    /// Prepares the terminal for drawing, e.g. entering raw mode.
+    fn init(&mut self) -> io::Result<()>;
+    /// Puts the terminal back the way `init` found it.
+    fn restore(&mut self) -> io::Result<()>;
+    fn size(&mut self) -> io::Result<WindowSize>;
+    /// Waits up to `timeout` for the next event.
+    fn poll_event(&mut self, timeout: Duration) -> io::Result<Option<Event>>;
+    /// Shows `frame`, replacing whatever was drawn before.
+    fn draw(&mut self, frame: Frame) -> io::Result<()>;
+    /// Called when the terminal contents are unknown and the next frame must be drawn in full.
+    fn invalidate(&mut self);
+    /// Sends an escape sequence to the terminal as it is, for things besides drawing such as
+    /// setting the clipboard.
+    fn write_escape(&mut self, sequence: &str) -> io::Result<()>;
+}
+
+/// Draws to the real terminal on stdout, sending only the cells that changed.
+#[derive(Default)]
+pub struct CrosstermBackend{
+    screen: Screen,
+    active: bool,
+}
+
+/// Puts the terminal back if the backend goes away while it still owns it, for example when
+/// an error unwinds out of `Editor::run`.
+impl Drop for CrosstermBackend{
+    fn drop(&mut self){
+        if self.active{
+            let _ = self.restore();
+        }
+    }
+}
+
+impl Backend for CrosstermBackend{
+    fn init(&mut self) -> io::Result<()>{
+        execute!(stdout(),
+                crossterm::terminal::EnterAlternateScreen,
+                crossterm::event::EnableBracketedPaste,
+                crossterm::event::EnableMouseCapture,
+        )?;
+        self.active = true;
+        crossterm::terminal::enable_raw_mode()
+    }
+
+    fn restore(&mut self) -> io::Result<()>{
+        execute!(stdout(),
+                crossterm::event::DisableBracketedPaste,
+                crossterm::event::DisableMouseCapture,
+                crossterm::terminal::LeaveAlternateScreen,
+        )?;
+        self.active = false;
+        crossterm::terminal::disable_raw_mode()
+    }
+
+    fn size(&mut self) -> io::Result<WindowSize>{
+        Ok(crossterm::terminal::size()?.into())
+    }
+
+    fn poll_event(&mut self, timeout: Duration) -> io::Result<Option<Event>>{
+        if crossterm::event::poll(timeout)?{
+            return crossterm::event::read().map(Some);
+        }
+        Ok(None)
+    }
+
+    fn draw(&mut self, frame: Frame) -> io::Result<()>{
+        self.screen.render(frame, &mut stdout())
+    }
+
+    fn invalidate(&mut self){
+        self.screen.invalidate();
     }
+
+    fn write_escape(&mut self, sequence: &str) -> io::Result<()>{
+        let mut stdout = stdout();
+        stdout.write_all(sequence.as_bytes())?;
+        stdout.flush()
+    }
+}
+
+/// A terminal that lives in memory. It replays a script of events and keeps the last
+/// frame drawn so tests can look at the screen. Once the script runs out `poll_event`
+/// fails with `UnexpectedEof`, which ends `Editor::run`.
+#[cfg(test)]
+pub(crate) struct HeadlessBackend{
+    size: WindowSize,
+    /// `None` stands for a poll that times out without an event.
+    events: std::collections::VecDeque<Option<Event>>,
+    frame: Option<Frame>,
+    escapes: Vec<String>,
 }
 
-impl Default for Position{
-    fn default()->Self{
-        Self{r:0,c:0}
+#[cfg(test)]
+impl HeadlessBackend{
+    pub(crate) fn new(cols: u16, rows: u16) -> Self{
+        Self{size: (cols,rows).into(), events: Default::default(), frame: None, escapes: vec![]}
+    }
+
+    pub(crate) fn event(mut self, event: Event) -> Self{
+        self.events.push_back(Some(event));
+        self
+    }
+
+    /// Lets one poll time out after a short wait, giving background work a chance to finish.
+    pub(crate) fn idle(mut self) -> Self{
+        self.events.push_back(None);
+        self
+    }
+
+    pub(crate) fn key(self, code: crossterm::event::KeyCode, modifiers: crossterm::event::KeyModifiers) -> Self{
+        self.event(Event::Key(crossterm::event::KeyEvent::new(code, modifiers)))
+    }
+
+    /// Queues the events of `script` after the ones still to come, for an editor that has
+    /// already run through part of its script.
+    pub(crate) fn extend(&mut self, script: HeadlessBackend){
+        self.events.extend(script.events);
+    }
+
+    /// Types `text` one key at a time, sending Enter for each newline.
+    pub(crate) fn keys(self, text: &str) -> Self{
+        use crossterm::event::{KeyCode, KeyModifiers};
+        text.chars().fold(self, |backend, c| match c{
+            '\n' => backend.key(KeyCode::Enter, KeyModifiers::NONE),
+            c => backend.key(KeyCode::Char(c), KeyModifiers::NONE),
+        })
+    }
+
+    /// The text on each row of the last frame, without trailing blanks.
+    pub(crate) fn screen(&self) -> Vec<String>{
+        match &self.frame{
+            Some(frame) => (0..frame.rows()).map(|y| frame.row_text(y)).collect(),
+            None => vec![],
+        }
+    }
+
+    pub(crate) fn frame(&self) -> Option<&Frame>{
+        self.frame.as_ref()
+    }
+
+    pub(crate) fn cursor(&self) -> Option<(u16,u16)>{
+        self.frame.as_ref().and_then(|frame| frame.cursor)
+    }
+
+    /// The escape sequences written so far, oldest first.
+    pub(crate) fn escapes(&self) -> &[String]{
+        &self.escapes
     }
 }
 
+#[cfg(test)]
+impl Backend for HeadlessBackend{
+    fn init(&mut self) -> io::Result<()>{
+        Ok(())
+    }
+
+    fn restore(&mut self) -> io::Result<()>{
+        Ok(())
+    }
+
+    fn size(&mut self) -> io::Result<WindowSize>{
+        Ok(self.size)
+    }
+
+    fn poll_event(&mut self, timeout: Duration) -> io::Result<Option<Event>>{
+        match self.events.pop_front(){
+            Some(Some(event)) => Ok(Some(event)),
+            Some(None) =>{
+                std::thread::sleep(timeout.min(Duration::from_millis(20)));
+                Ok(None)
+            },
+            None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "event script exhausted")),
+        }
+    }
+
+    fn draw(&mut self, frame: Frame) -> io::Result<()>{
+        self.frame = Some(frame);
+        Ok(())
+    }
+
+    fn invalidate(&mut self){}
+
+    fn write_escape(&mut self, sequence: &str) -> io::Result<()>{
+        self.escapes.push(sequence.to_string());
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+use crate::terminal::Position;
+
+const HISTORY_SIZE: usize = 1000;
+
+/// Lines `row..row+new_len` of a buffer used to be `old`.
+pub(crate) struct Change{
+    pub(crate) row: usize,
+    pub(crate) old: Vec<String>,
+    pub(crate) new_len: usize,
+}
+
+/// Changes that are undone together, along with where the cursor was before them.
+struct Group{
+    cursor: Position,
+    changes: Vec<Change>,
+}
+
+pub(crate) struct History{
+    undo: Vec<Group>,
+    redo: Vec<Group>,
+    /// How many groups were undoable when the text was last saved, or `None` once no
+    /// amount of undoing and redoing gets back to that text.
+    saved: Option<usize>,
+}
+
+impl Default for History{
+    fn default() -> Self{
+        Self{undo: vec![], redo: vec![], saved: Some(0)}
+    }
+}
+
+impl History{
+    /// Starts a new undo group. Changes recorded until the next checkpoint are undone as one.
+    pub(crate) fn checkpoint(&mut self, cursor: Position){
+        match self.undo.last_mut(){
+            Some(group) if group.changes.is_empty() => group.cursor = cursor,
+            _ =>{
+                self.undo.push(Group{cursor, changes: vec![]});
+                if self.undo.len() > HISTORY_SIZE{
+                    self.undo.remove(0);
+                    self.saved = self.saved.and_then(|depth| depth.checked_sub(1));
+                }
+            },
+        }
+    }
+
+    pub(crate) fn record(&mut self, change: Change){
+        if self.undo.is_empty(){
+            self.undo.push(Group{cursor: Default::default(), changes: vec![]});
+        }
+        let depth = self.depth();
+        if let Some(group) = self.undo.last_mut(){
+            // Adding to the group the text was saved after, or dropping the redo groups
+            // that lead back to it, leaves the saved text out of reach.
+            let grows_saved = !group.changes.is_empty() && self.saved == Some(depth);
+            if grows_saved || self.saved.is_some_and(|saved| saved > depth){
+                self.saved = None;
+            }
+            group.changes.push(change);
+        }
+        self.redo.clear();
+    }
+
+    /// Notes that the text as it is now was saved.
+    pub(crate) fn mark_saved(&mut self){
+        self.saved = Some(self.depth());
+    }
+
+    /// Whether undoing and redoing have led back to the text as it was last saved.
+    pub(crate) fn at_saved(&self) -> bool{
+        self.saved == Some(self.depth())
+    }
+
+    /// How many groups there are to undo; only the last one can be empty.
+    fn depth(&self) -> usize{
+        match self.undo.last(){
+            Some(group) if group.changes.is_empty() => self.undo.len() - 1,
+            _ => self.undo.len(),
+        }
+    }
+
+    pub(crate) fn pop_undo(&mut self) -> Option<(Position, Vec<Change>)>{
+        while let Some(group) = self.undo.pop(){
+            if !group.changes.is_empty(){
+                return Some((group.cursor, group.changes));
+            }
+        }
+        None
+    }
+
+    pub(crate) fn pop_redo(&mut self) -> Option<(Position, Vec<Change>)>{
+        self.redo.pop().map(|group| (group.cursor, group.changes))
+    }
+
+    pub(crate) fn push_undo(&mut self, cursor: Position, changes: Vec<Change>){
+        // An empty group left by a checkpoint would end up below this one.
+        if self.undo.last().is_some_and(|group| group.changes.is_empty()){
+            self.undo.pop();
+        }
+        self.undo.push(Group{cursor, changes});
+    }
+
+    pub(crate) fn push_redo(&mut self, cursor: Position, changes: Vec<Change>){
+        self.redo.push(Group{cursor, changes});
+    }
+}